@@ -1,3 +1,6 @@
+// Std Lib Imports
+use std::path::PathBuf;
+
 // Third Party Imports
 use clap::Parser;
 
@@ -9,4 +12,32 @@ pub struct Config {
 
     #[arg(short, long, default_value = "6870")]
     pub port: u16,
+
+    /// Name identifying this client in the server logs
+    #[arg(short, long, default_value = "squeef-client")]
+    pub name: String,
+
+    /// User to authenticate as. The password is read from `SQUEEF_PASSWORD`, or prompted for
+    #[arg(short, long)]
+    pub user: Option<String>,
+
+    /// Encrypt the connection with TLS
+    #[arg(long)]
+    pub tls: bool,
+
+    /// PEM CA certificates to verify the server against, instead of the system's trusted ones
+    #[arg(long, requires = "tls")]
+    pub ca_cert: Option<PathBuf>,
+
+    /// PEM certificate to present to servers which verify clients
+    #[arg(long, requires_all = ["tls", "client_key"])]
+    pub client_cert: Option<PathBuf>,
+
+    /// PEM private key of `--client-cert`
+    #[arg(long, requires = "client_cert")]
+    pub client_key: Option<PathBuf>,
+
+    /// Log in and send passwords over TCP connections without TLS
+    #[arg(long)]
+    pub allow_plaintext_credentials: bool,
 }
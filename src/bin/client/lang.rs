@@ -1,6 +1,15 @@
 use std::{error::Error, fmt::Display};
 
-use squeef::command::Command;
+use squeef::acl::{GrantOn, Privilege};
+use squeef::auth::Credentials;
+use squeef::column::{Column, ColumnType, DefaultValue};
+use squeef::command::{AlterTableAction, Command};
+use squeef::constraint::{
+    Check, CompareOp, Constraint, ConstraintKind, ForeignKey, ReferentialAction,
+};
+use squeef::lock::LockMode;
+use squeef::policy::{Policy, PolicyCommand};
+use squeef::transaction::IsolationLevel;
 
 fn tokenize(command: &str) -> Vec<&str> {
     return command.split_whitespace().collect();
@@ -14,9 +23,83 @@ pub fn parse(user_input: String) -> Result<Command, ParseError> {
             "DATABASE" | "DB" => Ok(Command::CreateDatabase {
                 name: String::from(tokens[2]),
             }),
-            "TABLE" => Ok(Command::CreateTable {
+            "TABLE" => parse_create_table(&user_input),
+            "USER" => parse_user_credentials(&tokens[2..])
+                .map(|(name, credentials)| Command::CreateUser { name, credentials }),
+            "ROLE" => Ok(Command::CreateRole {
                 name: String::from(tokens[2]),
-                cols: vec![],
+            }),
+            "POLICY" => parse_create_policy(&user_input),
+            _ => Err(ParseError::InvalidCommand),
+        },
+        "ALTER" => match tokens[1..] {
+            ["USER", ..] => parse_user_credentials(&tokens[2..])
+                .map(|(name, credentials)| Command::AlterUser { name, credentials }),
+            ["DATABASE" | "DB", name, "RENAME", "TO", new_name] => Ok(Command::RenameDatabase {
+                name: String::from(name),
+                new_name: String::from(new_name),
+            }),
+            ["TABLE", name, "RENAME", "TO", new_name] => Ok(Command::RenameTable {
+                name: String::from(name),
+                new_name: String::from(new_name),
+            }),
+            ["TABLE", ..] => parse_alter_table(&user_input),
+            _ => Err(ParseError::InvalidCommand),
+        },
+        "DROP" => match tokens[1..] {
+            ["USER", name] => Ok(Command::DropUser {
+                name: String::from(name),
+            }),
+            ["ROLE", name] => Ok(Command::DropRole {
+                name: String::from(name),
+            }),
+            ["POLICY", name, "ON", table] => Ok(Command::DropPolicy {
+                table: String::from(table),
+                name: String::from(name),
+            }),
+            ["DATABASE" | "DB", ..] => {
+                parse_drop(&tokens[2..]).map(|(name, if_exists, cascade)| Command::DropDatabase {
+                    name,
+                    if_exists,
+                    cascade,
+                })
+            }
+            ["TABLE", ..] => {
+                parse_drop(&tokens[2..]).map(|(name, if_exists, cascade)| Command::DropTable {
+                    name,
+                    if_exists,
+                    cascade,
+                })
+            }
+            _ => Err(ParseError::InvalidCommand),
+        },
+        "GRANT" => parse_grant(&tokens[1..], "TO").map(|grant| match grant {
+            Grant::Role { role, user } => Command::GrantRole { role, user },
+            Grant::Privileges {
+                privileges,
+                on,
+                role,
+            } => Command::Grant {
+                privileges,
+                on,
+                role,
+            },
+        }),
+        "REVOKE" => parse_grant(&tokens[1..], "FROM").map(|grant| match grant {
+            Grant::Role { role, user } => Command::RevokeRole { role, user },
+            Grant::Privileges {
+                privileges,
+                on,
+                role,
+            } => Command::Revoke {
+                privileges,
+                on,
+                role,
+            },
+        }),
+        "DESCRIBE" | "DESC" => match tokens[1..] {
+            [name] | ["TABLE", name] => Ok(Command::DescribeTable {
+                name: String::from(name),
             }),
             _ => Err(ParseError::InvalidCommand),
         },
@@ -28,10 +111,626 @@ pub fn parse(user_input: String) -> Result<Command, ParseError> {
             "TABLES" => Ok(Command::ListTables),
             _ => Err(ParseError::InvalidCommand),
         },
+        "BEGIN" => Ok(Command::Begin),
+        "COMMIT" => Ok(Command::Commit),
+        "ROLLBACK" => match tokens[1..] {
+            [] => Ok(Command::Rollback),
+            ["TO", "SAVEPOINT", name] | ["TO", name] => Ok(Command::RollbackToSavepoint {
+                name: String::from(name),
+            }),
+            _ => Err(ParseError::InvalidCommand),
+        },
+        "SAVEPOINT" => match tokens[1..] {
+            [name] => Ok(Command::Savepoint {
+                name: String::from(name),
+            }),
+            _ => Err(ParseError::InvalidCommand),
+        },
+        "RELEASE" => match tokens[1..] {
+            ["SAVEPOINT", name] | [name] => Ok(Command::ReleaseSavepoint {
+                name: String::from(name),
+            }),
+            _ => Err(ParseError::InvalidCommand),
+        },
+        "LOCK" => parse_lock_table(&tokens[1..]),
+        "SET" => match tokens[1..] {
+            ["TRANSACTION", "ISOLATION", "LEVEL", "READ", "COMMITTED"] => {
+                Ok(Command::SetIsolationLevel {
+                    level: IsolationLevel::ReadCommitted,
+                })
+            }
+            ["TRANSACTION", "ISOLATION", "LEVEL", "REPEATABLE", "READ"] => {
+                Ok(Command::SetIsolationLevel {
+                    level: IsolationLevel::RepeatableRead,
+                })
+            }
+            ["TRANSACTION", "ISOLATION", "LEVEL", "SERIALIZABLE"] => {
+                Ok(Command::SetIsolationLevel {
+                    level: IsolationLevel::Serializable,
+                })
+            }
+            _ => Err(ParseError::InvalidCommand),
+        },
+        _ => Err(ParseError::InvalidCommand),
+    };
+}
+
+/// `<name> PASSWORD '<password>'`, hashed here so the password never reaches the server
+fn parse_user_credentials(tokens: &[&str]) -> Result<(String, Credentials), ParseError> {
+    return match tokens {
+        [name, "PASSWORD", password] => {
+            let password = password.trim_matches('\'');
+
+            if password.is_empty() {
+                return Err(ParseError::InvalidCommand);
+            }
+
+            Ok((String::from(*name), Credentials::new(password)))
+        }
+        _ => Err(ParseError::InvalidCommand),
+    };
+}
+
+enum Grant {
+    Role {
+        role: String,
+        user: String,
+    },
+    Privileges {
+        privileges: Vec<Privilege>,
+        on: GrantOn,
+        role: String,
+    },
+}
+
+/// `<role> TO <user>` or `<privilege>[, ...] ON DATABASE|TABLE <name> TO <role>`, with `FROM`
+/// instead of `TO` for REVOKE. `ALL [PRIVILEGES]` stands for every privilege.
+fn parse_grant(tokens: &[&str], to: &str) -> Result<Grant, ParseError> {
+    let on_idx = match tokens.iter().position(|t| *t == "ON") {
+        Some(on_idx) => on_idx,
+        None => {
+            return match tokens {
+                [role, t, user] if *t == to => Ok(Grant::Role {
+                    role: String::from(*role),
+                    user: String::from(*user),
+                }),
+                _ => Err(ParseError::InvalidCommand),
+            }
+        }
+    };
+
+    let privileges = match &tokens[..on_idx] {
+        ["ALL"] | ["ALL", "PRIVILEGES"] => Privilege::ALL.to_vec(),
+        names => {
+            let mut privileges = vec![];
+
+            for name in names.join(" ").split(',').map(str::trim) {
+                privileges.push(match name {
+                    "SELECT" => Privilege::Select,
+                    "INSERT" => Privilege::Insert,
+                    "UPDATE" => Privilege::Update,
+                    "DELETE" => Privilege::Delete,
+                    "CREATE" => Privilege::Create,
+                    "REFERENCES" => Privilege::References,
+                    _ => return Err(ParseError::InvalidCommand),
+                });
+            }
+
+            privileges
+        }
+    };
+
+    return match tokens[on_idx + 1..] {
+        ["DATABASE", name, t, role] if t == to => Ok(Grant::Privileges {
+            privileges,
+            on: GrantOn::Database {
+                name: String::from(name),
+            },
+            role: String::from(role),
+        }),
+        ["TABLE", name, t, role] if t == to => Ok(Grant::Privileges {
+            privileges,
+            on: GrantOn::Table {
+                name: String::from(name),
+            },
+            role: String::from(role),
+        }),
+        _ => Err(ParseError::InvalidCommand),
+    };
+}
+
+/// Split a table definition into words, quoted literals, comparison operators and single `(`, `)`
+/// and `,`. Quoted literals keep their quotes, so they can hold whitespace and commas.
+fn lex(command: &str) -> Result<Vec<String>, ParseError> {
+    let mut tokens = vec![];
+    let mut chars = command.chars().peekable();
+
+    while let Some(c) = chars.next() {
+        let mut token = String::from(c);
+
+        match c {
+            c if c.is_whitespace() => continue,
+            '(' | ')' | ',' => {}
+            '\'' => loop {
+                match chars.next() {
+                    Some('\'') => {
+                        token.push('\'');
+                        break;
+                    }
+                    Some(c) => token.push(c),
+                    None => return Err(ParseError::InvalidCommand),
+                }
+            },
+            '<' | '>' | '=' | '!' => {
+                while let Some(c) = chars.next_if(|c| matches!(c, '<' | '>' | '=')) {
+                    token.push(c);
+                }
+            }
+            _ => {
+                while let Some(c) = chars.next_if(|c| {
+                    !c.is_whitespace()
+                        && !matches!(c, '(' | ')' | ',' | '\'' | '<' | '>' | '=' | '!')
+                }) {
+                    token.push(c);
+                }
+            }
+        }
+
+        tokens.push(token);
+    }
+
+    return Ok(tokens);
+}
+
+/// Split `tokens` at the commas outside of parentheses
+fn split_top_level<'a>(tokens: &'a [&'a str]) -> Vec<&'a [&'a str]> {
+    let mut parts = vec![];
+    let mut depth = 0;
+    let mut start = 0;
+
+    for (i, token) in tokens.iter().enumerate() {
+        match *token {
+            "(" => depth += 1,
+            ")" => depth -= 1,
+            "," if depth == 0 => {
+                parts.push(&tokens[start..i]);
+                start = i + 1;
+            }
+            _ => {}
+        }
+    }
+
+    parts.push(&tokens[start..]);
+
+    return parts;
+}
+
+/// `CREATE TABLE <name> [(<column> | <constraint>, ...)]`, see `parse_column` and
+/// `parse_constraint`
+fn parse_create_table(user_input: &str) -> Result<Command, ParseError> {
+    let lexed = lex(user_input)?;
+    let tokens: Vec<&str> = lexed.iter().map(|t| t.as_str()).collect();
+
+    let (name, elements) = match tokens[..] {
+        ["CREATE", "TABLE", name] => (String::from(name), None),
+        ["CREATE", "TABLE", name, "(", ref elements @ .., ")"] => {
+            (String::from(name), Some(elements))
+        }
+        _ => return Err(ParseError::InvalidCommand),
+    };
+
+    let mut cols = vec![];
+    let mut constraints = vec![];
+
+    for element in elements.map(split_top_level).unwrap_or_default() {
+        match element {
+            ["CONSTRAINT" | "UNIQUE" | "CHECK" | "FOREIGN", ..] => {
+                constraints.push(parse_constraint(element)?);
+            }
+            _ => {
+                let (col, col_constraints) = parse_column(element)?;
+                cols.push(col);
+                constraints.extend(col_constraints);
+            }
+        }
+    }
+
+    return Ok(Command::CreateTable {
+        name,
+        cols,
+        constraints,
+    });
+}
+
+/// `CREATE POLICY <name> ON <table> [FOR ALL | SELECT | INSERT | UPDATE | DELETE] TO <role>
+/// USING (<column> <op> <literal>)`
+fn parse_create_policy(user_input: &str) -> Result<Command, ParseError> {
+    let lexed = lex(user_input)?;
+    let tokens: Vec<&str> = lexed.iter().map(|t| t.as_str()).collect();
+
+    let (name, table, tokens) = match tokens[..] {
+        ["CREATE", "POLICY", name, "ON", table, ref rest @ ..] => {
+            (String::from(name), String::from(table), rest)
+        }
+        _ => return Err(ParseError::InvalidCommand),
+    };
+
+    let (command, tokens) = match tokens {
+        ["FOR", command, rest @ ..] => (
+            PolicyCommand::from_name(command).ok_or(ParseError::InvalidCommand)?,
+            rest,
+        ),
+        tokens => (PolicyCommand::All, tokens),
+    };
+
+    return match tokens {
+        ["TO", role, "USING", "(", column, op, value, ")"] => Ok(Command::CreatePolicy {
+            table,
+            policy: Policy {
+                name,
+                command,
+                role: String::from(*role),
+                predicate: parse_check(column, op, value)?,
+            },
+        }),
         _ => Err(ParseError::InvalidCommand),
     };
 }
 
+/// `ALTER TABLE <name>` followed by one of
+/// `ADD [COLUMN] <column>`, `ADD <constraint>`, `DROP [COLUMN] <name>`,
+/// `DROP CONSTRAINT <name>`, `RENAME [COLUMN] <name> TO <new_name>`,
+/// `ALTER [COLUMN] <name> TYPE <type>`, `ALTER [COLUMN] <name> SET DEFAULT <default>`,
+/// `ALTER [COLUMN] <name> DROP DEFAULT` or `ALTER [COLUMN] <name> SET | DROP NOT NULL`
+fn parse_alter_table(user_input: &str) -> Result<Command, ParseError> {
+    let lexed = lex(user_input)?;
+    let tokens: Vec<&str> = lexed.iter().map(|t| t.as_str()).collect();
+
+    let (name, tokens) = match tokens[..] {
+        ["ALTER", "TABLE", name, ref rest @ ..] => (String::from(name), rest),
+        _ => return Err(ParseError::InvalidCommand),
+    };
+
+    let tokens = match tokens {
+        ["ALTER", "COLUMN", rest @ ..] => {
+            return parse_alter_column(rest).map(|action| Command::AlterTable { name, action })
+        }
+        ["ALTER", rest @ ..] => {
+            return parse_alter_column(rest).map(|action| Command::AlterTable { name, action })
+        }
+        tokens => tokens,
+    };
+
+    let action = match tokens {
+        ["ADD", "CONSTRAINT" | "UNIQUE" | "CHECK" | "FOREIGN", ..] => {
+            AlterTableAction::AddConstraint {
+                constraint: parse_constraint(&tokens[1..])?,
+            }
+        }
+        ["ADD", "COLUMN", column @ ..] | ["ADD", column @ ..] => {
+            let (column, constraints) = parse_column(column)?;
+            AlterTableAction::AddColumn {
+                column,
+                constraints,
+            }
+        }
+        ["DROP", "CONSTRAINT", name] => AlterTableAction::DropConstraint {
+            name: String::from(*name),
+        },
+        ["DROP", "COLUMN", name] | ["DROP", name] => AlterTableAction::DropColumn {
+            name: String::from(*name),
+        },
+        ["RENAME", "COLUMN", name, "TO", new_name] | ["RENAME", name, "TO", new_name] => {
+            AlterTableAction::RenameColumn {
+                name: String::from(*name),
+                new_name: String::from(*new_name),
+            }
+        }
+        _ => return Err(ParseError::InvalidCommand),
+    };
+
+    return Ok(Command::AlterTable { name, action });
+}
+
+/// What follows `ALTER [COLUMN]` in ALTER TABLE
+fn parse_alter_column(tokens: &[&str]) -> Result<AlterTableAction, ParseError> {
+    return match tokens {
+        [name, "TYPE", column_type] => match ColumnType::from_name(column_type) {
+            Some(column_type) => Ok(AlterTableAction::AlterColumnType {
+                name: String::from(*name),
+                column_type,
+            }),
+            None => Err(ParseError::InvalidCommand),
+        },
+        [name, "SET", "DEFAULT", default @ ..] => match parse_default(default)? {
+            (default, []) => Ok(AlterTableAction::SetDefault {
+                name: String::from(*name),
+                default,
+            }),
+            _ => Err(ParseError::InvalidCommand),
+        },
+        [name, "DROP", "DEFAULT"] => Ok(AlterTableAction::SetDefault {
+            name: String::from(*name),
+            default: None,
+        }),
+        [name, "SET", "NOT", "NULL"] => Ok(AlterTableAction::SetNotNull {
+            name: String::from(*name),
+            not_null: true,
+        }),
+        [name, "DROP", "NOT", "NULL"] => Ok(AlterTableAction::SetNotNull {
+            name: String::from(*name),
+            not_null: false,
+        }),
+        _ => Err(ParseError::InvalidCommand),
+    };
+}
+
+/// `<name> <type>` followed by any of `NULL`, `NOT NULL`, `PRIMARY KEY`, `DEFAULT <default>`,
+/// `UNIQUE`, `CHECK (...)` and `REFERENCES ...`. Columns are nullable unless they're declared
+/// NOT NULL or are part of the primary key. UNIQUE, CHECK and REFERENCES are returned as table
+/// constraints.
+fn parse_column(tokens: &[&str]) -> Result<(Column, Vec<Constraint>), ParseError> {
+    let (name, column_type, mut rest) = match tokens {
+        [name, column_type, rest @ ..] => match ColumnType::from_name(column_type) {
+            Some(column_type) => (String::from(*name), column_type, rest),
+            None => return Err(ParseError::InvalidCommand),
+        },
+        _ => return Err(ParseError::InvalidCommand),
+    };
+
+    let mut nullable = None;
+    let mut is_primary_key = false;
+    let mut default = None;
+    let mut constraints = vec![];
+
+    while !rest.is_empty() {
+        rest = match rest {
+            ["NULL", rest @ ..] if nullable.is_none() => {
+                nullable = Some(true);
+                rest
+            }
+            ["NOT", "NULL", rest @ ..] if nullable.is_none() => {
+                nullable = Some(false);
+                rest
+            }
+            ["PRIMARY", "KEY", rest @ ..] if !is_primary_key => {
+                is_primary_key = true;
+                rest
+            }
+            ["DEFAULT", tokens @ ..] if default.is_none() => {
+                let (value, rest) = parse_default(tokens)?;
+                default = Some(value);
+                rest
+            }
+            ["UNIQUE", rest @ ..] => {
+                constraints.push(Constraint {
+                    name: None,
+                    kind: ConstraintKind::Unique {
+                        columns: vec![name.clone()],
+                    },
+                });
+                rest
+            }
+            ["CHECK", "(", column, op, value, ")", rest @ ..] => {
+                constraints.push(Constraint {
+                    name: None,
+                    kind: ConstraintKind::Check(parse_check(column, op, value)?),
+                });
+                rest
+            }
+            ["REFERENCES", ..] => {
+                let (fk, rest) = parse_references(vec![name.clone()], &rest[1..])?;
+                constraints.push(Constraint {
+                    name: None,
+                    kind: ConstraintKind::ForeignKey(fk),
+                });
+                rest
+            }
+            _ => return Err(ParseError::InvalidCommand),
+        };
+    }
+
+    let column = Column {
+        name,
+        column_type,
+        is_optional: nullable.unwrap_or(!is_primary_key),
+        is_primary_key,
+        is_foreign_key: false,
+        default: default.flatten(),
+    };
+
+    return Ok((column, constraints));
+}
+
+/// `[CONSTRAINT <name>] UNIQUE (<column>, ...)`,
+/// `[CONSTRAINT <name>] CHECK (<column> <operator> <literal>)` or
+/// `[CONSTRAINT <name>] FOREIGN KEY (<column>, ...) REFERENCES ...`, see `parse_references`
+fn parse_constraint(tokens: &[&str]) -> Result<Constraint, ParseError> {
+    let (name, tokens) = match tokens {
+        ["CONSTRAINT", name, rest @ ..] => (Some(String::from(*name)), rest),
+        rest => (None, rest),
+    };
+
+    let kind = match tokens {
+        ["UNIQUE", rest @ ..] => match parse_column_list(rest)? {
+            (columns, []) => ConstraintKind::Unique { columns },
+            _ => return Err(ParseError::InvalidCommand),
+        },
+        ["CHECK", "(", column, op, value, ")"] => {
+            ConstraintKind::Check(parse_check(column, op, value)?)
+        }
+        ["FOREIGN", "KEY", rest @ ..] => {
+            let (columns, rest) = parse_column_list(rest)?;
+
+            match rest {
+                ["REFERENCES", rest @ ..] => match parse_references(columns, rest)? {
+                    (fk, []) => ConstraintKind::ForeignKey(fk),
+                    _ => return Err(ParseError::InvalidCommand),
+                },
+                _ => return Err(ParseError::InvalidCommand),
+            }
+        }
+        _ => return Err(ParseError::InvalidCommand),
+    };
+
+    return Ok(Constraint { name, kind });
+}
+
+/// What follows REFERENCES: `<table> [(<column>, ...)] [ON DELETE <action>] [ON UPDATE <action>]`,
+/// with `RESTRICT`, `CASCADE` or `SET NULL` as actions. Without columns, the foreign key
+/// references the primary key. Returns the tokens after it.
+fn parse_references<'a>(
+    columns: Vec<String>,
+    tokens: &'a [&'a str],
+) -> Result<(ForeignKey, &'a [&'a str]), ParseError> {
+    let (ref_table, mut rest) = match tokens {
+        [ref_table, rest @ ..] => (String::from(*ref_table), rest),
+        [] => return Err(ParseError::InvalidCommand),
+    };
+
+    let mut ref_columns = vec![];
+
+    if let ["(", ..] = rest {
+        (ref_columns, rest) = parse_column_list(rest)?;
+    }
+
+    let mut on_delete = None;
+    let mut on_update = None;
+
+    loop {
+        let (event, action, after) = match rest {
+            ["ON", event @ ("DELETE" | "UPDATE"), "RESTRICT", after @ ..] => {
+                (*event, ReferentialAction::Restrict, after)
+            }
+            ["ON", event @ ("DELETE" | "UPDATE"), "CASCADE", after @ ..] => {
+                (*event, ReferentialAction::Cascade, after)
+            }
+            ["ON", event @ ("DELETE" | "UPDATE"), "SET", "NULL", after @ ..] => {
+                (*event, ReferentialAction::SetNull, after)
+            }
+            _ => break,
+        };
+
+        let slot = match event {
+            "DELETE" => &mut on_delete,
+            _ => &mut on_update,
+        };
+
+        if slot.replace(action).is_some() {
+            return Err(ParseError::InvalidCommand);
+        }
+
+        rest = after;
+    }
+
+    let fk = ForeignKey {
+        columns,
+        ref_table,
+        ref_columns,
+        on_delete: on_delete.unwrap_or(ReferentialAction::Restrict),
+        on_update: on_update.unwrap_or(ReferentialAction::Restrict),
+    };
+
+    return Ok((fk, rest));
+}
+
+/// `(<column>, ...)`. Returns the tokens after it.
+fn parse_column_list<'a>(
+    tokens: &'a [&'a str],
+) -> Result<(Vec<String>, &'a [&'a str]), ParseError> {
+    let close = match tokens {
+        ["(", ..] => tokens.iter().position(|t| *t == ")"),
+        _ => None,
+    }
+    .ok_or(ParseError::InvalidCommand)?;
+
+    let columns = split_top_level(&tokens[1..close])
+        .into_iter()
+        .map(|column| match column {
+            [column] => Ok(String::from(*column)),
+            _ => Err(ParseError::InvalidCommand),
+        })
+        .collect::<Result<Vec<String>, ParseError>>()?;
+
+    return Ok((columns, &tokens[close + 1..]));
+}
+
+fn parse_check(column: &str, op: &str, value: &str) -> Result<Check, ParseError> {
+    let op = CompareOp::from_symbol(op).ok_or(ParseError::InvalidCommand)?;
+
+    return match parse_literal(value) {
+        Some(value) => Ok(Check {
+            column: String::from(column),
+            op,
+            value,
+        }),
+        None => Err(ParseError::InvalidCommand),
+    };
+}
+
+/// `now()` or a literal, see `parse_literal`. Returns the tokens after it.
+fn parse_default<'a>(
+    tokens: &'a [&'a str],
+) -> Result<(Option<DefaultValue>, &'a [&'a str]), ParseError> {
+    return match tokens {
+        [now, "(", ")", rest @ ..] if now.eq_ignore_ascii_case("now") => {
+            Ok((Some(DefaultValue::Now), rest))
+        }
+        [literal, rest @ ..] => Ok((parse_literal(literal).map(DefaultValue::Literal), rest)),
+        [] => Err(ParseError::InvalidCommand),
+    };
+}
+
+/// `NULL` is `None`. Quotes around a literal are removed.
+fn parse_literal(literal: &str) -> Option<String> {
+    if literal == "NULL" {
+        return None;
+    }
+
+    return Some(String::from(
+        literal
+            .strip_prefix('\'')
+            .and_then(|l| l.strip_suffix('\''))
+            .unwrap_or(literal),
+    ));
+}
+
+/// `[IF EXISTS] <name> [CASCADE | RESTRICT]`, after `DROP DATABASE` or `DROP TABLE`
+fn parse_drop(tokens: &[&str]) -> Result<(String, bool, bool), ParseError> {
+    let (if_exists, rest) = match tokens {
+        ["IF", "EXISTS", rest @ ..] => (true, rest),
+        rest => (false, rest),
+    };
+
+    return match rest {
+        [name] | [name, "RESTRICT"] => Ok((String::from(*name), if_exists, false)),
+        [name, "CASCADE"] => Ok((String::from(*name), if_exists, true)),
+        _ => Err(ParseError::InvalidCommand),
+    };
+}
+
+/// `LOCK TABLE <name> [IN SHARE MODE | IN EXCLUSIVE MODE] [NOWAIT]`
+fn parse_lock_table(tokens: &[&str]) -> Result<Command, ParseError> {
+    let (name, rest) = match tokens {
+        ["TABLE", name, rest @ ..] => (String::from(*name), rest),
+        _ => return Err(ParseError::InvalidCommand),
+    };
+
+    let (mode, rest) = match rest {
+        ["IN", "SHARE", "MODE", rest @ ..] => (LockMode::Shared, rest),
+        ["IN", "EXCLUSIVE", "MODE", rest @ ..] => (LockMode::Exclusive, rest),
+        rest => (LockMode::Exclusive, rest),
+    };
+
+    let nowait = match rest {
+        [] => false,
+        ["NOWAIT"] => true,
+        _ => return Err(ParseError::InvalidCommand),
+    };
+
+    return Ok(Command::LockTable { name, mode, nowait });
+}
+
 #[derive(Debug)]
 pub enum ParseError {
     InvalidCommand,
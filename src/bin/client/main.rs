@@ -1,16 +1,24 @@
+// Explicit `return`s and `-> ()` are house style
+#![allow(clippy::needless_return, clippy::unused_unit)]
+
 // Std Lib Imports
-use std::io::{stdin, stdout, Write};
+use std::io::{self, stdin, stdout, Read, Write};
 use std::net;
+use std::os::unix::net::UnixStream;
 
 // Executable Imports
 mod config;
 mod lang;
 
 // Squeef Lib Imports
+use squeef::auth;
+use squeef::command::Command;
 use squeef::protocol::v0;
+use squeef::tls;
 
 // Third Party Imports
 use clap::Parser;
+use rustls::{ClientConnection, StreamOwned};
 use squeef::utils;
 
 const BANNER: &str = r#"
@@ -30,34 +38,125 @@ fn main() -> () {
 
     let config = config::Config::parse();
 
-    println!("Connecting to {}:{} ...", config.host, config.port);
+    // Unix domain sockets are given as `--host unix:/path/to.sock`, the port is ignored then
+    if let Some(path) = config.host.strip_prefix("unix:") {
+        println!("Connecting to {} ...", path);
+
+        // Unix domain sockets never leave the host, so credentials can go over them as they are
+        match UnixStream::connect(path) {
+            Ok(stream) => {
+                println!("Connected!");
+                run(stream, config.name, config.user, true);
+            }
 
-    match net::TcpStream::connect((config.host, config.port)) {
-        Ok(stream) => {
-            println!("Connected!");
-            run(stream);
+            Err(e) => {
+                eprintln!("{}", e);
+            }
         }
+    } else if config.tls {
+        println!("Connecting to {}:{} with TLS ...", config.host, config.port);
 
-        Err(e) => {
-            eprintln!("{}", e);
+        match connect_tls(&config) {
+            Ok(stream) => {
+                println!("Connected!");
+                run(stream, config.name, config.user, true);
+            }
+
+            Err(e) => {
+                eprintln!("{}", e);
+            }
+        }
+    } else {
+        println!("Connecting to {}:{} ...", config.host, config.port);
+
+        match net::TcpStream::connect((config.host.as_str(), config.port)) {
+            Ok(stream) => {
+                println!("Connected!");
+                run(
+                    stream,
+                    config.name,
+                    config.user,
+                    config.allow_plaintext_credentials,
+                );
+            }
+
+            Err(e) => {
+                eprintln!("{}", e);
+            }
         }
     }
 
     println!("\nBye!");
 }
 
-fn run(mut stream: net::TcpStream) -> () {
+fn connect_tls(
+    config: &config::Config,
+) -> io::Result<StreamOwned<ClientConnection, net::TcpStream>> {
+    let client_cert = match (&config.client_cert, &config.client_key) {
+        (Some(cert), Some(key)) => Some((cert.as_path(), key.as_path())),
+        _ => None,
+    };
+
+    let tls_config = tls::client_config(config.ca_cert.as_deref(), client_cert)?;
+    let conn = ClientConnection::new(tls_config, tls::server_name(&config.host)?)
+        .map_err(io::Error::other)?;
+
+    let mut stream = StreamOwned::new(
+        conn,
+        net::TcpStream::connect((config.host.as_str(), config.port))?,
+    );
+
+    // Handshake now, so certificate problems are reported before anything is sent
+    while stream.conn.is_handshaking() {
+        stream.conn.complete_io(&mut stream.sock)?;
+    }
+
+    return Ok(stream);
+}
+
+/// `send_credentials` tells whether the connection is safe to log in and send passwords over
+fn run<S: Read + Write>(
+    mut stream: S,
+    client_name: String,
+    user: Option<String>,
+    send_credentials: bool,
+) -> () {
+    if user.is_some() && !send_credentials {
+        eprintln!("{}", PLAINTEXT_CREDENTIALS);
+        return;
+    }
+
+    // Identify ourselves so the server can tell connections apart in its logs
+    let data = request(&mut stream, Command::SetClientName { name: client_name });
+
+    if let Err(e) = v0::response::parse(&data) {
+        eprintln!("{}", e);
+    }
+
+    if let Some(user) = user {
+        if let Err(e) = authenticate(&mut stream, user) {
+            eprintln!("{}", e);
+            return;
+        }
+    }
+
     print!("> ");
     stdout().flush().unwrap();
 
     for line in stdin().lines() {
         let line = line.unwrap();
         match lang::parse(line) {
+            Ok(Command::CreateUser { .. } | Command::AlterUser { .. }) if !send_credentials => {
+                eprintln!("{}", PLAINTEXT_CREDENTIALS);
+                print!("> ");
+                stdout().flush().unwrap();
+                continue;
+            }
             Ok(cmd) => {
                 let data: Vec<u8> = v0::request::serialise(cmd);
                 let data_len = data.len() as u32;
-                stream.write(&data_len.to_le_bytes()).unwrap();
-                stream.write(data.as_slice()).unwrap();
+                stream.write_all(&data_len.to_le_bytes()).unwrap();
+                stream.write_all(data.as_slice()).unwrap();
             }
             Err(e) => {
                 eprintln!("{}", e);
@@ -68,7 +167,7 @@ fn run(mut stream: net::TcpStream) -> () {
 
         match v0::response::parse(&data) {
             Ok(resp) => {
-                println!("{:?}", resp);
+                println!("{}", resp);
             }
             Err(e) => {
                 eprintln!("{}", e);
@@ -78,3 +177,43 @@ fn run(mut stream: net::TcpStream) -> () {
         stdout().flush().unwrap();
     }
 }
+
+const PLAINTEXT_CREDENTIALS: &str =
+    "Refusing to send credentials without TLS. Use --tls, or --allow-plaintext-credentials";
+
+/// Send `cmd` and wait for the response
+fn request<S: Read + Write>(stream: &mut S, cmd: Command) -> Vec<u8> {
+    let data = v0::request::serialise(cmd);
+    stream
+        .write_all(&(data.len() as u32).to_le_bytes())
+        .unwrap();
+    stream.write_all(data.as_slice()).unwrap();
+
+    return utils::read_msg(stream).unwrap();
+}
+
+/// Log in as `user`. Only a proof derived from the password and the server's challenge is sent.
+fn authenticate<S: Read + Write>(stream: &mut S, user: String) -> Result<(), String> {
+    let password = match std::env::var("SQUEEF_PASSWORD") {
+        Ok(password) => password,
+        Err(_) => {
+            print!("Password for {}: ", user);
+            stdout().flush().unwrap();
+
+            match stdin().lines().next() {
+                Some(Ok(password)) => password,
+                _ => return Err(String::from("No password given")),
+            }
+        }
+    };
+
+    let data = request(stream, Command::Authenticate { user });
+    let challenge = v0::response::parse_challenge(&data)?;
+
+    let proof = auth::client_proof(&password, &challenge);
+    let data = request(stream, Command::AuthProof { proof });
+
+    println!("{}", v0::response::parse(&data)?);
+
+    return Ok(());
+}
@@ -1,55 +1,824 @@
+use std::collections::HashSet;
+use std::fmt::Display;
+use std::io::{stderr, ErrorKind};
 use std::path::PathBuf;
+use std::sync::{Arc, RwLock};
 
-use lazy_static::lazy_static;
+use clap::{Parser, Subcommand};
 use serde::Deserialize;
 use serde_inline_default::serde_inline_default;
 
-lazy_static! {
-    pub static ref CONFIG: Config = {
-        let config = std::fs::read_to_string("squeef.toml").unwrap();
-        toml::from_str(&config).unwrap()
-    };
+use squeef::log::{
+    LogFormat, LogLevel, LogOutput, Logger, Loggers, OverflowPolicy, RotatingFile, Rotation,
+    StreamOutput, SyslogOutput,
+};
+use squeef::net::BindAddress;
+
+const DEFAULT_CONFIG_PATH: &str = "squeef.toml";
+
+/// Prefix of the environment variables overriding config file settings. `SQUEEF_SERVER_PORT`
+/// overrides `port` in the `[server]` section, `SQUEEF_AUTH_ALLOW_ANONYMOUS` overrides
+/// `allow_anonymous` in the `[auth]` section, and so on.
+const ENV_PREFIX: &str = "SQUEEF_";
+
+/// Config currently in effect. Swapped as a whole when the config file is reloaded on SIGHUP.
+pub type SharedConfig = Arc<RwLock<Arc<Config>>>;
+
+#[derive(Parser, Debug)]
+#[command(author, version, about, long_about = None)]
+pub struct Cli {
+    /// Path to the config file
+    #[arg(short, long)]
+    pub config: Option<PathBuf>,
+
+    /// Port of the default listener. Ignored when `server.bind` is set
+    #[arg(short, long)]
+    pub port: Option<u16>,
+
+    /// Directory holding the persistent storage, overriding `storage.persistent_storage_dir`
+    #[arg(short, long)]
+    pub data_dir: Option<PathBuf>,
+
+    #[command(subcommand)]
+    pub command: Option<CliCommand>,
+}
+
+#[derive(Subcommand, Debug)]
+pub enum CliCommand {
+    /// Check that no record of the audit log was modified, inserted or removed, and print the
+    /// number of records and the hash of the last one. Doesn't read the config.
+    VerifyAuditLog {
+        /// Audit log to check
+        path: PathBuf,
+
+        /// File holding the key the log is chained with, `server.audit_key_file`
+        #[arg(short, long)]
+        key_file: PathBuf,
+    },
 }
 
 #[derive(Deserialize, Debug)]
+#[serde(deny_unknown_fields)]
 pub struct Config {
     #[serde(default)]
     pub server: ServerConfig,
 
     #[serde(default)]
     pub storage: StorageConfig,
+
+    #[serde(default)]
+    pub auth: AuthConfig,
+
+    #[serde(default = "default_log")]
+    pub log: Vec<LogConfig>,
+
+    /// Loggers recording commands slower than `server.slow_query_threshold`. No slow query log is
+    /// kept when empty.
+    #[serde(default)]
+    pub slow_query_log: Vec<LogConfig>,
+
+    /// Problems which didn't stop the config from loading, to be logged once loggers exist
+    #[serde(skip)]
+    pub warnings: Vec<String>,
 }
 
 #[serde_inline_default]
 #[derive(Deserialize, Debug)]
+#[serde(deny_unknown_fields)]
 pub struct ServerConfig {
     #[serde_inline_default(6870)]
     pub port: u16,
 
+    /// Addresses to listen on, `host:port` or `unix:/path/to.sock`. Defaults to `127.0.0.1:<port>`
+    #[serde(default)]
+    pub bind: Vec<BindAddress>,
+
     #[serde_inline_default(8)]
-    pub max_concurrent_connection: isize,
+    pub max_concurrent_connection: usize,
+
+    /// Seconds given to in-flight requests to complete once a shutdown is requested
+    #[serde_inline_default(10)]
+    pub shutdown_grace_period: u64,
+
+    /// Number of log records waiting to be written before `log_overflow` kicks in
+    #[serde_inline_default(4096)]
+    pub log_queue_size: usize,
+
+    /// What to do with log records when the queue is full, `drop` or `block`
+    #[serde_inline_default(OverflowPolicy::Drop)]
+    pub log_overflow: OverflowPolicy,
+
+    /// Milliseconds after which a command is recorded in the slow query log
+    #[serde_inline_default(1000)]
+    pub slow_query_threshold: u64,
+
+    /// Milliseconds a command waits for a lock held by another transaction before failing
+    #[serde_inline_default(10000)]
+    pub lock_timeout: u64,
+
+    /// `host:port` serving Prometheus metrics at `/metrics`. No metrics endpoint when unset
+    #[serde(default)]
+    pub metrics_bind: Option<String>,
+
+    /// PEM certificate chain TCP connections are encrypted with. Requires `tls_key`
+    #[serde(default)]
+    pub tls_cert: Option<PathBuf>,
+
+    /// PEM private key of `tls_cert`
+    #[serde(default)]
+    pub tls_key: Option<PathBuf>,
+
+    /// PEM CA certificates client certificates are verified against. Clients don't need a
+    /// certificate when unset
+    #[serde(default)]
+    pub tls_client_ca: Option<PathBuf>,
+
+    /// File recording logins, DDL, privilege changes and denied commands, hash chained so tampering
+    /// shows. No audit log is kept when unset
+    #[serde(default)]
+    pub audit_log: Option<PathBuf>,
+
+    /// Secret of at least 32 bytes the audit log's hashes are keyed with. Required with
+    /// `audit_log`, and must be kept outside its directory.
+    #[serde(default)]
+    pub audit_key_file: Option<PathBuf>,
 }
 
 impl Default for ServerConfig {
     fn default() -> ServerConfig {
         ServerConfig {
             port: 6870,
+            bind: vec![],
             max_concurrent_connection: 8,
+            shutdown_grace_period: 10,
+            log_queue_size: 4096,
+            log_overflow: OverflowPolicy::Drop,
+            slow_query_threshold: 1000,
+            lock_timeout: 10000,
+            metrics_bind: None,
+            tls_cert: None,
+            tls_key: None,
+            tls_client_ca: None,
+            audit_log: None,
+            audit_key_file: None,
         }
     }
 }
 
 #[serde_inline_default]
 #[derive(Deserialize, Debug)]
+#[serde(deny_unknown_fields)]
 pub struct StorageConfig {
+    /// Directory for persistent storage. Not used yet, everything is kept in memory.
     #[serde_inline_default(PathBuf::from("/var/lib/squeef"))]
     pub persistent_storage_dir: PathBuf,
+
+    /// File holding the key, exactly 32 bytes, that data under `persistent_storage_dir` is
+    /// encrypted with. Checked at startup against the key the directory was first used with.
+    pub encryption_key_file: Option<PathBuf>,
 }
 
 impl Default for StorageConfig {
     fn default() -> StorageConfig {
         StorageConfig {
             persistent_storage_dir: PathBuf::from("/var/lib/squeef"),
+            encryption_key_file: None,
+        }
+    }
+}
+
+#[serde_inline_default]
+#[derive(Deserialize, Debug)]
+#[serde(deny_unknown_fields)]
+pub struct AuthConfig {
+    /// Let clients run commands without authenticating
+    #[serde(default)]
+    pub allow_anonymous: bool,
+
+    /// User created when the server starts, with the password in `admin_password_file`
+    #[serde_inline_default(String::from("admin"))]
+    pub admin_user: String,
+
+    /// File holding the password of `admin_user`. No user is created when unset
+    pub admin_password_file: Option<PathBuf>,
+}
+
+impl Default for AuthConfig {
+    fn default() -> AuthConfig {
+        AuthConfig {
+            allow_anonymous: false,
+            admin_user: String::from("admin"),
+            admin_password_file: None,
+        }
+    }
+}
+
+impl AuthConfig {
+    /// Password of the admin user, if there is one. A trailing newline in the file is ignored.
+    pub fn admin_password(&self) -> Result<Option<String>, ConfigError> {
+        let path = match &self.admin_password_file {
+            Some(path) => path,
+            None => return Ok(None),
+        };
+
+        let password =
+            std::fs::read_to_string(path).map_err(|e| ConfigError::Read(path.clone(), e))?;
+
+        let password = password.trim_end_matches(['\r', '\n']);
+
+        if password.is_empty() {
+            return Err(ConfigError::Invalid(format!(
+                "auth.admin_password_file [{}] is empty",
+                path.display()
+            )));
+        }
+
+        return Ok(Some(String::from(password)));
+    }
+}
+
+/// A `[[log]]` entry, declaring one logger
+#[serde_inline_default]
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct LogConfig {
+    pub name: String,
+
+    #[serde_inline_default(LogLevel::INFO)]
+    pub level: LogLevel,
+
+    #[serde_inline_default(LogFormat::Text)]
+    pub format: LogFormat,
+
+    #[serde(default)]
+    pub output: LogOutputKind,
+
+    /// Log file, for the `file` output
+    pub path: Option<PathBuf>,
+
+    /// Rotate the log file before it grows beyond this many bytes
+    pub max_size: Option<u64>,
+
+    /// Rotate the log file every day
+    #[serde(default)]
+    pub rotate_daily: bool,
+
+    /// Number of rotated log files kept
+    #[serde_inline_default(7)]
+    pub keep: usize,
+
+    /// Port of the syslog daemon on localhost, for the `syslog` output
+    #[serde_inline_default(514)]
+    pub syslog_port: u16,
+}
+
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum LogOutputKind {
+    #[default]
+    Stderr,
+    File,
+    Syslog,
+}
+
+fn default_log() -> Vec<LogConfig> {
+    return vec![LogConfig {
+        name: String::from("default"),
+        level: LogLevel::DEBUG,
+        format: LogFormat::Color,
+        output: LogOutputKind::Stderr,
+        path: None,
+        max_size: None,
+        rotate_daily: false,
+        keep: 7,
+        syslog_port: 514,
+    }];
+}
+
+impl LogConfig {
+    pub fn logger(&self) -> std::io::Result<Logger> {
+        let output: Box<dyn LogOutput> = match self.output {
+            LogOutputKind::Stderr => Box::new(StreamOutput(stderr())),
+            LogOutputKind::File => Box::new(RotatingFile::open(
+                // Checked by `Config::validate`
+                self.path.clone().unwrap(),
+                Rotation {
+                    max_size: self.max_size,
+                    daily: self.rotate_daily,
+                    keep: self.keep,
+                },
+            )?),
+            LogOutputKind::Syslog => Box::new(SyslogOutput::new(self.syslog_port)?),
+        };
+
+        return Ok(Logger::new(
+            self.name.clone(),
+            self.level,
+            self.format,
+            output,
+        ));
+    }
+
+    /// Whether `self` and `other` only differ by their level, which can change at runtime
+    fn same_but_level(&self, other: &LogConfig) -> bool {
+        return *self
+            == LogConfig {
+                level: self.level,
+                ..other.clone()
+            };
+    }
+}
+
+#[derive(Debug)]
+pub enum ConfigError {
+    Read(PathBuf, std::io::Error),
+    Parse(PathBuf, toml::de::Error),
+    Env(String, String),
+    Invalid(String),
+}
+
+impl Display for ConfigError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        return match self {
+            ConfigError::Read(path, e) => {
+                write!(f, "Failed to read config file [{}]: {}", path.display(), e)
+            }
+            ConfigError::Parse(path, e) => {
+                write!(f, "Invalid config file [{}]: {}", path.display(), e)
+            }
+            ConfigError::Env(var, msg) => {
+                write!(f, "Invalid environment variable [{}]: {}", var, msg)
+            }
+            ConfigError::Invalid(msg) => write!(f, "Invalid config: {}", msg),
+        };
+    }
+}
+
+impl Config {
+    /// Load the config file named on the command line, or `squeef.toml` if there is one, then apply
+    /// the `SQUEEF_*` environment variables and finally the command line overrides.
+    pub fn load(cli: &Cli) -> Result<Config, ConfigError> {
+        let path = cli
+            .config
+            .clone()
+            .unwrap_or_else(|| PathBuf::from(DEFAULT_CONFIG_PATH));
+
+        let contents = match std::fs::read_to_string(&path) {
+            Ok(contents) => contents,
+            // Only the default config file is optional
+            Err(e) if e.kind() == ErrorKind::NotFound && cli.config.is_none() => String::new(),
+            Err(e) => return Err(ConfigError::Read(path, e)),
+        };
+
+        let mut table: toml::Table =
+            toml::from_str(&contents).map_err(|e| ConfigError::Parse(path.clone(), e))?;
+
+        let (overrides, ignored) = apply_env_overrides(&mut table, std::env::vars())?;
+
+        let mut config: Config = table.try_into().map_err(|e| {
+            if overrides.is_empty() {
+                ConfigError::Parse(path.clone(), e)
+            } else {
+                ConfigError::Invalid(format!(
+                    "{} (config file [{}] overridden by {})",
+                    e.to_string().trim_end(),
+                    path.display(),
+                    overrides.join(", ")
+                ))
+            }
+        })?;
+
+        config.warnings = ignored
+            .into_iter()
+            .map(|var| {
+                format!(
+                    "Ignoring environment variable [{}], it isn't shaped SQUEEF_<SECTION>_<KEY>",
+                    var
+                )
+            })
+            .collect();
+
+        if let Some(port) = cli.port {
+            config.server.port = port;
+        }
+
+        if let Some(data_dir) = &cli.data_dir {
+            config.storage.persistent_storage_dir = data_dir.clone();
+        }
+
+        config.validate()?;
+
+        return Ok(config);
+    }
+
+    /// Settings which differ between `self` and `new` but only take effect after a restart
+    pub fn restart_required_changes(&self, new: &Config) -> Vec<&'static str> {
+        let mut changes = vec![];
+
+        if self.server.port != new.server.port {
+            changes.push("server.port");
+        }
+
+        if self.server.bind != new.server.bind {
+            changes.push("server.bind");
+        }
+
+        if self.server.metrics_bind != new.server.metrics_bind {
+            changes.push("server.metrics_bind");
+        }
+
+        if self.server.tls_cert != new.server.tls_cert
+            || self.server.tls_key != new.server.tls_key
+            || self.server.tls_client_ca != new.server.tls_client_ca
+        {
+            changes.push("server.tls_*");
+        }
+
+        if self.server.audit_log != new.server.audit_log {
+            changes.push("server.audit_log");
+        }
+
+        if self.server.audit_key_file != new.server.audit_key_file {
+            changes.push("server.audit_key_file");
+        }
+
+        if self.server.log_queue_size != new.server.log_queue_size {
+            changes.push("server.log_queue_size");
+        }
+
+        if self.server.log_overflow != new.server.log_overflow {
+            changes.push("server.log_overflow");
+        }
+
+        if self.storage.persistent_storage_dir != new.storage.persistent_storage_dir {
+            changes.push("storage.persistent_storage_dir");
+        }
+
+        if self.storage.encryption_key_file != new.storage.encryption_key_file {
+            changes.push("storage.encryption_key_file");
+        }
+
+        if self.auth.admin_user != new.auth.admin_user {
+            changes.push("auth.admin_user");
+        }
+
+        if self.auth.admin_password_file != new.auth.admin_password_file {
+            changes.push("auth.admin_password_file");
+        }
+
+        if log_configs_changed(&self.log, &new.log) {
+            changes.push("log");
+        }
+
+        if log_configs_changed(&self.slow_query_log, &new.slow_query_log) {
+            changes.push("slow_query_log");
+        }
+
+        return changes;
+    }
+
+    /// Build the loggers declared by the `[[log]]` entries
+    pub fn loggers(&self) -> Result<Loggers, ConfigError> {
+        return self.build_loggers(&self.log);
+    }
+
+    /// Build the loggers declared by the `[[slow_query_log]]` entries, if there are any
+    pub fn slow_query_loggers(&self) -> Result<Option<Loggers>, ConfigError> {
+        if self.slow_query_log.is_empty() {
+            return Ok(None);
+        }
+
+        return Ok(Some(self.build_loggers(&self.slow_query_log)?));
+    }
+
+    fn build_loggers(&self, logs: &[LogConfig]) -> Result<Loggers, ConfigError> {
+        let mut loggers = vec![];
+
+        for log in logs {
+            let logger = log.logger().map_err(|e| {
+                ConfigError::Invalid(format!("Failed to open logger [{}]: {}", log.name, e))
+            })?;
+
+            loggers.push(logger);
+        }
+
+        return Ok(Loggers::new(
+            loggers,
+            self.server.log_queue_size,
+            self.server.log_overflow,
+        ));
+    }
+
+    /// `new`, with the settings which require a restart kept as they are in `self`
+    pub fn reloaded(&self, mut new: Config) -> Config {
+        new.server.port = self.server.port;
+        new.server.bind = self.server.bind.clone();
+        new.server.metrics_bind = self.server.metrics_bind.clone();
+        new.server.tls_cert = self.server.tls_cert.clone();
+        new.server.tls_key = self.server.tls_key.clone();
+        new.server.tls_client_ca = self.server.tls_client_ca.clone();
+        new.server.audit_log = self.server.audit_log.clone();
+        new.server.audit_key_file = self.server.audit_key_file.clone();
+        new.server.log_queue_size = self.server.log_queue_size;
+        new.server.log_overflow = self.server.log_overflow;
+        new.storage.persistent_storage_dir = self.storage.persistent_storage_dir.clone();
+        new.storage.encryption_key_file = self.storage.encryption_key_file.clone();
+        new.auth.admin_user = self.auth.admin_user.clone();
+        new.auth.admin_password_file = self.auth.admin_password_file.clone();
+
+        new.log = reloaded_log_configs(&self.log, &new.log);
+        new.slow_query_log = reloaded_log_configs(&self.slow_query_log, &new.slow_query_log);
+
+        return new;
+    }
+
+    fn validate(&self) -> Result<(), ConfigError> {
+        if self.server.max_concurrent_connection == 0 {
+            return Err(ConfigError::Invalid(String::from(
+                "server.max_concurrent_connection must be at least 1",
+            )));
+        }
+
+        if self.server.log_queue_size == 0 {
+            return Err(ConfigError::Invalid(String::from(
+                "server.log_queue_size must be at least 1",
+            )));
+        }
+
+        if self.server.tls_cert.is_some() != self.server.tls_key.is_some() {
+            return Err(ConfigError::Invalid(String::from(
+                "server.tls_cert and server.tls_key must be set together",
+            )));
+        }
+
+        if self.server.tls_client_ca.is_some() && self.server.tls_cert.is_none() {
+            return Err(ConfigError::Invalid(String::from(
+                "server.tls_client_ca requires server.tls_cert and server.tls_key",
+            )));
+        }
+
+        if self.server.audit_log.is_some() && self.server.audit_key_file.is_none() {
+            return Err(ConfigError::Invalid(String::from(
+                "server.audit_log requires server.audit_key_file",
+            )));
+        }
+
+        validate_log_configs(&self.log)?;
+        validate_log_configs(&self.slow_query_log)?;
+
+        return Ok(());
+    }
+}
+
+/// Whether loggers changed in a way which requires a restart, i.e. in anything but their levels
+fn log_configs_changed(current: &[LogConfig], new: &[LogConfig]) -> bool {
+    return current.len() != new.len()
+        || current
+            .iter()
+            .zip(new)
+            .any(|(current, new)| !current.same_but_level(new));
+}
+
+/// `current` loggers with the levels from `new`. Only the levels of running loggers can change.
+fn reloaded_log_configs(current: &[LogConfig], new: &[LogConfig]) -> Vec<LogConfig> {
+    let mut reloaded = current.to_vec();
+
+    for log in &mut reloaded {
+        if let Some(new_log) = new.iter().find(|l| l.name == log.name) {
+            log.level = new_log.level;
+        }
+    }
+
+    return reloaded;
+}
+
+fn validate_log_configs(logs: &[LogConfig]) -> Result<(), ConfigError> {
+    let mut log_names = HashSet::new();
+
+    for log in logs {
+        if !log_names.insert(&log.name) {
+            return Err(ConfigError::Invalid(format!(
+                "Duplicate logger name [{}]",
+                log.name
+            )));
+        }
+
+        match (log.output, &log.path) {
+            (LogOutputKind::File, None) => {
+                return Err(ConfigError::Invalid(format!(
+                    "Logger [{}] has a file output but no path",
+                    log.name
+                )))
+            }
+            (LogOutputKind::Stderr | LogOutputKind::Syslog, Some(_)) => {
+                return Err(ConfigError::Invalid(format!(
+                    "Logger [{}] has a path but doesn't have a file output",
+                    log.name
+                )))
+            }
+            _ => {}
+        }
+
+        if log.max_size == Some(0) {
+            return Err(ConfigError::Invalid(format!(
+                "Logger [{}] has a max_size of 0",
+                log.name
+            )));
+        }
+    }
+
+    return Ok(());
+}
+
+/// Set `table[section][key]` for every `SQUEEF_<SECTION>_<KEY>` variable. Values are parsed as TOML
+/// when possible (numbers, booleans, arrays) and taken as plain strings otherwise. Returns the names
+/// of the variables applied, and of the `SQUEEF_*` variables ignored because they aren't shaped
+/// like an override, such as the client's `SQUEEF_PASSWORD`.
+fn apply_env_overrides(
+    table: &mut toml::Table,
+    vars: impl Iterator<Item = (String, String)>,
+) -> Result<(Vec<String>, Vec<String>), ConfigError> {
+    let mut applied = vec![];
+    let mut ignored = vec![];
+
+    for (var, raw) in vars {
+        let name = match var.strip_prefix(ENV_PREFIX) {
+            Some(name) => name.to_lowercase(),
+            None => continue,
+        };
+
+        let (section, key) = match name.split_once('_') {
+            Some((section, key)) if !section.is_empty() && !key.is_empty() => (section, key),
+            _ => {
+                ignored.push(var);
+                continue;
+            }
+        };
+
+        let value = match toml::from_str::<toml::Table>(&format!("v = {}", raw)) {
+            Ok(mut parsed) => parsed.remove("v").unwrap(),
+            Err(_) => toml::Value::String(raw),
+        };
+
+        let section_table = table
+            .entry(section)
+            .or_insert_with(|| toml::Value::Table(toml::Table::new()));
+
+        match section_table {
+            toml::Value::Table(section_table) => {
+                section_table.insert(String::from(key), value);
+            }
+            _ => {
+                return Err(ConfigError::Env(
+                    var,
+                    format!("[{}] is not a config section", section),
+                ))
+            }
         }
+
+        applied.push(var);
+    }
+
+    return Ok((applied, ignored));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn vars(vars: &[(&str, &str)]) -> impl Iterator<Item = (String, String)> {
+        return vars
+            .iter()
+            .map(|(var, value)| (String::from(*var), String::from(*value)))
+            .collect::<Vec<_>>()
+            .into_iter();
+    }
+
+    fn overridden(file: &str, env: &[(&str, &str)]) -> Result<Config, ConfigError> {
+        let mut table: toml::Table = toml::from_str(file).unwrap();
+        apply_env_overrides(&mut table, vars(env))?;
+
+        let config: Config = table
+            .try_into()
+            .map_err(|e: toml::de::Error| ConfigError::Invalid(e.to_string()))?;
+        config.validate()?;
+
+        return Ok(config);
+    }
+
+    #[test]
+    fn overrides_keys_with_underscores() {
+        let config = overridden(
+            "[server]\nport = 1234\nmax_concurrent_connection = 2\n",
+            &[
+                ("SQUEEF_SERVER_PORT", "4321"),
+                ("SQUEEF_SERVER_MAX_CONCURRENT_CONNECTION", "16"),
+            ],
+        )
+        .unwrap();
+
+        assert_eq!(config.server.port, 4321);
+        assert_eq!(config.server.max_concurrent_connection, 16);
+    }
+
+    #[test]
+    fn creates_missing_sections() {
+        let config = overridden("", &[("SQUEEF_AUTH_ALLOW_ANONYMOUS", "true")]).unwrap();
+
+        assert!(config.auth.allow_anonymous);
+    }
+
+    #[test]
+    fn unparsable_values_are_strings() {
+        let config = overridden("", &[("SQUEEF_AUTH_ADMIN_USER", "root")]).unwrap();
+
+        assert_eq!(config.auth.admin_user, "root");
+    }
+
+    #[test]
+    fn ignores_other_variables() {
+        let mut table = toml::Table::new();
+        let (applied, ignored) = apply_env_overrides(
+            &mut table,
+            vars(&[
+                ("SQUEEF_PASSWORD", "secret"),
+                ("SQUEEF_", "x"),
+                ("SQUEEF__PORT", "1"),
+                ("HOME", "/root"),
+                ("SQUEEF_SERVER_PORT", "1"),
+            ]),
+        )
+        .unwrap();
+
+        assert_eq!(applied, ["SQUEEF_SERVER_PORT"]);
+        assert_eq!(ignored, ["SQUEEF_PASSWORD", "SQUEEF_", "SQUEEF__PORT"]);
+        assert_eq!(table.len(), 1);
+    }
+
+    #[test]
+    fn fails_on_non_section() {
+        let mut table: toml::Table = toml::from_str("server = 1").unwrap();
+        let res = apply_env_overrides(&mut table, vars(&[("SQUEEF_SERVER_PORT", "1")]));
+
+        assert!(matches!(res, Err(ConfigError::Env(var, _)) if var == "SQUEEF_SERVER_PORT"));
+    }
+
+    #[test]
+    fn rejects_invalid_values() {
+        let negative = overridden("", &[("SQUEEF_SERVER_MAX_CONCURRENT_CONNECTION", "-1")]);
+        assert!(matches!(negative, Err(ConfigError::Invalid(_))));
+
+        let zero = overridden("", &[("SQUEEF_SERVER_MAX_CONCURRENT_CONNECTION", "0")]);
+        assert!(
+            matches!(zero, Err(ConfigError::Invalid(e)) if e.contains("max_concurrent_connection"))
+        );
+
+        let unknown = overridden("", &[("SQUEEF_SERVER_NO_SUCH_KEY", "1")]);
+        assert!(matches!(unknown, Err(ConfigError::Invalid(e)) if e.contains("no_such_key")));
+    }
+
+    #[test]
+    fn cli_overrides_config_file() {
+        let path = std::env::temp_dir().join(format!("squeef-cli-{}.toml", std::process::id()));
+        std::fs::write(
+            &path,
+            "[server]\nport = 1234\n[storage]\npersistent_storage_dir = \"/from/file\"\n",
+        )
+        .unwrap();
+
+        let cli = Cli::parse_from([
+            "server",
+            "--config",
+            path.to_str().unwrap(),
+            "--port",
+            "4321",
+            "--data-dir",
+            "/from/cli",
+        ]);
+        let config = Config::load(&cli);
+        std::fs::remove_file(&path).unwrap();
+
+        let config = config.unwrap();
+        assert_eq!(config.server.port, 4321);
+        assert_eq!(
+            config.storage.persistent_storage_dir,
+            PathBuf::from("/from/cli")
+        );
+    }
+
+    #[test]
+    fn encryption_key_file_needs_a_restart() {
+        let current = overridden("", &[]).unwrap();
+        let new = overridden(
+            "[storage]\nencryption_key_file = \"/etc/squeef/storage.key\"\n",
+            &[],
+        )
+        .unwrap();
+
+        assert_eq!(
+            current.restart_required_changes(&new),
+            vec!["storage.encryption_key_file"]
+        );
+        assert_eq!(current.reloaded(new).storage.encryption_key_file, None);
     }
 }
@@ -1,24 +1,258 @@
+// Explicit `return`s and `-> ()` are house style
+#![allow(clippy::needless_return, clippy::unused_unit)]
+
 // Std Lib Imports
-use std::io::stderr;
+use std::path::Path;
+use std::sync::atomic::Ordering;
+use std::sync::{Arc, RwLock};
+use std::thread;
+use std::time::Duration;
 
 // Executable Imports
 mod config;
-mod log;
-mod server;
 mod thread_pool;
 
-use config::CONFIG;
-use log::{LogLevel, Logger, Loggers};
-use server::Server;
+use config::{Cli, CliCommand, Config, SharedConfig};
+
+// Squeef Lib Imports
+use squeef::audit;
+use squeef::auth::Credentials;
+use squeef::cipher::Cipher;
+use squeef::log::{LogLevel, Loggers};
+use squeef::server::{Server, ServerSettings};
+
+// Third Party Imports
+use clap::Parser;
+use signal_hook::consts::{SIGHUP, SIGINT, SIGTERM};
+use signal_hook::iterator::Signals;
 
 fn main() {
-    let loggers = Loggers::from(vec![Logger::new(
-        String::from("default"),
-        LogLevel::DEBUG,
-        Box::new(stderr()),
-    )]);
+    let cli = Cli::parse();
+
+    // Verifying the audit log mustn't depend on the config of a server which may be compromised
+    if let Some(CliCommand::VerifyAuditLog { path, key_file }) = &cli.command {
+        std::process::exit(verify_audit_log(path, key_file));
+    }
+
+    let config = match Config::load(&cli) {
+        Ok(config) => config,
+        Err(e) => {
+            eprintln!("{}", e);
+            std::process::exit(1);
+        }
+    };
+
+    let loggers = match config.loggers() {
+        Ok(loggers) => loggers,
+        Err(e) => {
+            eprintln!("{}", e);
+            std::process::exit(1);
+        }
+    };
+
+    let slow_query_loggers = match config.slow_query_loggers() {
+        Ok(slow_query_loggers) => slow_query_loggers,
+        Err(e) => {
+            eprintln!("{}", e);
+            std::process::exit(1);
+        }
+    };
+
+    let admin_password = match config.auth.admin_password() {
+        Ok(admin_password) => admin_password,
+        Err(e) => {
+            eprintln!("{}", e);
+            std::process::exit(1);
+        }
+    };
+
+    if let Some(key_file) = &config.storage.encryption_key_file {
+        let checked = Cipher::load(key_file)
+            .and_then(|cipher| cipher.check_key(&config.storage.persistent_storage_dir));
+
+        if let Err(e) = checked {
+            eprintln!("{}", e);
+            std::process::exit(1);
+        }
+    }
+
+    for warning in &config.warnings {
+        loggers.log(LogLevel::WARN, warning);
+    }
+
+    if admin_password.is_none() && !config.auth.allow_anonymous {
+        loggers.log(
+            LogLevel::WARN,
+            "Neither auth.admin_password_file nor auth.allow_anonymous is set. No client can log in",
+        );
+    }
+
+    let mut builder = Server::builder()
+        .port(config.server.port)
+        .max_concurrent_connections(config.server.max_concurrent_connection)
+        .shutdown_grace_period(Duration::from_secs(config.server.shutdown_grace_period))
+        .lock_timeout(Duration::from_millis(config.server.lock_timeout))
+        .allow_anonymous(config.auth.allow_anonymous)
+        .loggers(loggers);
+
+    if let Some(admin_password) = admin_password {
+        let credentials = Credentials::new(&admin_password);
+
+        builder = match builder.user(config.auth.admin_user.clone(), credentials, true) {
+            Ok(builder) => builder,
+            Err(e) => {
+                eprintln!("{}", e);
+                std::process::exit(1);
+            }
+        };
+    }
+
+    for addr in &config.server.bind {
+        builder = builder.bind(addr.clone());
+    }
+
+    if let Some(metrics_bind) = &config.server.metrics_bind {
+        builder = builder.metrics_bind(metrics_bind.clone());
+    }
+
+    if let (Some(cert), Some(key)) = (&config.server.tls_cert, &config.server.tls_key) {
+        builder = builder.tls(
+            cert.clone(),
+            key.clone(),
+            config.server.tls_client_ca.clone(),
+        );
+    }
+
+    if let (Some(audit_log), Some(key_file)) =
+        (&config.server.audit_log, &config.server.audit_key_file)
+    {
+        builder = builder.audit_log(audit_log.clone(), key_file.clone());
+    }
 
-    let mut s = Server::new(CONFIG.server.port, loggers);
+    if let Some(slow_query_loggers) = slow_query_loggers.clone() {
+        builder = builder.slow_query_log(
+            slow_query_loggers,
+            Duration::from_millis(config.server.slow_query_threshold),
+        );
+    }
+
+    let mut s = match builder.build() {
+        Ok(s) => s,
+        Err(e) => {
+            eprintln!("{}", e);
+            std::process::exit(1);
+        }
+    };
+
+    let config: SharedConfig = Arc::new(RwLock::new(Arc::new(config)));
+
+    let mut signals = Signals::new([SIGHUP, SIGTERM, SIGINT]).unwrap();
+    let shutdown = s.shutdown_flag();
+    let settings = s.settings();
+    let loggers = s.loggers();
+
+    thread::spawn(move || {
+        for signal in signals.forever() {
+            match signal {
+                SIGHUP => reload_config(
+                    &cli,
+                    &config,
+                    &settings,
+                    &loggers,
+                    slow_query_loggers.as_ref(),
+                ),
+                _ => shutdown.store(true, Ordering::SeqCst),
+            }
+        }
+    });
 
     s.run();
 }
+
+/// Check the audit log at `path` with the key in `key_file` and return the exit code
+fn verify_audit_log(path: &Path, key_file: &Path) -> i32 {
+    let key = match audit::load_key(key_file) {
+        Ok(key) => key,
+        Err(e) => {
+            eprintln!("{}", e);
+            return 2;
+        }
+    };
+
+    return match audit::verify(path, &key) {
+        Ok(head) => {
+            println!(
+                "Audit log [{}] is intact. {} record(s), last hash {}",
+                path.display(),
+                head.records,
+                head.hash
+            );
+            0
+        }
+        Err(e) => {
+            eprintln!("Audit log [{}] is corrupt. {}", path.display(), e);
+            1
+        }
+    };
+}
+
+/// Re-read the config file and apply the settings which are safe to change at runtime. A config
+/// which fails to load leaves the current one in place.
+fn reload_config(
+    cli: &Cli,
+    config: &SharedConfig,
+    settings: &Arc<RwLock<ServerSettings>>,
+    loggers: &Loggers,
+    slow_query_loggers: Option<&Loggers>,
+) -> () {
+    let new_config = match Config::load(cli) {
+        Ok(new_config) => new_config,
+        Err(e) => {
+            loggers.log(
+                LogLevel::ERROR,
+                &format!("Failed to reload config, keeping the current one. {}", e),
+            );
+            return;
+        }
+    };
+
+    let current_config = config.read().unwrap().clone();
+
+    for field in current_config.restart_required_changes(&new_config) {
+        loggers.log(
+            LogLevel::WARN,
+            &format!("Change to [{}] requires a restart. Ignoring it", field),
+        );
+    }
+
+    let new_config = current_config.reloaded(new_config);
+
+    {
+        let mut settings = settings.write().unwrap();
+        settings.max_concurrent_connections = new_config.server.max_concurrent_connection;
+        settings.shutdown_grace_period =
+            Duration::from_secs(new_config.server.shutdown_grace_period);
+        settings.lock_timeout = Duration::from_millis(new_config.server.lock_timeout);
+        settings.allow_anonymous = new_config.auth.allow_anonymous;
+
+        if slow_query_loggers.is_some() {
+            settings.slow_query_threshold = Some(Duration::from_millis(
+                new_config.server.slow_query_threshold,
+            ));
+        }
+    }
+
+    for log in &new_config.log {
+        loggers.set_log_level(&log.name, log.level);
+    }
+
+    if let Some(slow_query_loggers) = slow_query_loggers {
+        for log in &new_config.slow_query_log {
+            slow_query_loggers.set_log_level(&log.name, log.level);
+        }
+    }
+
+    loggers.log(LogLevel::INFO, "Reloaded config");
+
+    *config.write().unwrap() = Arc::new(new_config);
+}
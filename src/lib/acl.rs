@@ -0,0 +1,473 @@
+use std::collections::{BTreeSet, HashMap, HashSet};
+
+use crate::error::Error;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Privilege {
+    Select,
+    Insert,
+    Update,
+    Delete,
+    /// Create tables in a database
+    Create,
+    /// Reference a table from a foreign key
+    References,
+}
+
+impl Privilege {
+    pub const ALL: [Privilege; 6] = [
+        Privilege::Select,
+        Privilege::Insert,
+        Privilege::Update,
+        Privilege::Delete,
+        Privilege::Create,
+        Privilege::References,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        return match self {
+            Privilege::Select => "SELECT",
+            Privilege::Insert => "INSERT",
+            Privilege::Update => "UPDATE",
+            Privilege::Delete => "DELETE",
+            Privilege::Create => "CREATE",
+            Privilege::References => "REFERENCES",
+        };
+    }
+
+    /// Bit of the privilege in the masks sent over the wire
+    pub fn bit(&self) -> u8 {
+        return match self {
+            Privilege::Select => 0x01,
+            Privilege::Insert => 0x02,
+            Privilege::Update => 0x04,
+            Privilege::Delete => 0x08,
+            Privilege::Create => 0x10,
+            Privilege::References => 0x20,
+        };
+    }
+}
+
+/// What a privilege is granted on, as named in GRANT and REVOKE. Tables are in the open database.
+#[derive(Debug, Clone, PartialEq)]
+pub enum GrantOn {
+    Database { name: String },
+    Table { name: String },
+}
+
+/// What a privilege is granted on, once resolved
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum Object {
+    Database { name: String },
+    Table { db: String, name: String },
+}
+
+#[derive(Debug, Default)]
+struct Role {
+    members: HashSet<String>,
+    grants: HashMap<Object, BTreeSet<Privilege>>,
+}
+
+/// Role every user is a member of, anonymous connections included
+pub const PUBLIC: &str = "PUBLIC";
+
+/// Roles, the users who are members of them and the privileges granted to them. A privilege
+/// granted on a database covers every table in it.
+#[derive(Debug)]
+pub struct Acl {
+    roles: HashMap<String, Role>,
+}
+
+impl Default for Acl {
+    fn default() -> Acl {
+        return Acl {
+            roles: HashMap::from([(String::from(PUBLIC), Role::default())]),
+        };
+    }
+}
+
+impl Acl {
+    pub fn new() -> Acl {
+        return Acl::default();
+    }
+
+    pub fn create_role(&mut self, name: String) -> Result<(), Error> {
+        if self.roles.contains_key(&name) {
+            return Err(Error::from(format!(
+                "Failed to create role. Name [{}] already in use",
+                name
+            )));
+        }
+
+        self.roles.insert(name, Role::default());
+
+        return Ok(());
+    }
+
+    /// Whether role `name` exists. PUBLIC always does.
+    pub fn role_exists(&self, name: &str) -> bool {
+        return self.roles.contains_key(name);
+    }
+
+    pub fn drop_role(&mut self, name: &str) -> Result<(), Error> {
+        if name == PUBLIC {
+            return Err(Error::from(format!("Role [{}] can't be dropped", PUBLIC)));
+        }
+
+        match self.roles.remove(name) {
+            Some(_) => return Ok(()),
+            None => return Err(Error::from(format!("No role with name [{}]", name))),
+        }
+    }
+
+    pub fn grant_role(&mut self, role: &str, user: String) -> Result<(), Error> {
+        check_not_public(role)?;
+        self.role_mut(role)?.members.insert(user);
+
+        return Ok(());
+    }
+
+    pub fn revoke_role(&mut self, role: &str, user: &str) -> Result<(), Error> {
+        check_not_public(role)?;
+        self.role_mut(role)?.members.remove(user);
+
+        return Ok(());
+    }
+
+    pub fn grant(
+        &mut self,
+        role: &str,
+        privileges: &[Privilege],
+        object: Object,
+    ) -> Result<(), Error> {
+        self.role_mut(role)?
+            .grants
+            .entry(object)
+            .or_default()
+            .extend(privileges);
+
+        return Ok(());
+    }
+
+    pub fn revoke(
+        &mut self,
+        role: &str,
+        privileges: &[Privilege],
+        object: &Object,
+    ) -> Result<(), Error> {
+        let grants = &mut self.role_mut(role)?.grants;
+
+        if let Some(granted) = grants.get_mut(object) {
+            granted.retain(|p| !privileges.contains(p));
+
+            if granted.is_empty() {
+                grants.remove(object);
+            }
+        }
+
+        return Ok(());
+    }
+
+    /// Forget a dropped user's role memberships
+    pub fn forget_user(&mut self, user: &str) -> () {
+        for role in self.roles.values_mut() {
+            role.members.remove(user);
+        }
+    }
+
+    /// Forget the grants on database `name` and on its tables
+    pub fn drop_database(&mut self, name: &str) -> () {
+        self.remap(|object| match object {
+            Object::Database { name: n } | Object::Table { db: n, .. } if n == name => None,
+            object => Some(object),
+        });
+    }
+
+    /// Forget the grants on table `name` of database `db`
+    pub fn drop_table(&mut self, db: &str, name: &str) -> () {
+        let dropped = Object::Table {
+            db: String::from(db),
+            name: String::from(name),
+        };
+
+        self.remap(|object| (object != dropped).then_some(object));
+    }
+
+    /// Move the grants on database `from` and on its tables to database `to`
+    pub fn rename_database(&mut self, from: &str, to: &str) -> () {
+        self.remap(|object| match object {
+            Object::Database { name } if name == from => Some(Object::Database {
+                name: String::from(to),
+            }),
+            Object::Table { db, name } if db == from => Some(Object::Table {
+                db: String::from(to),
+                name,
+            }),
+            object => Some(object),
+        });
+    }
+
+    /// Move the grants on table `from` of database `db` to table `to`
+    pub fn rename_table(&mut self, db: &str, from: &str, to: &str) -> () {
+        self.remap(|object| match object {
+            Object::Table { db: d, name } if d == db && name == from => Some(Object::Table {
+                db: d,
+                name: String::from(to),
+            }),
+            object => Some(object),
+        });
+    }
+
+    /// Whether `user` holds any of `privileges` on `object`, through one of their roles. `None`
+    /// stands for an anonymous connection, which only holds the privileges granted to PUBLIC.
+    pub fn has_any(&self, user: Option<&str>, privileges: &[Privilege], object: &Object) -> bool {
+        let db_object = match object {
+            Object::Table { db, .. } => Some(Object::Database { name: db.clone() }),
+            Object::Database { .. } => None,
+        };
+
+        return self.roles_of(user).any(|role| {
+            [Some(object), db_object.as_ref()]
+                .into_iter()
+                .flatten()
+                .filter_map(|o| role.grants.get(o))
+                .any(|granted| privileges.iter().any(|p| granted.contains(p)))
+        });
+    }
+
+    /// Whether `user` holds any privilege on database `db` or on a table in it
+    pub fn has_any_in_database(&self, user: Option<&str>, db: &str) -> bool {
+        return self.roles_of(user).any(|role| {
+            role.grants.keys().any(|object| match object {
+                Object::Database { name } => name == db,
+                Object::Table { db: table_db, .. } => table_db == db,
+            })
+        });
+    }
+
+    fn roles_of<'a>(&'a self, user: Option<&'a str>) -> impl Iterator<Item = &'a Role> {
+        return self
+            .roles
+            .iter()
+            .filter(move |(name, role)| {
+                *name == PUBLIC || user.is_some_and(|user| role.members.contains(user))
+            })
+            .map(|(_, role)| role);
+    }
+
+    /// Replace the object of every grant with what `f` returns for it, dropping the grant if
+    /// that's `None`. Grants which end up on the same object are merged.
+    fn remap(&mut self, f: impl Fn(Object) -> Option<Object>) -> () {
+        for role in self.roles.values_mut() {
+            for (object, privileges) in std::mem::take(&mut role.grants) {
+                if let Some(object) = f(object) {
+                    role.grants.entry(object).or_default().extend(privileges);
+                }
+            }
+        }
+    }
+
+    fn role_mut(&mut self, name: &str) -> Result<&mut Role, Error> {
+        return self
+            .roles
+            .get_mut(name)
+            .ok_or_else(|| Error::from(format!("No role with name [{}]", name)));
+    }
+}
+
+fn check_not_public(role: &str) -> Result<(), Error> {
+    if role == PUBLIC {
+        return Err(Error::from(format!(
+            "Every user is a member of role [{}]",
+            PUBLIC
+        )));
+    }
+
+    return Ok(());
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn database(name: &str) -> Object {
+        return Object::Database {
+            name: String::from(name),
+        };
+    }
+
+    fn table(db: &str, name: &str) -> Object {
+        return Object::Table {
+            db: String::from(db),
+            name: String::from(name),
+        };
+    }
+
+    /// `alice` is a member of `analysts`, `bob` of no role
+    fn acl() -> Acl {
+        let mut acl = Acl::new();
+        acl.create_role(String::from("analysts")).unwrap();
+        acl.grant_role("analysts", String::from("alice")).unwrap();
+
+        return acl;
+    }
+
+    #[test]
+    fn table_grants_cover_only_that_table() {
+        let mut acl = acl();
+        acl.grant("analysts", &[Privilege::Select], table("shop", "orders"))
+            .unwrap();
+
+        let orders = table("shop", "orders");
+        assert!(acl.has_any(Some("alice"), &[Privilege::Select], &orders));
+        assert!(!acl.has_any(Some("alice"), &[Privilege::Delete], &orders));
+        assert!(!acl.has_any(Some("alice"), &[Privilege::Select], &table("shop", "users")));
+        assert!(!acl.has_any(Some("alice"), &[Privilege::Select], &database("shop")));
+        assert!(!acl.has_any(Some("bob"), &[Privilege::Select], &orders));
+
+        assert!(acl.has_any_in_database(Some("alice"), "shop"));
+        assert!(!acl.has_any_in_database(Some("alice"), "blog"));
+    }
+
+    #[test]
+    fn database_grants_cover_its_tables() {
+        let mut acl = acl();
+        acl.grant("analysts", &[Privilege::Select], database("shop"))
+            .unwrap();
+
+        assert!(acl.has_any(
+            Some("alice"),
+            &[Privilege::Select],
+            &table("shop", "orders")
+        ));
+        assert!(!acl.has_any(Some("alice"), &[Privilege::Select], &table("blog", "posts")));
+    }
+
+    #[test]
+    fn revoke_removes_only_named_privileges() {
+        let mut acl = acl();
+        let orders = table("shop", "orders");
+        acl.grant(
+            "analysts",
+            &[Privilege::Select, Privilege::Insert],
+            orders.clone(),
+        )
+        .unwrap();
+
+        acl.revoke("analysts", &[Privilege::Insert], &orders)
+            .unwrap();
+        assert!(acl.has_any(Some("alice"), &[Privilege::Select], &orders));
+        assert!(!acl.has_any(Some("alice"), &[Privilege::Insert], &orders));
+
+        acl.revoke("analysts", &[Privilege::Select], &orders)
+            .unwrap();
+        assert!(!acl.has_any_in_database(Some("alice"), "shop"));
+    }
+
+    #[test]
+    fn privileges_come_from_role_membership() {
+        let mut acl = acl();
+        acl.grant("analysts", &[Privilege::Select], database("shop"))
+            .unwrap();
+        acl.grant_role("analysts", String::from("bob")).unwrap();
+
+        let orders = table("shop", "orders");
+        assert!(acl.has_any(Some("bob"), &[Privilege::Select], &orders));
+
+        acl.revoke_role("analysts", "bob").unwrap();
+        assert!(!acl.has_any(Some("bob"), &[Privilege::Select], &orders));
+
+        acl.forget_user("alice");
+        assert!(!acl.has_any(Some("alice"), &[Privilege::Select], &orders));
+
+        acl.drop_role("analysts").unwrap();
+        assert!(acl.grant("analysts", &[Privilege::Select], orders).is_err());
+    }
+
+    #[test]
+    fn public_covers_everyone() {
+        let mut acl = acl();
+        acl.grant(PUBLIC, &[Privilege::Select], database("shop"))
+            .unwrap();
+
+        let orders = table("shop", "orders");
+        assert!(acl.has_any(Some("alice"), &[Privilege::Select], &orders));
+        assert!(acl.has_any(Some("bob"), &[Privilege::Select], &orders));
+        assert!(acl.has_any(None, &[Privilege::Select], &orders));
+
+        assert!(acl.grant_role(PUBLIC, String::from("bob")).is_err());
+        assert!(acl.revoke_role(PUBLIC, "bob").is_err());
+        assert!(acl.drop_role(PUBLIC).is_err());
+    }
+
+    #[test]
+    fn anonymous_users_only_hold_public_grants() {
+        let mut acl = acl();
+        acl.grant("analysts", &[Privilege::Select], database("shop"))
+            .unwrap();
+
+        assert!(!acl.has_any(None, &[Privilege::Select], &database("shop")));
+        assert!(!acl.has_any_in_database(None, "shop"));
+    }
+
+    #[test]
+    fn roles_exist_until_dropped() {
+        let mut acl = acl();
+        assert!(acl.role_exists(PUBLIC));
+        assert!(acl.role_exists("analysts"));
+        assert!(!acl.role_exists("auditors"));
+
+        acl.drop_role("analysts").unwrap();
+        assert!(!acl.role_exists("analysts"));
+    }
+
+    #[test]
+    fn dropping_forgets_grants() {
+        let mut acl = acl();
+        acl.grant("analysts", &[Privilege::Select], table("shop", "orders"))
+            .unwrap();
+        acl.grant("analysts", &[Privilege::Select], table("shop", "users"))
+            .unwrap();
+
+        acl.drop_table("shop", "orders");
+        assert!(!acl.has_any(
+            Some("alice"),
+            &[Privilege::Select],
+            &table("shop", "orders")
+        ));
+        assert!(acl.has_any(Some("alice"), &[Privilege::Select], &table("shop", "users")));
+
+        acl.drop_database("shop");
+        assert!(!acl.has_any_in_database(Some("alice"), "shop"));
+    }
+
+    #[test]
+    fn renaming_moves_grants() {
+        let mut acl = acl();
+        acl.grant("analysts", &[Privilege::Select], table("shop", "orders"))
+            .unwrap();
+        acl.grant("analysts", &[Privilege::Insert], database("shop"))
+            .unwrap();
+
+        acl.rename_table("shop", "orders", "purchases");
+        assert!(acl.has_any(
+            Some("alice"),
+            &[Privilege::Select],
+            &table("shop", "purchases")
+        ));
+        assert!(!acl.has_any(
+            Some("alice"),
+            &[Privilege::Select],
+            &table("shop", "orders")
+        ));
+
+        acl.rename_database("shop", "store");
+        assert!(acl.has_any(
+            Some("alice"),
+            &[Privilege::Select],
+            &table("store", "purchases")
+        ));
+        assert!(acl.has_any(Some("alice"), &[Privilege::Insert], &database("store")));
+        assert!(!acl.has_any_in_database(Some("alice"), "shop"));
+    }
+}
@@ -0,0 +1,560 @@
+use std::fs::{File, OpenOptions};
+use std::io::{self, Read, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+use chrono::{Local, SecondsFormat};
+use hmac::{Hmac, Mac};
+use serde::{Deserialize, Serialize};
+use sha2::Sha256;
+
+type HmacSha256 = Hmac<Sha256>;
+
+/// Shortest audit key accepted, in bytes
+pub const MIN_KEY_LEN: usize = 32;
+
+/// `prev_hash` of the first record
+const GENESIS_HASH: &str = "0000000000000000000000000000000000000000000000000000000000000000";
+
+/// One line of the audit log, as a JSON object
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct AuditRecord {
+    /// Position of the record in the log, from 1
+    pub seq: u64,
+    pub timestamp: String,
+
+    /// User the command ran as, or tried to log in as. Empty for anonymous connections
+    pub user: String,
+    pub peer: String,
+    pub command: String,
+
+    /// `ok`, or the error the command failed with
+    pub outcome: String,
+
+    /// `hash` of the previous record
+    pub prev_hash: String,
+
+    /// HMAC-SHA256 keyed with the audit key of the record with an empty `hash`, `prev_hash`
+    /// included
+    pub hash: String,
+}
+
+impl AuditRecord {
+    fn compute_hash(&self, key: &[u8]) -> String {
+        let unhashed = AuditRecord {
+            hash: String::new(),
+            ..self.clone()
+        };
+
+        let mut mac = HmacSha256::new_from_slice(key).unwrap();
+        mac.update(serde_json::to_string(&unhashed).unwrap().as_bytes());
+
+        return mac
+            .finalize()
+            .into_bytes()
+            .iter()
+            .map(|b| format!("{:02x}", b))
+            .collect();
+    }
+}
+
+#[derive(Debug)]
+struct Chain {
+    file: File,
+    next_seq: u64,
+    last_hash: String,
+
+    /// Why an append failed. The log may end with part of a record then, which only reopening
+    /// it drops, so nothing more is appended until the server restarts.
+    failure: Option<String>,
+}
+
+/// Append-only record of logins, DDL, privilege changes, denied commands and the COMMIT or
+/// ROLLBACK of transactions running DDL, one JSON object per line.
+///
+/// Each record holds the hash of the one before it, so editing, inserting or removing a record
+/// breaks the chain from there on, which `verify` reports. Hashes are keyed with a secret kept
+/// outside the log's directory, so rewriting the chain from the altered record on takes the key
+/// as well as write access to the log. Removing the latest records only shows against a head
+/// noted down earlier.
+pub struct AuditLog {
+    path: PathBuf,
+    key: Vec<u8>,
+    truncated: usize,
+    chain: Mutex<Chain>,
+}
+
+// The key is left out, like credentials
+impl std::fmt::Debug for AuditLog {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        return f
+            .debug_struct("AuditLog")
+            .field("path", &self.path)
+            .field("truncated", &self.truncated)
+            .field("chain", &self.chain)
+            .finish_non_exhaustive();
+    }
+}
+
+impl AuditLog {
+    /// Open the audit log at `path`, creating it if needed, and continue its chain with the key
+    /// in `key_file`, which must be outside the log's directory
+    pub fn open(path: PathBuf, key_file: &Path) -> io::Result<AuditLog> {
+        check_key_location(key_file, &path)?;
+        let key = load_key(key_file)?;
+
+        let file = OpenOptions::new()
+            .create(true)
+            .read(true)
+            .append(true)
+            .open(&path)
+            .map_err(|e| {
+                io::Error::new(
+                    e.kind(),
+                    format!("Failed to open audit log [{}]: {}", path.display(), e),
+                )
+            })?;
+
+        let mut contents = vec![];
+        (&file).read_to_end(&mut contents)?;
+
+        // A crash while appending leaves part of a record without its newline. It can't be
+        // verified, so it's dropped rather than keeping the server from starting.
+        let complete = match contents.iter().rposition(|b| *b == b'\n') {
+            Some(i) => i + 1,
+            None => 0,
+        };
+        let truncated = contents.len() - complete;
+
+        if truncated > 0 {
+            file.set_len(complete as u64)?;
+            file.sync_data()?;
+        }
+
+        let last_line = contents[..complete]
+            .rsplit(|b| *b == b'\n')
+            .find(|line| !line.is_empty());
+
+        let (next_seq, last_hash) = match last_line {
+            Some(line) => {
+                let record: AuditRecord = serde_json::from_slice(line).map_err(|e| {
+                    io::Error::new(
+                        io::ErrorKind::InvalidData,
+                        format!(
+                            "Invalid last record in audit log [{}]: {}",
+                            path.display(),
+                            e
+                        ),
+                    )
+                })?;
+
+                (record.seq + 1, record.hash)
+            }
+            None => (1, String::from(GENESIS_HASH)),
+        };
+
+        return Ok(AuditLog {
+            path,
+            key,
+            truncated,
+            chain: Mutex::new(Chain {
+                file,
+                next_seq,
+                last_hash,
+                failure: None,
+            }),
+        });
+    }
+
+    pub fn path(&self) -> &Path {
+        return &self.path;
+    }
+
+    /// Bytes of a partial last record dropped when the log was opened
+    pub fn truncated(&self) -> usize {
+        return self.truncated;
+    }
+
+    /// Fail if an earlier append failed, in which case every later one fails too
+    pub fn check_writable(&self) -> io::Result<()> {
+        return match &self.chain.lock().unwrap().failure {
+            Some(e) => Err(unwritable(e)),
+            None => Ok(()),
+        };
+    }
+
+    /// Append a record and sync it to disk before returning
+    pub fn append(&self, user: &str, peer: &str, command: &str, outcome: &str) -> io::Result<()> {
+        let mut chain = self.chain.lock().unwrap();
+
+        if let Some(e) = &chain.failure {
+            return Err(unwritable(e));
+        }
+
+        let mut record = AuditRecord {
+            seq: chain.next_seq,
+            timestamp: Local::now().to_rfc3339_opts(SecondsFormat::Millis, false),
+            user: String::from(user),
+            peer: String::from(peer),
+            command: String::from(command),
+            outcome: String::from(outcome),
+            prev_hash: chain.last_hash.clone(),
+            hash: String::new(),
+        };
+        record.hash = record.compute_hash(&self.key);
+
+        let mut line = serde_json::to_string(&record).unwrap();
+        line.push('\n');
+
+        let written = chain
+            .file
+            .write_all(line.as_bytes())
+            .and_then(|_| chain.file.sync_data());
+
+        if let Err(e) = written {
+            chain.failure = Some(e.to_string());
+            return Err(e);
+        }
+
+        chain.next_seq += 1;
+        chain.last_hash = record.hash;
+
+        return Ok(());
+    }
+}
+
+fn unwritable(failure: &str) -> io::Error {
+    return io::Error::other(format!(
+        "Audit log unwritable since an earlier write failed ({}). Restart the server",
+        failure
+    ));
+}
+
+/// Last record of an intact audit log
+#[derive(Debug, Clone, PartialEq)]
+pub struct ChainHead {
+    pub records: u64,
+    pub hash: String,
+}
+
+/// Read the audit key. A key shared with the log's directory would be copied, backed up and
+/// exposed along with the log, see `check_key_location`.
+pub fn load_key(path: &Path) -> io::Result<Vec<u8>> {
+    let key = std::fs::read(path).map_err(|e| {
+        io::Error::new(
+            e.kind(),
+            format!("Failed to read audit key [{}]: {}", path.display(), e),
+        )
+    })?;
+
+    if key.len() < MIN_KEY_LEN {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!(
+                "Audit key [{}] is {} byte(s), it must be at least {}",
+                path.display(),
+                key.len(),
+                MIN_KEY_LEN
+            ),
+        ));
+    }
+
+    return Ok(key);
+}
+
+/// Fail if `key_file` is in the directory of the audit log at `log`, or below it
+fn check_key_location(key_file: &Path, log: &Path) -> io::Result<()> {
+    let log_dir = match log.parent() {
+        Some(dir) if !dir.as_os_str().is_empty() => dir,
+        _ => Path::new("."),
+    };
+
+    let (key_file, log_dir) = match (key_file.canonicalize(), log_dir.canonicalize()) {
+        (Ok(key_file), Ok(log_dir)) => (key_file, log_dir),
+        // Reported when the key is read or the log opened
+        _ => return Ok(()),
+    };
+
+    if key_file.starts_with(&log_dir) {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!(
+                "Audit key [{}] must be kept outside the audit log's directory [{}]",
+                key_file.display(),
+                log_dir.display()
+            ),
+        ));
+    }
+
+    return Ok(());
+}
+
+/// Check every record of the audit log at `path` against its hash, keyed with `key`, and the one
+/// before it
+pub fn verify(path: &Path, key: &[u8]) -> Result<ChainHead, String> {
+    let contents = std::fs::read_to_string(path)
+        .map_err(|e| format!("Failed to read audit log [{}]: {}", path.display(), e))?;
+
+    let mut head = ChainHead {
+        records: 0,
+        hash: String::from(GENESIS_HASH),
+    };
+
+    for (i, line) in contents.split_inclusive('\n').enumerate() {
+        let line_no = i + 1;
+
+        let line = match line.strip_suffix('\n') {
+            Some(line) => line,
+            None => {
+                return Err(format!(
+                    "Line {}: partial record, left by a crash while appending. The server drops it \
+                     when it next opens the log",
+                    line_no
+                ))
+            }
+        };
+
+        let record: AuditRecord = serde_json::from_str(line)
+            .map_err(|e| format!("Line {}: invalid record: {}", line_no, e))?;
+
+        if record.seq != head.records + 1 {
+            return Err(format!(
+                "Line {}: expected record {}, found record {}",
+                line_no,
+                head.records + 1,
+                record.seq
+            ));
+        }
+
+        if record.prev_hash != head.hash {
+            return Err(format!(
+                "Line {}: chain broken, previous hash doesn't match record {}",
+                line_no, head.records
+            ));
+        }
+
+        if record.compute_hash(key) != record.hash {
+            return Err(format!(
+                "Line {}: record {} doesn't match its hash",
+                line_no, record.seq
+            ));
+        }
+
+        head = ChainHead {
+            records: record.seq,
+            hash: record.hash,
+        };
+    }
+
+    return Ok(head);
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    use super::*;
+
+    /// Directories for a log and its key, removed when dropped
+    struct Dirs {
+        root: PathBuf,
+    }
+
+    impl Dirs {
+        fn new() -> Dirs {
+            static NEXT: AtomicUsize = AtomicUsize::new(0);
+
+            let root = std::env::temp_dir().join(format!(
+                "squeef-audit-{}-{}",
+                std::process::id(),
+                NEXT.fetch_add(1, Ordering::SeqCst)
+            ));
+            std::fs::create_dir_all(root.join("log")).unwrap();
+            std::fs::create_dir_all(root.join("key")).unwrap();
+            std::fs::write(root.join("key/audit.key"), [7u8; MIN_KEY_LEN]).unwrap();
+
+            return Dirs { root };
+        }
+
+        fn log(&self) -> PathBuf {
+            return self.root.join("log/audit.log");
+        }
+
+        fn key_file(&self) -> PathBuf {
+            return self.root.join("key/audit.key");
+        }
+
+        fn open(&self) -> AuditLog {
+            return AuditLog::open(self.log(), &self.key_file()).unwrap();
+        }
+
+        fn verify(&self) -> Result<ChainHead, String> {
+            return verify(&self.log(), &load_key(&self.key_file()).unwrap());
+        }
+
+        fn lines(&self) -> Vec<String> {
+            return std::fs::read_to_string(self.log())
+                .unwrap()
+                .lines()
+                .map(String::from)
+                .collect();
+        }
+
+        fn write_lines(&self, lines: &[String]) -> () {
+            std::fs::write(self.log(), lines.join("\n") + "\n").unwrap();
+        }
+    }
+
+    impl Drop for Dirs {
+        fn drop(&mut self) {
+            let _ = std::fs::remove_dir_all(&self.root);
+        }
+    }
+
+    fn append_three(log: &AuditLog) -> () {
+        for command in ["CREATE DATABASE a", "CREATE DATABASE b", "DROP DATABASE a"] {
+            log.append("admin", "127.0.0.1:1234", command, "ok")
+                .unwrap();
+        }
+    }
+
+    #[test]
+    fn verifies_intact_chain() {
+        let dirs = Dirs::new();
+        append_three(&dirs.open());
+
+        let head = dirs.verify().unwrap();
+        assert_eq!(head.records, 3);
+
+        let last: AuditRecord = serde_json::from_str(&dirs.lines()[2]).unwrap();
+        assert_eq!(head.hash, last.hash);
+    }
+
+    #[test]
+    fn continues_chain_when_reopened() {
+        let dirs = Dirs::new();
+        append_three(&dirs.open());
+        append_three(&dirs.open());
+
+        assert_eq!(dirs.verify().unwrap().records, 6);
+    }
+
+    #[test]
+    fn refuses_appends_after_failed_write() {
+        let dirs = Dirs::new();
+        let log = dirs.open();
+        append_three(&log);
+        log.check_writable().unwrap();
+
+        // Writes through a read-only handle fail
+        log.chain.lock().unwrap().file = File::open(dirs.log()).unwrap();
+        assert!(log
+            .append("admin", "127.0.0.1:1234", "DROP DATABASE b", "ok")
+            .is_err());
+
+        // Even once they would succeed again
+        log.chain.lock().unwrap().file = OpenOptions::new().append(true).open(dirs.log()).unwrap();
+        let e = log
+            .append("admin", "127.0.0.1:1234", "DROP DATABASE b", "ok")
+            .unwrap_err();
+        assert!(e.to_string().contains("Restart the server"), "{}", e);
+        assert!(log.check_writable().is_err());
+
+        assert_eq!(dirs.verify().unwrap().records, 3);
+    }
+
+    #[test]
+    fn detects_edited_record() {
+        let dirs = Dirs::new();
+        append_three(&dirs.open());
+
+        let mut lines = dirs.lines();
+        lines[1] = lines[1].replace("CREATE DATABASE b", "CREATE DATABASE c");
+        dirs.write_lines(&lines);
+
+        assert_eq!(
+            dirs.verify(),
+            Err(String::from("Line 2: record 2 doesn't match its hash"))
+        );
+    }
+
+    #[test]
+    fn detects_rehashed_record_without_key() {
+        let dirs = Dirs::new();
+        append_three(&dirs.open());
+
+        // Rewriting the chain from the edited record on needs the key
+        let mut lines = dirs.lines();
+        let mut record: AuditRecord = serde_json::from_str(&lines[2]).unwrap();
+        record.command = String::from("DROP DATABASE b");
+        record.hash = record.compute_hash(&[0u8; MIN_KEY_LEN]);
+        lines[2] = serde_json::to_string(&record).unwrap();
+        dirs.write_lines(&lines);
+
+        assert!(dirs.verify().is_err());
+    }
+
+    #[test]
+    fn detects_removed_record() {
+        let dirs = Dirs::new();
+        append_three(&dirs.open());
+
+        let mut lines = dirs.lines();
+        lines.remove(1);
+        dirs.write_lines(&lines);
+
+        assert_eq!(
+            dirs.verify(),
+            Err(String::from("Line 2: expected record 2, found record 3"))
+        );
+    }
+
+    #[test]
+    fn detects_reordered_records() {
+        let dirs = Dirs::new();
+        append_three(&dirs.open());
+
+        let mut lines = dirs.lines();
+        lines.swap(0, 1);
+        dirs.write_lines(&lines);
+
+        assert!(dirs.verify().is_err());
+    }
+
+    #[test]
+    fn drops_partial_last_record() {
+        let dirs = Dirs::new();
+        append_three(&dirs.open());
+
+        let mut file = OpenOptions::new().append(true).open(dirs.log()).unwrap();
+        file.write_all(b"{\"seq\":4,\"time").unwrap();
+        assert!(dirs.verify().unwrap_err().contains("partial record"));
+
+        let log = dirs.open();
+        assert_eq!(log.truncated(), 14);
+        log.append("admin", "127.0.0.1:1234", "CREATE DATABASE c", "ok")
+            .unwrap();
+
+        assert_eq!(dirs.verify().unwrap().records, 4);
+    }
+
+    #[test]
+    fn rejects_key_next_to_log() {
+        let dirs = Dirs::new();
+        let key_file = dirs.root.join("log/audit.key");
+        std::fs::copy(dirs.key_file(), &key_file).unwrap();
+
+        let e = AuditLog::open(dirs.log(), &key_file).unwrap_err();
+        assert!(e.to_string().contains("outside the audit log's directory"));
+    }
+
+    #[test]
+    fn rejects_short_key() {
+        let dirs = Dirs::new();
+        std::fs::write(dirs.key_file(), b"short").unwrap();
+
+        let e = AuditLog::open(dirs.log(), &dirs.key_file()).unwrap_err();
+        assert_eq!(e.kind(), io::ErrorKind::InvalidData);
+    }
+}
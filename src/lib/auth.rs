@@ -0,0 +1,330 @@
+use std::collections::HashMap;
+
+use hmac::{Hmac, Mac};
+use sha2::{Digest, Sha256};
+
+use crate::error::{Error, ErrorCode};
+
+type HmacSha256 = Hmac<Sha256>;
+
+/// PBKDF2 rounds used for new passwords
+pub const ITERATIONS: u32 = 100_000;
+
+const SALT_LEN: usize = 16;
+
+const NONCE_LEN: usize = 32;
+
+/// What the server keeps to check a password, without being able to recover it or to log in with
+/// it. Computed by the client, so passwords never leave it.
+///
+/// The scheme follows SCRAM-SHA-256: `client_key = HMAC(PBKDF2(password, salt), "Client Key")` and
+/// `stored_key = SHA256(client_key)`. To log in, the client proves it knows `client_key` by
+/// sending `client_key XOR HMAC(stored_key, nonce)` for a nonce picked by the server.
+#[derive(Clone, PartialEq)]
+pub struct Credentials {
+    pub salt: Vec<u8>,
+    pub iterations: u32,
+    pub stored_key: Vec<u8>,
+}
+
+impl Credentials {
+    /// Credentials for `password`, with a random salt
+    pub fn new(password: &str) -> Credentials {
+        let salt = random_bytes(SALT_LEN);
+        let client_key = client_key(password, &salt, ITERATIONS);
+
+        Credentials {
+            salt,
+            iterations: ITERATIONS,
+            stored_key: Sha256::digest(&client_key).to_vec(),
+        }
+    }
+
+    /// Whether `proof` was computed from the right password for `nonce`
+    pub fn verify(&self, nonce: &[u8], proof: &[u8]) -> bool {
+        let signature = signature(&self.stored_key, nonce);
+
+        if proof.len() != signature.len() {
+            return false;
+        }
+
+        let client_key: Vec<u8> = proof.iter().zip(&signature).map(|(p, s)| p ^ s).collect();
+
+        return constant_time_eq(&Sha256::digest(&client_key), &self.stored_key);
+    }
+}
+
+// A stored key is enough to attempt cracking the password offline, so it's kept out of logs
+impl std::fmt::Debug for Credentials {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        return f
+            .debug_struct("Credentials")
+            .field("iterations", &self.iterations)
+            .finish_non_exhaustive();
+    }
+}
+
+/// Challenge sent by the server in answer to `Command::Authenticate`
+#[derive(Debug, Clone, PartialEq)]
+pub struct Challenge {
+    pub salt: Vec<u8>,
+    pub iterations: u32,
+    pub nonce: Vec<u8>,
+}
+
+/// Answer to `challenge` proving the client knows `password`
+pub fn client_proof(password: &str, challenge: &Challenge) -> Vec<u8> {
+    let client_key = client_key(password, &challenge.salt, challenge.iterations);
+    let stored_key = Sha256::digest(&client_key);
+    let signature = signature(&stored_key, &challenge.nonce);
+
+    return client_key
+        .iter()
+        .zip(&signature)
+        .map(|(k, s)| k ^ s)
+        .collect();
+}
+
+fn client_key(password: &str, salt: &[u8], iterations: u32) -> Vec<u8> {
+    let mut salted = [0; 32];
+    pbkdf2::pbkdf2_hmac::<Sha256>(password.as_bytes(), salt, iterations, &mut salted);
+
+    return hmac(&salted, b"Client Key");
+}
+
+fn signature(stored_key: &[u8], nonce: &[u8]) -> Vec<u8> {
+    return hmac(stored_key, nonce);
+}
+
+fn hmac(key: &[u8], data: &[u8]) -> Vec<u8> {
+    let mut mac = HmacSha256::new_from_slice(key).unwrap();
+    mac.update(data);
+
+    return mac.finalize().into_bytes().to_vec();
+}
+
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    return a.len() == b.len() && a.iter().zip(b).fold(0, |acc, (x, y)| acc | (x ^ y)) == 0;
+}
+
+fn random_bytes(len: usize) -> Vec<u8> {
+    let mut bytes = vec![0; len];
+    getrandom::getrandom(&mut bytes).expect("No randomness available from the OS");
+
+    return bytes;
+}
+
+#[derive(Debug)]
+struct User {
+    credentials: Credentials,
+
+    /// Holds every privilege, and manages users, roles and grants
+    superuser: bool,
+}
+
+/// Catalog of the users allowed to connect
+#[derive(Debug)]
+pub struct Users {
+    users: HashMap<String, User>,
+
+    /// Key deriving the salt of users which don't exist, so that a challenge doesn't tell whether
+    /// a user exists
+    decoy_key: Vec<u8>,
+}
+
+impl Users {
+    pub fn new() -> Users {
+        Users {
+            users: HashMap::new(),
+            decoy_key: random_bytes(32),
+        }
+    }
+
+    pub fn create(
+        &mut self,
+        name: String,
+        credentials: Credentials,
+        superuser: bool,
+    ) -> Result<(), Error> {
+        if self.users.contains_key(&name) {
+            return Err(Error::from(format!(
+                "Failed to create user. Name [{}] already in use",
+                name
+            )));
+        }
+
+        self.users.insert(
+            name,
+            User {
+                credentials,
+                superuser,
+            },
+        );
+
+        return Ok(());
+    }
+
+    pub fn alter(&mut self, name: &str, credentials: Credentials) -> Result<(), Error> {
+        match self.users.get_mut(name) {
+            Some(user) => user.credentials = credentials,
+            None => return Err(Error::from(format!("No user with name [{}]", name))),
+        }
+
+        return Ok(());
+    }
+
+    pub fn remove(&mut self, name: &str) -> Result<(), Error> {
+        match self.users.remove(name) {
+            Some(_) => return Ok(()),
+            None => return Err(Error::from(format!("No user with name [{}]", name))),
+        }
+    }
+
+    pub fn exists(&self, name: &str) -> bool {
+        return self.users.contains_key(name);
+    }
+
+    pub fn is_superuser(&self, name: &str) -> bool {
+        return self.users.get(name).is_some_and(|user| user.superuser);
+    }
+
+    /// Challenge for `name` to answer, along with the credentials to check the answer against.
+    /// Users which don't exist get a challenge which looks the same but can't be answered.
+    pub fn challenge(&self, name: &str) -> (Challenge, Option<Credentials>) {
+        let nonce = random_bytes(NONCE_LEN);
+
+        return match self.users.get(name) {
+            Some(user) => (
+                Challenge {
+                    salt: user.credentials.salt.clone(),
+                    iterations: user.credentials.iterations,
+                    nonce,
+                },
+                Some(user.credentials.clone()),
+            ),
+            None => (
+                Challenge {
+                    salt: hmac(&self.decoy_key, name.as_bytes())[..SALT_LEN].to_vec(),
+                    iterations: ITERATIONS,
+                    nonce,
+                },
+                None,
+            ),
+        };
+    }
+}
+
+impl Default for Users {
+    fn default() -> Users {
+        Users::new()
+    }
+}
+
+/// Error for every failed login, whatever the reason, so a client can't tell which users exist
+pub fn authentication_failed() -> Error {
+    return Error::new(
+        ErrorCode::AuthenticationFailed,
+        String::from("Authentication failed"),
+    );
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Fewer rounds than `ITERATIONS`, to keep the tests fast
+    const TEST_ITERATIONS: u32 = 1000;
+
+    fn credentials(password: &str) -> Credentials {
+        let salt = random_bytes(SALT_LEN);
+        let client_key = client_key(password, &salt, TEST_ITERATIONS);
+
+        return Credentials {
+            salt,
+            iterations: TEST_ITERATIONS,
+            stored_key: Sha256::digest(&client_key).to_vec(),
+        };
+    }
+
+    fn challenge(credentials: &Credentials) -> Challenge {
+        return Challenge {
+            salt: credentials.salt.clone(),
+            iterations: credentials.iterations,
+            nonce: random_bytes(NONCE_LEN),
+        };
+    }
+
+    #[test]
+    fn verify_accepts_only_the_right_password() {
+        let credentials = credentials("secret");
+        let challenge = challenge(&credentials);
+
+        let proof = client_proof("secret", &challenge);
+        assert!(credentials.verify(&challenge.nonce, &proof));
+
+        let wrong = client_proof("Secret", &challenge);
+        assert!(!credentials.verify(&challenge.nonce, &wrong));
+    }
+
+    #[test]
+    fn proofs_only_answer_their_nonce() {
+        let credentials = credentials("secret");
+        let proof = client_proof("secret", &challenge(&credentials));
+
+        // A proof seen on the wire can't be replayed against a new challenge
+        assert!(!credentials.verify(&challenge(&credentials).nonce, &proof));
+    }
+
+    #[test]
+    fn verify_rejects_malformed_proofs() {
+        let credentials = credentials("secret");
+        let challenge = challenge(&credentials);
+        let proof = client_proof("secret", &challenge);
+
+        assert!(!credentials.verify(&challenge.nonce, &[]));
+        assert!(!credentials.verify(&challenge.nonce, &proof[1..]));
+        assert!(!credentials.verify(&challenge.nonce, &[proof.as_slice(), &[0]].concat()));
+    }
+
+    #[test]
+    fn unknown_users_get_a_decoy_challenge() {
+        let mut users = Users::new();
+        users
+            .create(String::from("alice"), credentials("secret"), false)
+            .unwrap();
+
+        let (real, real_credentials) = users.challenge("alice");
+        let (decoy, decoy_credentials) = users.challenge("mallory");
+        assert!(real_credentials.is_some());
+        assert!(decoy_credentials.is_none());
+
+        // Same shape as a real challenge, and the same salt every time, like a real user's
+        assert_eq!(decoy.salt.len(), real.salt.len());
+        assert_eq!(decoy.nonce.len(), real.nonce.len());
+        assert_eq!(decoy.iterations, ITERATIONS);
+        assert_eq!(users.challenge("mallory").0.salt, decoy.salt);
+        assert_ne!(users.challenge("eve").0.salt, decoy.salt);
+        assert_ne!(users.challenge("mallory").0.nonce, decoy.nonce);
+    }
+
+    #[test]
+    fn constant_time_eq_compares_contents_and_lengths() {
+        assert!(constant_time_eq(b"", b""));
+        assert!(constant_time_eq(b"abc", b"abc"));
+        assert!(!constant_time_eq(b"abc", b"abd"));
+        assert!(!constant_time_eq(b"abc", b"ab"));
+        assert!(!constant_time_eq(b"", b"a"));
+    }
+
+    #[test]
+    fn debug_hides_salt_and_stored_key() {
+        let credentials = Credentials {
+            salt: vec![0xab; SALT_LEN],
+            iterations: TEST_ITERATIONS,
+            stored_key: vec![0xcd; 32],
+        };
+
+        let debug = format!("{:?}", credentials);
+        assert_eq!(debug, "Credentials { iterations: 1000, .. }");
+    }
+}
@@ -0,0 +1,227 @@
+use std::io;
+use std::path::Path;
+
+use ring::aead::{Aad, LessSafeKey, Nonce, UnboundKey, AES_256_GCM, NONCE_LEN};
+
+/// Length of the storage encryption key, in bytes
+pub const KEY_LEN: usize = 32;
+
+/// File under the persistent storage directory telling whether the key is the one its data was
+/// encrypted with
+pub const KEY_CHECK_FILE: &str = "key_check";
+
+/// Plaintext of the key check file
+const KEY_CHECK: &[u8] = b"squeef storage key check";
+
+/// AES-256-GCM, used to encrypt everything written under the persistent storage directory.
+/// Every message gets a random nonce, stored in front of its ciphertext, and is bound to a
+/// context such as the file and offset it belongs to, so that it can't be moved elsewhere.
+pub struct Cipher {
+    key: LessSafeKey,
+}
+
+// The key is left out, like credentials
+impl std::fmt::Debug for Cipher {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        return f.debug_struct("Cipher").finish_non_exhaustive();
+    }
+}
+
+impl Cipher {
+    pub fn new(key: &[u8]) -> Result<Cipher, String> {
+        if key.len() != KEY_LEN {
+            return Err(format!(
+                "Encryption key is {} byte(s), it must be {}",
+                key.len(),
+                KEY_LEN
+            ));
+        }
+
+        let key = UnboundKey::new(&AES_256_GCM, key).unwrap();
+
+        return Ok(Cipher {
+            key: LessSafeKey::new(key),
+        });
+    }
+
+    /// Read the key in `path`, which holds exactly `KEY_LEN` bytes
+    pub fn load(path: &Path) -> io::Result<Cipher> {
+        let key = std::fs::read(path).map_err(|e| {
+            io::Error::new(
+                e.kind(),
+                format!("Failed to read encryption key [{}]: {}", path.display(), e),
+            )
+        })?;
+
+        return Cipher::new(&key).map_err(|e| {
+            io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("{} [{}]", e, path.display()),
+            )
+        });
+    }
+
+    /// Nonce, then the ciphertext of `plaintext` and its tag
+    pub fn encrypt(&self, plaintext: &[u8], context: &[u8]) -> Vec<u8> {
+        let mut nonce = [0; NONCE_LEN];
+        getrandom::getrandom(&mut nonce).expect("No randomness available from the OS");
+
+        let mut sealed = plaintext.to_vec();
+        self.key
+            .seal_in_place_append_tag(
+                Nonce::assume_unique_for_key(nonce),
+                Aad::from(context),
+                &mut sealed,
+            )
+            .unwrap();
+
+        let mut bytes = nonce.to_vec();
+        bytes.extend(sealed);
+
+        return bytes;
+    }
+
+    /// Plaintext of `bytes`, from `encrypt` with the same key and `context`. Fails if they were
+    /// changed.
+    pub fn decrypt(&self, bytes: &[u8], context: &[u8]) -> Result<Vec<u8>, String> {
+        if bytes.len() < NONCE_LEN + AES_256_GCM.tag_len() {
+            return Err(format!(
+                "Encrypted data is {} byte(s), too short to hold a nonce and tag",
+                bytes.len()
+            ));
+        }
+
+        let (nonce, sealed) = bytes.split_at(NONCE_LEN);
+        let nonce = Nonce::try_assume_unique_for_key(nonce).unwrap();
+
+        let mut sealed = sealed.to_vec();
+        let plaintext = self
+            .key
+            .open_in_place(nonce, Aad::from(context), &mut sealed)
+            .map_err(|_| String::from("Decryption failed. Wrong key, or altered data"))?;
+
+        return Ok(plaintext.to_vec());
+    }
+
+    /// Check the key against the key check file in the persistent storage directory `dir`,
+    /// written with the first key used there. Fails if it's a different key.
+    pub fn check_key(&self, dir: &Path) -> io::Result<()> {
+        let path = dir.join(KEY_CHECK_FILE);
+
+        let check = match std::fs::read(&path) {
+            Ok(check) => check,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => {
+                std::fs::create_dir_all(dir)?;
+                return std::fs::write(&path, self.encrypt(KEY_CHECK, KEY_CHECK_FILE.as_bytes()));
+            }
+            Err(e) => {
+                return Err(io::Error::new(
+                    e.kind(),
+                    format!("Failed to read key check [{}]: {}", path.display(), e),
+                ))
+            }
+        };
+
+        match self.decrypt(&check, KEY_CHECK_FILE.as_bytes()) {
+            Ok(plaintext) if plaintext == KEY_CHECK => return Ok(()),
+            _ => {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!(
+                        "Wrong encryption key. [{}] was encrypted with a different one",
+                        dir.display()
+                    ),
+                ))
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::path::PathBuf;
+
+    fn cipher(byte: u8) -> Cipher {
+        return Cipher::new(&[byte; KEY_LEN]).unwrap();
+    }
+
+    fn storage_dir(name: &str) -> PathBuf {
+        let dir =
+            std::env::temp_dir().join(format!("squeef-cipher-{}-{}", name, std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+
+        return dir;
+    }
+
+    #[test]
+    fn round_trips() {
+        let cipher = cipher(1);
+        let encrypted = cipher.encrypt(b"page", b"orders:0");
+
+        assert_ne!(&encrypted[NONCE_LEN..NONCE_LEN + 4], b"page");
+        assert_eq!(
+            cipher.decrypt(&encrypted, b"orders:0"),
+            Ok(b"page".to_vec())
+        );
+
+        // Random nonces, so the same plaintext doesn't give away that it's repeated
+        assert_ne!(cipher.encrypt(b"page", b"orders:0"), encrypted);
+    }
+
+    #[test]
+    fn detects_wrong_key_context_and_tampering() {
+        let encrypted = cipher(1).encrypt(b"page", b"orders:0");
+
+        assert!(cipher(2).decrypt(&encrypted, b"orders:0").is_err());
+        assert!(cipher(1).decrypt(&encrypted, b"orders:1").is_err());
+
+        let mut tampered = encrypted.clone();
+        tampered[NONCE_LEN] ^= 1;
+        assert!(cipher(1).decrypt(&tampered, b"orders:0").is_err());
+
+        assert!(cipher(1)
+            .decrypt(&encrypted[..NONCE_LEN], b"orders:0")
+            .is_err());
+    }
+
+    #[test]
+    fn rejects_keys_of_the_wrong_length() {
+        assert!(Cipher::new(&[0; KEY_LEN - 1]).is_err());
+        assert!(Cipher::new(&[0; KEY_LEN + 1]).is_err());
+    }
+
+    #[test]
+    fn loads_the_key_file() {
+        let dir = storage_dir("load");
+        std::fs::create_dir_all(&dir).unwrap();
+
+        let path = dir.join("storage.key");
+        std::fs::write(&path, [1; KEY_LEN]).unwrap();
+        let encrypted = Cipher::load(&path).unwrap().encrypt(b"page", b"");
+        assert_eq!(cipher(1).decrypt(&encrypted, b""), Ok(b"page".to_vec()));
+
+        std::fs::write(&path, "too short\n").unwrap();
+        let e = Cipher::load(&path).unwrap_err();
+        assert!(e.to_string().contains("it must be 32"), "{}", e);
+
+        assert!(Cipher::load(&dir.join("missing.key")).is_err());
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn checks_the_key_against_the_storage_dir() {
+        let dir = storage_dir("check");
+
+        cipher(1).check_key(&dir).unwrap();
+        assert!(dir.join(KEY_CHECK_FILE).exists());
+        cipher(1).check_key(&dir).unwrap();
+
+        let e = cipher(2).check_key(&dir).unwrap_err();
+        assert!(e.to_string().contains("Wrong encryption key"), "{}", e);
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
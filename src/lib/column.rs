@@ -1,18 +1,143 @@
-#[derive(Debug)]
+#[derive(Debug, Clone, PartialEq)]
 pub struct Column {
-    _column_type: ColumnType,
-    _is_optional: bool,
-    _is_primary_key: bool,
-    _is_foreign_key: bool,
+    pub name: String,
+    pub column_type: ColumnType,
+    pub is_optional: bool,
+    pub is_primary_key: bool,
+
+    /// Part of a foreign key, kept up to date by `Table`
+    pub is_foreign_key: bool,
+
+    /// Value the column is set to when a row doesn't give one
+    pub default: Option<DefaultValue>,
+}
+
+impl Column {
+    /// Fail if the default isn't a value of the column's type
+    pub fn check_default(&self) -> Result<(), String> {
+        let ok = match &self.default {
+            None => true,
+            Some(DefaultValue::Literal(literal)) => self.column_type.accepts(literal),
+            Some(DefaultValue::Now) => self.column_type == ColumnType::STRING,
+        };
+
+        if !ok {
+            return Err(format!(
+                "Default [{}] of column [{}] isn't a {}",
+                self.default.as_ref().unwrap().describe(),
+                self.name,
+                self.column_type.name()
+            ));
+        }
+
+        return Ok(());
+    }
 }
 
-#[derive(Debug)]
-enum ColumnType {
-    _UINT8,
-    _SINT8,
-    _UINT32,
-    _SINT32,
-    _FLOAT32,
-    _FLOAT64,
-    _STRING,
+#[derive(Debug, Clone, PartialEq)]
+pub enum DefaultValue {
+    Literal(String),
+
+    /// Time the row is written, as an RFC 3339 string, so only for STRING columns
+    Now,
+}
+
+impl DefaultValue {
+    pub fn describe(&self) -> String {
+        return match self {
+            DefaultValue::Literal(literal) => literal.clone(),
+            DefaultValue::Now => String::from("now()"),
+        };
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ColumnType {
+    UINT8,
+    SINT8,
+    UINT32,
+    SINT32,
+    FLOAT32,
+    FLOAT64,
+    STRING,
+}
+
+impl ColumnType {
+    pub const ALL: [ColumnType; 7] = [
+        ColumnType::UINT8,
+        ColumnType::SINT8,
+        ColumnType::UINT32,
+        ColumnType::SINT32,
+        ColumnType::FLOAT32,
+        ColumnType::FLOAT64,
+        ColumnType::STRING,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        return match self {
+            ColumnType::UINT8 => "UINT8",
+            ColumnType::SINT8 => "SINT8",
+            ColumnType::UINT32 => "UINT32",
+            ColumnType::SINT32 => "SINT32",
+            ColumnType::FLOAT32 => "FLOAT32",
+            ColumnType::FLOAT64 => "FLOAT64",
+            ColumnType::STRING => "STRING",
+        };
+    }
+
+    pub fn from_name(name: &str) -> Option<ColumnType> {
+        return ColumnType::ALL
+            .into_iter()
+            .find(|t| t.name().eq_ignore_ascii_case(name));
+    }
+
+    /// Whether `literal` is a value of this type
+    pub fn accepts(&self, literal: &str) -> bool {
+        return match self {
+            ColumnType::UINT8 => literal.parse::<u8>().is_ok(),
+            ColumnType::SINT8 => literal.parse::<i8>().is_ok(),
+            ColumnType::UINT32 => literal.parse::<u32>().is_ok(),
+            ColumnType::SINT32 => literal.parse::<i32>().is_ok(),
+            ColumnType::FLOAT32 => literal.parse::<f32>().is_ok(),
+            ColumnType::FLOAT64 => literal.parse::<f64>().is_ok(),
+            ColumnType::STRING => true,
+        };
+    }
+
+    /// Whether every value of this type converts to `other` without losing anything
+    pub fn converts_to(&self, other: ColumnType) -> bool {
+        return match (self, other) {
+            (from, to) if *from == to => true,
+            (_, ColumnType::STRING) => true,
+            (
+                ColumnType::UINT8,
+                ColumnType::UINT32 | ColumnType::SINT32 | ColumnType::FLOAT32 | ColumnType::FLOAT64,
+            ) => true,
+            (ColumnType::SINT8, ColumnType::SINT32 | ColumnType::FLOAT32 | ColumnType::FLOAT64) => {
+                true
+            }
+            (
+                ColumnType::UINT32 | ColumnType::SINT32 | ColumnType::FLOAT32,
+                ColumnType::FLOAT64,
+            ) => true,
+            _ => false,
+        };
+    }
+
+    /// Tag of the type on the wire
+    pub fn tag(&self) -> u8 {
+        return match self {
+            ColumnType::UINT8 => 0x00,
+            ColumnType::SINT8 => 0x01,
+            ColumnType::UINT32 => 0x02,
+            ColumnType::SINT32 => 0x03,
+            ColumnType::FLOAT32 => 0x04,
+            ColumnType::FLOAT64 => 0x05,
+            ColumnType::STRING => 0x06,
+        };
+    }
+
+    pub fn from_tag(tag: u8) -> Option<ColumnType> {
+        return ColumnType::ALL.into_iter().find(|t| t.tag() == tag);
+    }
 }
@@ -1,10 +1,203 @@
-use crate::column::Column;
+use crate::acl::{GrantOn, Privilege};
+use crate::auth::Credentials;
+use crate::column::{Column, ColumnType, DefaultValue};
+use crate::constraint::Constraint;
+use crate::lock::LockMode;
+use crate::policy::Policy;
+use crate::transaction::IsolationLevel;
 
 #[derive(Debug)]
 pub enum Command {
-    CreateDatabase { name: String },
-    CreateTable { name: String, cols: Vec<Column> },
-    OpenDatabase { name: String },
+    CreateDatabase {
+        name: String,
+    },
+    CreateTable {
+        name: String,
+        cols: Vec<Column>,
+        constraints: Vec<Constraint>,
+    },
+    /// Without `cascade`, only a database without tables can be dropped
+    DropDatabase {
+        name: String,
+        if_exists: bool,
+        cascade: bool,
+    },
+    /// Without `cascade`, only a table no other table references can be dropped. With it, the
+    /// foreign keys referencing it are dropped too.
+    DropTable {
+        name: String,
+        if_exists: bool,
+        cascade: bool,
+    },
+    RenameDatabase {
+        name: String,
+        new_name: String,
+    },
+    RenameTable {
+        name: String,
+        new_name: String,
+    },
+    DescribeTable {
+        name: String,
+    },
+    AlterTable {
+        name: String,
+        action: AlterTableAction,
+    },
+    CreatePolicy {
+        table: String,
+        policy: Policy,
+    },
+    DropPolicy {
+        table: String,
+        name: String,
+    },
+    OpenDatabase {
+        name: String,
+    },
     ListDatabases,
     ListTables,
+    SetClientName {
+        name: String,
+    },
+    Begin,
+    Commit,
+    Rollback,
+    SetIsolationLevel {
+        level: IsolationLevel,
+    },
+    LockTable {
+        name: String,
+        mode: LockMode,
+        nowait: bool,
+    },
+    Savepoint {
+        name: String,
+    },
+    ReleaseSavepoint {
+        name: String,
+    },
+    RollbackToSavepoint {
+        name: String,
+    },
+    /// First step of logging in, answered with a challenge
+    Authenticate {
+        user: String,
+    },
+    /// Answer to the challenge, see `auth::client_proof`
+    AuthProof {
+        proof: Vec<u8>,
+    },
+    CreateUser {
+        name: String,
+        credentials: Credentials,
+    },
+    AlterUser {
+        name: String,
+        credentials: Credentials,
+    },
+    DropUser {
+        name: String,
+    },
+    CreateRole {
+        name: String,
+    },
+    DropRole {
+        name: String,
+    },
+    /// Make `user` a member of `role`
+    GrantRole {
+        role: String,
+        user: String,
+    },
+    RevokeRole {
+        role: String,
+        user: String,
+    },
+    Grant {
+        privileges: Vec<Privilege>,
+        on: GrantOn,
+        role: String,
+    },
+    Revoke {
+        privileges: Vec<Privilege>,
+        on: GrantOn,
+        role: String,
+    },
+}
+
+/// Change to the columns of a table made by ALTER TABLE
+#[derive(Debug)]
+pub enum AlterTableAction {
+    AddColumn {
+        column: Column,
+        constraints: Vec<Constraint>,
+    },
+    DropColumn {
+        name: String,
+    },
+    RenameColumn {
+        name: String,
+        new_name: String,
+    },
+    AlterColumnType {
+        name: String,
+        column_type: ColumnType,
+    },
+    /// `None` drops the default
+    SetDefault {
+        name: String,
+        default: Option<DefaultValue>,
+    },
+    SetNotNull {
+        name: String,
+        not_null: bool,
+    },
+    AddConstraint {
+        constraint: Constraint,
+    },
+    DropConstraint {
+        name: String,
+    },
+}
+
+impl Command {
+    /// Name of the command in metrics, e.g. `create_database`
+    pub fn name(&self) -> &'static str {
+        return match self {
+            Command::CreateDatabase { .. } => "create_database",
+            Command::CreateTable { .. } => "create_table",
+            Command::DropDatabase { .. } => "drop_database",
+            Command::DropTable { .. } => "drop_table",
+            Command::RenameDatabase { .. } => "rename_database",
+            Command::RenameTable { .. } => "rename_table",
+            Command::DescribeTable { .. } => "describe_table",
+            Command::AlterTable { .. } => "alter_table",
+            Command::CreatePolicy { .. } => "create_policy",
+            Command::DropPolicy { .. } => "drop_policy",
+            Command::OpenDatabase { .. } => "open_database",
+            Command::ListDatabases => "list_databases",
+            Command::ListTables => "list_tables",
+            Command::SetClientName { .. } => "set_client_name",
+            Command::Begin => "begin",
+            Command::Commit => "commit",
+            Command::Rollback => "rollback",
+            Command::SetIsolationLevel { .. } => "set_isolation_level",
+            Command::LockTable { .. } => "lock_table",
+            Command::Savepoint { .. } => "savepoint",
+            Command::ReleaseSavepoint { .. } => "release_savepoint",
+            Command::RollbackToSavepoint { .. } => "rollback_to_savepoint",
+            Command::Authenticate { .. } => "authenticate",
+            Command::AuthProof { .. } => "auth_proof",
+            Command::CreateUser { .. } => "create_user",
+            Command::AlterUser { .. } => "alter_user",
+            Command::DropUser { .. } => "drop_user",
+            Command::CreateRole { .. } => "create_role",
+            Command::DropRole { .. } => "drop_role",
+            Command::GrantRole { .. } => "grant_role",
+            Command::RevokeRole { .. } => "revoke_role",
+            Command::Grant { .. } => "grant",
+            Command::Revoke { .. } => "revoke",
+        };
+    }
 }
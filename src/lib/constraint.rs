@@ -0,0 +1,331 @@
+use std::cmp::Ordering;
+
+use crate::column::ColumnType;
+
+/// Table constraint. NOT NULL and DEFAULT are part of the column instead.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Constraint {
+    /// Generated from the table and column names when not given, once added to a table
+    pub name: Option<String>,
+    pub kind: ConstraintKind,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum ConstraintKind {
+    /// No two rows hold the same values in all of `columns`. Rows with a NULL in one of them
+    /// never clash.
+    Unique {
+        columns: Vec<String>,
+    },
+
+    /// Rows must satisfy the check, or hold NULL in its column
+    Check(Check),
+
+    ForeignKey(ForeignKey),
+}
+
+impl ConstraintKind {
+    pub fn describe(&self) -> String {
+        return match self {
+            ConstraintKind::Unique { columns } => format!("UNIQUE ({})", columns.join(", ")),
+            ConstraintKind::Check(check) => format!("CHECK ({})", check.describe()),
+            ConstraintKind::ForeignKey(fk) => fk.describe(),
+        };
+    }
+
+    pub fn columns(&self) -> Vec<&str> {
+        return match self {
+            ConstraintKind::Unique { columns } => columns.iter().map(|c| c.as_str()).collect(),
+            ConstraintKind::Check(check) => vec![check.column.as_str()],
+            ConstraintKind::ForeignKey(fk) => fk.columns.iter().map(|c| c.as_str()).collect(),
+        };
+    }
+
+    /// Suffix of generated constraint names, after the table and column names
+    pub fn suffix(&self) -> &'static str {
+        return match self {
+            ConstraintKind::Unique { .. } => "key",
+            ConstraintKind::Check(_) => "check",
+            ConstraintKind::ForeignKey(_) => "fkey",
+        };
+    }
+}
+
+/// Comparison of a column with a literal of its type
+#[derive(Debug, Clone, PartialEq)]
+pub struct Check {
+    pub column: String,
+    pub op: CompareOp,
+    pub value: String,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum CompareOp {
+    Eq,
+    Ne,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+}
+
+impl CompareOp {
+    pub const ALL: [CompareOp; 6] = [
+        CompareOp::Eq,
+        CompareOp::Ne,
+        CompareOp::Lt,
+        CompareOp::Le,
+        CompareOp::Gt,
+        CompareOp::Ge,
+    ];
+
+    pub fn symbol(&self) -> &'static str {
+        return match self {
+            CompareOp::Eq => "=",
+            CompareOp::Ne => "<>",
+            CompareOp::Lt => "<",
+            CompareOp::Le => "<=",
+            CompareOp::Gt => ">",
+            CompareOp::Ge => ">=",
+        };
+    }
+
+    /// Whether `a op b` holds, given how `a` compares to `b`
+    pub fn holds(&self, ordering: Ordering) -> bool {
+        return match self {
+            CompareOp::Eq => ordering == Ordering::Equal,
+            CompareOp::Ne => ordering != Ordering::Equal,
+            CompareOp::Lt => ordering == Ordering::Less,
+            CompareOp::Le => ordering != Ordering::Greater,
+            CompareOp::Gt => ordering == Ordering::Greater,
+            CompareOp::Ge => ordering != Ordering::Less,
+        };
+    }
+
+    pub fn from_symbol(symbol: &str) -> Option<CompareOp> {
+        if symbol == "!=" {
+            return Some(CompareOp::Ne);
+        }
+
+        return CompareOp::ALL.into_iter().find(|op| op.symbol() == symbol);
+    }
+
+    /// Tag of the operator on the wire
+    pub fn tag(&self) -> u8 {
+        return match self {
+            CompareOp::Eq => 0x00,
+            CompareOp::Ne => 0x01,
+            CompareOp::Lt => 0x02,
+            CompareOp::Le => 0x03,
+            CompareOp::Gt => 0x04,
+            CompareOp::Ge => 0x05,
+        };
+    }
+
+    pub fn from_tag(tag: u8) -> Option<CompareOp> {
+        return CompareOp::ALL.into_iter().find(|op| op.tag() == tag);
+    }
+}
+
+impl Check {
+    /// Fail unless `value` is a literal of `column_type`, the type of the checked column
+    pub fn check_type(&self, column_type: ColumnType) -> Result<(), String> {
+        if !column_type.accepts(&self.value) {
+            return Err(format!(
+                "CHECK value [{}] isn't a {} like column [{}]",
+                self.value,
+                column_type.name(),
+                self.column
+            ));
+        }
+
+        return Ok(());
+    }
+
+    /// Whether `value` of the checked column, of `column_type`, satisfies the check. NULL always
+    /// does. Numbers compare by value and strings byte by byte.
+    pub fn holds(&self, column_type: ColumnType, value: Option<&str>) -> Result<bool, String> {
+        let value = match value {
+            Some(value) => value,
+            None => return Ok(true),
+        };
+
+        if !column_type.accepts(value) {
+            return Err(format!(
+                "Value [{}] isn't a {} like column [{}]",
+                value,
+                column_type.name(),
+                self.column
+            ));
+        }
+
+        self.check_type(column_type)?;
+
+        let ordering = match column_type {
+            ColumnType::UINT8 | ColumnType::SINT8 | ColumnType::UINT32 | ColumnType::SINT32 => {
+                // Every integer type fits in an i64
+                let value: i64 = value.parse().unwrap();
+                value.cmp(&self.value.parse().unwrap())
+            }
+            ColumnType::FLOAT32 | ColumnType::FLOAT64 => {
+                let value: f64 = value.parse().unwrap();
+                match value.partial_cmp(&self.value.parse().unwrap()) {
+                    Some(ordering) => ordering,
+                    // NaN satisfies nothing but <>
+                    None => return Ok(self.op == CompareOp::Ne),
+                }
+            }
+            ColumnType::STRING => value.cmp(self.value.as_str()),
+        };
+
+        return Ok(self.op.holds(ordering));
+    }
+
+    pub fn describe(&self) -> String {
+        return format!("{} {} {}", self.column, self.op.symbol(), self.value);
+    }
+}
+
+/// Rows must hold the values of a row of `ref_table` in `columns`, or a NULL in one of them
+#[derive(Debug, Clone, PartialEq)]
+pub struct ForeignKey {
+    pub columns: Vec<String>,
+
+    /// Table in the same database, this one included
+    pub ref_table: String,
+
+    /// Primary key or UNIQUE columns of `ref_table`, in the order of `columns`. Left empty for
+    /// the primary key, which is filled in once the referenced table is looked up.
+    pub ref_columns: Vec<String>,
+
+    /// What happens to the rows referencing a row when it's deleted
+    pub on_delete: ReferentialAction,
+
+    /// What happens to the rows referencing a row when its referenced columns change
+    pub on_update: ReferentialAction,
+}
+
+impl ForeignKey {
+    pub fn describe(&self) -> String {
+        return format!(
+            "FOREIGN KEY ({}) REFERENCES {} ({}) ON DELETE {} ON UPDATE {}",
+            self.columns.join(", "),
+            self.ref_table,
+            self.ref_columns.join(", "),
+            self.on_delete.name(),
+            self.on_update.name()
+        );
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ReferentialAction {
+    /// Fail while rows reference the row
+    Restrict,
+    /// Delete the referencing rows, or change them along with the row
+    Cascade,
+    /// Set the referencing columns of the referencing rows to NULL
+    SetNull,
+}
+
+impl ReferentialAction {
+    pub const ALL: [ReferentialAction; 3] = [
+        ReferentialAction::Restrict,
+        ReferentialAction::Cascade,
+        ReferentialAction::SetNull,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        return match self {
+            ReferentialAction::Restrict => "RESTRICT",
+            ReferentialAction::Cascade => "CASCADE",
+            ReferentialAction::SetNull => "SET NULL",
+        };
+    }
+
+    /// Tag of the action on the wire
+    pub fn tag(&self) -> u8 {
+        return match self {
+            ReferentialAction::Restrict => 0x00,
+            ReferentialAction::Cascade => 0x01,
+            ReferentialAction::SetNull => 0x02,
+        };
+    }
+
+    pub fn from_tag(tag: u8) -> Option<ReferentialAction> {
+        return ReferentialAction::ALL.into_iter().find(|a| a.tag() == tag);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn check(column: &str, op: CompareOp, value: &str) -> Check {
+        return Check {
+            column: String::from(column),
+            op,
+            value: String::from(value),
+        };
+    }
+
+    #[test]
+    fn integers_compare_by_value() {
+        let adult = check("age", CompareOp::Ge, "18");
+
+        assert_eq!(adult.holds(ColumnType::UINT8, Some("18")), Ok(true));
+        assert_eq!(adult.holds(ColumnType::UINT8, Some("120")), Ok(true));
+        assert_eq!(adult.holds(ColumnType::UINT8, Some("9")), Ok(false));
+
+        let negative = check("delta", CompareOp::Lt, "0");
+        assert_eq!(negative.holds(ColumnType::SINT32, Some("-5")), Ok(true));
+        assert_eq!(negative.holds(ColumnType::SINT32, Some("0")), Ok(false));
+    }
+
+    #[test]
+    fn floats_compare_by_value() {
+        let positive = check("price", CompareOp::Gt, "0");
+
+        assert_eq!(positive.holds(ColumnType::FLOAT64, Some("0.5")), Ok(true));
+        assert_eq!(positive.holds(ColumnType::FLOAT64, Some("-0.5")), Ok(false));
+        assert_eq!(positive.holds(ColumnType::FLOAT64, Some("NaN")), Ok(false));
+        assert_eq!(
+            check("price", CompareOp::Ne, "0").holds(ColumnType::FLOAT32, Some("NaN")),
+            Ok(true)
+        );
+    }
+
+    #[test]
+    fn strings_compare_bytewise() {
+        let not_empty = check("name", CompareOp::Ne, "");
+        assert_eq!(not_empty.holds(ColumnType::STRING, Some("a")), Ok(true));
+        assert_eq!(not_empty.holds(ColumnType::STRING, Some("")), Ok(false));
+
+        // Unlike the numbers they look like
+        let before = check("code", CompareOp::Lt, "18");
+        assert_eq!(before.holds(ColumnType::STRING, Some("9")), Ok(false));
+    }
+
+    #[test]
+    fn null_always_holds() {
+        for op in CompareOp::ALL {
+            assert_eq!(
+                check("age", op, "18").holds(ColumnType::UINT8, None),
+                Ok(true)
+            );
+        }
+    }
+
+    #[test]
+    fn values_must_match_column_type() {
+        let adult = check("age", CompareOp::Ge, "18");
+
+        assert_eq!(
+            adult.holds(ColumnType::UINT8, Some("old")),
+            Err(String::from("Value [old] isn't a UINT8 like column [age]"))
+        );
+        assert!(check("age", CompareOp::Ge, "-1")
+            .holds(ColumnType::UINT8, Some("18"))
+            .is_err());
+    }
+}
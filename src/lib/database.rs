@@ -1,9 +1,10 @@
+use crate::mvcc::Version;
 use crate::table::Table;
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct Database {
     pub name:   String,
-    pub tables: Vec<Table>,
+    pub tables: Vec<Version<Table>>,
 }
 
 impl Database {
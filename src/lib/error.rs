@@ -0,0 +1,91 @@
+use std::fmt::Display;
+
+/// Code sent in error frames, so clients can tell failures apart without parsing messages
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+#[repr(u16)]
+pub enum ErrorCode {
+    /// The request couldn't be parsed
+    BadRequest = 0x0001,
+    /// The command couldn't be carried out, e.g. because a name is already in use
+    CommandFailed = 0x0002,
+    /// The command isn't allowed in the current transaction state, e.g. COMMIT without BEGIN
+    InvalidTransactionState = 0x0003,
+    /// The transaction was rolled back because it conflicts with a concurrent one. Retrying it
+    /// may succeed.
+    SerializationFailure = 0x0004,
+    /// A lock couldn't be acquired before the lock timeout, or straight away with NOWAIT
+    LockNotAvailable = 0x0005,
+    /// The transaction was rolled back to break a deadlock. Retrying it may succeed.
+    DeadlockDetected = 0x0006,
+    /// The client hasn't authenticated, or gave the wrong user or password
+    AuthenticationFailed = 0x0007,
+    /// The user lacks the privilege the command needs
+    PermissionDenied = 0x0008,
+}
+
+impl ErrorCode {
+    /// Name of the code in logs and metrics
+    pub fn name(&self) -> &'static str {
+        return match self {
+            ErrorCode::BadRequest => "bad_request",
+            ErrorCode::CommandFailed => "command_failed",
+            ErrorCode::InvalidTransactionState => "invalid_transaction_state",
+            ErrorCode::SerializationFailure => "serialization_failure",
+            ErrorCode::LockNotAvailable => "lock_not_available",
+            ErrorCode::DeadlockDetected => "deadlock_detected",
+            ErrorCode::AuthenticationFailed => "authentication_failed",
+            ErrorCode::PermissionDenied => "permission_denied",
+        };
+    }
+
+    /// Whether running the transaction again may succeed
+    pub fn is_retryable(&self) -> bool {
+        return matches!(
+            self,
+            ErrorCode::SerializationFailure | ErrorCode::DeadlockDetected
+        );
+    }
+}
+
+impl TryFrom<u16> for ErrorCode {
+    type Error = String;
+
+    fn try_from(code: u16) -> Result<ErrorCode, String> {
+        return match code {
+            0x0001 => Ok(ErrorCode::BadRequest),
+            0x0002 => Ok(ErrorCode::CommandFailed),
+            0x0003 => Ok(ErrorCode::InvalidTransactionState),
+            0x0004 => Ok(ErrorCode::SerializationFailure),
+            0x0005 => Ok(ErrorCode::LockNotAvailable),
+            0x0006 => Ok(ErrorCode::DeadlockDetected),
+            0x0007 => Ok(ErrorCode::AuthenticationFailed),
+            0x0008 => Ok(ErrorCode::PermissionDenied),
+            _ => Err(format!("Unknown error code [{:#06x}]", code)),
+        };
+    }
+}
+
+/// Failure of a command, reported to the client in an error frame
+#[derive(Debug, Clone, PartialEq)]
+pub struct Error {
+    pub code: ErrorCode,
+    pub msg: String,
+}
+
+impl Error {
+    pub fn new(code: ErrorCode, msg: String) -> Error {
+        Error { code, msg }
+    }
+}
+
+impl From<String> for Error {
+    fn from(msg: String) -> Error {
+        Error::new(ErrorCode::CommandFailed, msg)
+    }
+}
+
+impl Display for Error {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.msg)
+    }
+}
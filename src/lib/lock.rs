@@ -0,0 +1,624 @@
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::sync::{Condvar, Mutex};
+use std::time::{Duration, Instant};
+
+use crate::mvcc::TxnId;
+
+/// What a lock protects
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum LockTarget {
+    Database {
+        name: String,
+    },
+    Table {
+        db: String,
+        name: String,
+    },
+    /// Row of a table, by its primary key. Row locks don't conflict with the lock on their table,
+    /// so whoever locks rows holds a lock on the table as well.
+    Row {
+        db: String,
+        table: String,
+        key: String,
+    },
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum LockMode {
+    /// Held by any number of transactions at once
+    Shared,
+    /// Held by a single transaction
+    Exclusive,
+}
+
+impl LockMode {
+    fn compatible(&self, other: LockMode) -> bool {
+        return *self == LockMode::Shared && other == LockMode::Shared;
+    }
+
+    /// Whether holding `self` is enough for a request for `other`
+    fn covers(&self, other: LockMode) -> bool {
+        return *self == LockMode::Exclusive || other == LockMode::Shared;
+    }
+}
+
+/// What `LockManager::acquire` does when the lock is held in a conflicting mode
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Wait {
+    /// Wait up to this long for the lock
+    Timeout(Duration),
+    /// Fail straight away
+    NoWait,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum LockError {
+    /// The lock wasn't granted in time, or straight away with `Wait::NoWait`
+    NotAvailable,
+    /// Waiting would have deadlocked, and this transaction was picked to abort to break the cycle
+    Deadlock,
+}
+
+#[derive(Debug, Default)]
+struct LockEntry {
+    holders: Vec<(TxnId, LockMode)>,
+
+    /// Requests waiting for the lock, granted in order. Upgrades by holders go first.
+    queue: VecDeque<(TxnId, LockMode)>,
+}
+
+impl LockEntry {
+    fn is_held_by(&self, txn: TxnId) -> bool {
+        return self.holders.iter().any(|(holder, _)| *holder == txn);
+    }
+
+    /// Transactions `txn` would wait for if it requested `mode`
+    fn blockers(&self, txn: TxnId, mode: LockMode) -> Vec<TxnId> {
+        let mut blockers: Vec<TxnId> = self
+            .holders
+            .iter()
+            .filter(|(holder, held)| *holder != txn && !held.compatible(mode))
+            .map(|(holder, _)| *holder)
+            .collect();
+
+        // A holder upgrading only waits for the other holders. The requests queued behind its
+        // shared lock already wait for it, so waiting for them would be a deadlock.
+        if self.is_held_by(txn) {
+            return blockers;
+        }
+
+        // Requests queued earlier are granted first
+        for (waiter, wanted) in &self.queue {
+            if *waiter == txn {
+                break;
+            }
+
+            if !wanted.compatible(mode) {
+                blockers.push(*waiter);
+            }
+        }
+
+        return blockers;
+    }
+}
+
+#[derive(Debug, Default)]
+struct LockState {
+    locks: HashMap<LockTarget, LockEntry>,
+
+    /// Lock each waiting transaction waits for, the edges of the waits-for graph
+    waiting: HashMap<TxnId, (LockTarget, LockMode)>,
+
+    /// Waiting transactions picked to abort to break a deadlock
+    victims: HashSet<TxnId>,
+}
+
+impl LockState {
+    /// Transactions waiting for each other in a cycle including `start`, if there is one
+    fn find_cycle(&self, start: TxnId) -> Option<Vec<TxnId>> {
+        let mut path = vec![start];
+        let mut visited = HashSet::new();
+
+        return match self.search(start, start, &mut path, &mut visited) {
+            true => Some(path),
+            false => None,
+        };
+    }
+
+    fn search(
+        &self,
+        start: TxnId,
+        txn: TxnId,
+        path: &mut Vec<TxnId>,
+        visited: &mut HashSet<TxnId>,
+    ) -> bool {
+        let (target, mode) = match self.waiting.get(&txn) {
+            Some(waiting) => waiting,
+            None => return false,
+        };
+
+        let blockers = match self.locks.get(target) {
+            Some(entry) => entry.blockers(txn, *mode),
+            None => return false,
+        };
+
+        for blocker in blockers {
+            if blocker == start {
+                return true;
+            }
+
+            if visited.insert(blocker) {
+                path.push(blocker);
+
+                if self.search(start, blocker, path, visited) {
+                    return true;
+                }
+
+                path.pop();
+            }
+        }
+
+        return false;
+    }
+
+    fn stop_waiting(&mut self, txn: TxnId, target: &LockTarget) -> () {
+        self.waiting.remove(&txn);
+
+        if let Some(entry) = self.locks.get_mut(target) {
+            entry.queue.retain(|(waiter, _)| *waiter != txn);
+        }
+    }
+}
+
+/// Database, table and row locks held by transactions until they end. Requests wait in a queue per
+/// lock, and a request which would close a cycle in the waits-for graph aborts the youngest
+/// transaction of the cycle.
+#[derive(Debug, Default)]
+pub struct LockManager {
+    state: Mutex<LockState>,
+    released: Condvar,
+}
+
+impl LockManager {
+    pub fn new() -> LockManager {
+        return LockManager::default();
+    }
+
+    pub fn acquire(
+        &self,
+        txn: TxnId,
+        target: LockTarget,
+        mode: LockMode,
+        wait: Wait,
+    ) -> Result<(), LockError> {
+        let mut state = self.state.lock().unwrap();
+
+        let entry = state.locks.entry(target.clone()).or_default();
+
+        if let Some((_, held)) = entry.holders.iter().find(|(holder, _)| *holder == txn) {
+            if held.covers(mode) {
+                return Ok(());
+            }
+        }
+
+        if entry.blockers(txn, mode).is_empty() {
+            grant(entry, txn, mode);
+            return Ok(());
+        }
+
+        let deadline = match wait {
+            Wait::NoWait => return Err(LockError::NotAvailable),
+            Wait::Timeout(timeout) => Instant::now() + timeout,
+        };
+
+        match entry.is_held_by(txn) {
+            true => entry.queue.push_front((txn, mode)),
+            false => entry.queue.push_back((txn, mode)),
+        }
+        state.waiting.insert(txn, (target.clone(), mode));
+
+        if let Some(cycle) = state.find_cycle(txn) {
+            let victim = cycle.into_iter().max().unwrap();
+
+            if victim == txn {
+                state.stop_waiting(txn, &target);
+                self.released.notify_all();
+                return Err(LockError::Deadlock);
+            }
+
+            state.victims.insert(victim);
+            self.released.notify_all();
+        }
+
+        loop {
+            if state.victims.remove(&txn) {
+                state.stop_waiting(txn, &target);
+                self.released.notify_all();
+                return Err(LockError::Deadlock);
+            }
+
+            let entry = state.locks.get_mut(&target).unwrap();
+
+            if entry.blockers(txn, mode).is_empty() {
+                state.stop_waiting(txn, &target);
+                grant(state.locks.get_mut(&target).unwrap(), txn, mode);
+                // Requests queued behind this one may be compatible with it
+                self.released.notify_all();
+                return Ok(());
+            }
+
+            let now = Instant::now();
+
+            if now >= deadline {
+                state.stop_waiting(txn, &target);
+                self.released.notify_all();
+                return Err(LockError::NotAvailable);
+            }
+
+            state = self.released.wait_timeout(state, deadline - now).unwrap().0;
+        }
+    }
+
+    /// Take the lock only if it's free, without waiting or queueing. For SKIP LOCKED, which
+    /// passes over rows other transactions have locked rather than failing like NOWAIT.
+    pub fn try_acquire(&self, txn: TxnId, target: LockTarget, mode: LockMode) -> bool {
+        // Never waits, so it can't deadlock either
+        return self.acquire(txn, target, mode, Wait::NoWait).is_ok();
+    }
+
+    /// Release every lock `txn` holds, when it commits or rolls back
+    pub fn release_all(&self, txn: TxnId) -> () {
+        let mut state = self.state.lock().unwrap();
+
+        for entry in state.locks.values_mut() {
+            entry.holders.retain(|(holder, _)| *holder != txn);
+        }
+
+        state
+            .locks
+            .retain(|_, entry| !entry.holders.is_empty() || !entry.queue.is_empty());
+
+        self.released.notify_all();
+    }
+}
+
+fn grant(entry: &mut LockEntry, txn: TxnId, mode: LockMode) -> () {
+    match entry.holders.iter_mut().find(|(holder, _)| *holder == txn) {
+        // Upgrade from shared to exclusive
+        Some(held) => held.1 = mode,
+        None => entry.holders.push((txn, mode)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+    use std::thread;
+
+    use super::*;
+
+    const LONG: Wait = Wait::Timeout(Duration::from_secs(5));
+
+    fn table(name: &str) -> LockTarget {
+        return LockTarget::Table {
+            db: String::from("db"),
+            name: String::from(name),
+        };
+    }
+
+    fn row(key: &str) -> LockTarget {
+        return LockTarget::Row {
+            db: String::from("db"),
+            table: String::from("a"),
+            key: String::from(key),
+        };
+    }
+
+    /// Request a lock on another thread, once `txn` is queued for it
+    fn acquire_queued(
+        locks: &Arc<LockManager>,
+        txn: TxnId,
+        target: LockTarget,
+        mode: LockMode,
+    ) -> thread::JoinHandle<Result<(), LockError>> {
+        let handle = {
+            let locks = Arc::clone(locks);
+            thread::spawn(move || locks.acquire(txn, target, mode, LONG))
+        };
+
+        while !locks.state.lock().unwrap().waiting.contains_key(&txn) {
+            thread::yield_now();
+        }
+
+        return handle;
+    }
+
+    #[test]
+    fn shared_locks_are_compatible() {
+        let locks = LockManager::new();
+
+        assert_eq!(
+            locks.acquire(1, table("a"), LockMode::Shared, Wait::NoWait),
+            Ok(())
+        );
+        assert_eq!(
+            locks.acquire(2, table("a"), LockMode::Shared, Wait::NoWait),
+            Ok(())
+        );
+        assert_eq!(
+            locks.acquire(3, table("a"), LockMode::Exclusive, Wait::NoWait),
+            Err(LockError::NotAvailable)
+        );
+    }
+
+    #[test]
+    fn exclusive_lock_conflicts() {
+        let locks = LockManager::new();
+
+        assert_eq!(
+            locks.acquire(1, table("a"), LockMode::Exclusive, Wait::NoWait),
+            Ok(())
+        );
+        assert_eq!(
+            locks.acquire(2, table("a"), LockMode::Shared, Wait::NoWait),
+            Err(LockError::NotAvailable)
+        );
+        assert_eq!(
+            locks.acquire(2, table("b"), LockMode::Exclusive, Wait::NoWait),
+            Ok(())
+        );
+
+        locks.release_all(1);
+        assert_eq!(
+            locks.acquire(2, table("a"), LockMode::Shared, Wait::NoWait),
+            Ok(())
+        );
+    }
+
+    #[test]
+    fn locks_are_reentrant() {
+        let locks = LockManager::new();
+
+        assert_eq!(
+            locks.acquire(1, table("a"), LockMode::Exclusive, Wait::NoWait),
+            Ok(())
+        );
+        assert_eq!(
+            locks.acquire(1, table("a"), LockMode::Shared, Wait::NoWait),
+            Ok(())
+        );
+        assert_eq!(
+            locks.acquire(1, table("a"), LockMode::Exclusive, Wait::NoWait),
+            Ok(())
+        );
+    }
+
+    #[test]
+    fn times_out() {
+        let locks = LockManager::new();
+        let timeout = Duration::from_millis(50);
+
+        assert_eq!(
+            locks.acquire(1, table("a"), LockMode::Exclusive, Wait::NoWait),
+            Ok(())
+        );
+
+        let start = Instant::now();
+        assert_eq!(
+            locks.acquire(2, table("a"), LockMode::Shared, Wait::Timeout(timeout)),
+            Err(LockError::NotAvailable)
+        );
+        assert!(start.elapsed() >= timeout);
+        assert!(locks.state.lock().unwrap().waiting.is_empty());
+    }
+
+    #[test]
+    fn waiter_is_granted_on_release() {
+        let locks = Arc::new(LockManager::new());
+
+        assert_eq!(
+            locks.acquire(1, table("a"), LockMode::Exclusive, Wait::NoWait),
+            Ok(())
+        );
+        let waiter = acquire_queued(&locks, 2, table("a"), LockMode::Exclusive);
+
+        locks.release_all(1);
+        assert_eq!(waiter.join().unwrap(), Ok(()));
+    }
+
+    #[test]
+    fn queued_requests_are_granted_in_order() {
+        let locks = Arc::new(LockManager::new());
+
+        assert_eq!(
+            locks.acquire(1, table("a"), LockMode::Shared, Wait::NoWait),
+            Ok(())
+        );
+        let writer = acquire_queued(&locks, 2, table("a"), LockMode::Exclusive);
+
+        // Compatible with the holder, but not with the writer queued first
+        assert_eq!(
+            locks.acquire(3, table("a"), LockMode::Shared, Wait::NoWait),
+            Err(LockError::NotAvailable)
+        );
+
+        locks.release_all(1);
+        assert_eq!(writer.join().unwrap(), Ok(()));
+    }
+
+    #[test]
+    fn youngest_transaction_of_a_cycle_aborts() {
+        let locks = Arc::new(LockManager::new());
+
+        assert_eq!(
+            locks.acquire(1, table("a"), LockMode::Exclusive, Wait::NoWait),
+            Ok(())
+        );
+        assert_eq!(
+            locks.acquire(2, table("b"), LockMode::Exclusive, Wait::NoWait),
+            Ok(())
+        );
+        let older = acquire_queued(&locks, 1, table("b"), LockMode::Exclusive);
+
+        assert_eq!(
+            locks.acquire(2, table("a"), LockMode::Exclusive, LONG),
+            Err(LockError::Deadlock)
+        );
+
+        locks.release_all(2);
+        assert_eq!(older.join().unwrap(), Ok(()));
+    }
+
+    #[test]
+    fn waiting_victim_aborts() {
+        let locks = Arc::new(LockManager::new());
+
+        assert_eq!(
+            locks.acquire(1, table("a"), LockMode::Exclusive, Wait::NoWait),
+            Ok(())
+        );
+        assert_eq!(
+            locks.acquire(2, table("b"), LockMode::Exclusive, Wait::NoWait),
+            Ok(())
+        );
+        let younger = acquire_queued(&locks, 2, table("a"), LockMode::Exclusive);
+
+        // Closing the cycle picks the waiting transaction 2 rather than 1
+        let older = {
+            let locks = Arc::clone(&locks);
+            thread::spawn(move || locks.acquire(1, table("b"), LockMode::Exclusive, LONG))
+        };
+
+        assert_eq!(younger.join().unwrap(), Err(LockError::Deadlock));
+        locks.release_all(2);
+        assert_eq!(older.join().unwrap(), Ok(()));
+    }
+
+    #[test]
+    fn upgrade_goes_ahead_of_waiters() {
+        let locks = Arc::new(LockManager::new());
+
+        assert_eq!(
+            locks.acquire(1, table("a"), LockMode::Shared, Wait::NoWait),
+            Ok(())
+        );
+        let waiter = acquire_queued(&locks, 2, table("a"), LockMode::Exclusive);
+
+        assert_eq!(
+            locks.acquire(1, table("a"), LockMode::Exclusive, LONG),
+            Ok(())
+        );
+
+        locks.release_all(1);
+        assert_eq!(waiter.join().unwrap(), Ok(()));
+    }
+
+    #[test]
+    fn upgrade_waits_for_other_holders() {
+        let locks = Arc::new(LockManager::new());
+
+        assert_eq!(
+            locks.acquire(1, table("a"), LockMode::Shared, Wait::NoWait),
+            Ok(())
+        );
+        assert_eq!(
+            locks.acquire(2, table("a"), LockMode::Shared, Wait::NoWait),
+            Ok(())
+        );
+        let waiter = acquire_queued(&locks, 3, table("a"), LockMode::Exclusive);
+        let upgrade = acquire_queued(&locks, 1, table("a"), LockMode::Exclusive);
+
+        locks.release_all(2);
+        assert_eq!(upgrade.join().unwrap(), Ok(()));
+
+        locks.release_all(1);
+        assert_eq!(waiter.join().unwrap(), Ok(()));
+    }
+
+    #[test]
+    fn concurrent_upgrades_deadlock() {
+        let locks = Arc::new(LockManager::new());
+
+        assert_eq!(
+            locks.acquire(1, table("a"), LockMode::Shared, Wait::NoWait),
+            Ok(())
+        );
+        assert_eq!(
+            locks.acquire(2, table("a"), LockMode::Shared, Wait::NoWait),
+            Ok(())
+        );
+        let older = acquire_queued(&locks, 1, table("a"), LockMode::Exclusive);
+
+        assert_eq!(
+            locks.acquire(2, table("a"), LockMode::Exclusive, LONG),
+            Err(LockError::Deadlock)
+        );
+
+        locks.release_all(2);
+        assert_eq!(older.join().unwrap(), Ok(()));
+    }
+
+    #[test]
+    fn rows_are_locked_separately() {
+        let locks = LockManager::new();
+
+        assert_eq!(
+            locks.acquire(1, row("1"), LockMode::Exclusive, Wait::NoWait),
+            Ok(())
+        );
+        assert_eq!(
+            locks.acquire(2, row("2"), LockMode::Exclusive, Wait::NoWait),
+            Ok(())
+        );
+        assert_eq!(
+            locks.acquire(2, row("1"), LockMode::Shared, Wait::NoWait),
+            Err(LockError::NotAvailable)
+        );
+
+        // Independent of the lock on the table
+        assert_eq!(
+            locks.acquire(3, table("a"), LockMode::Exclusive, Wait::NoWait),
+            Ok(())
+        );
+    }
+
+    #[test]
+    fn try_acquire_skips_locked_rows() {
+        let locks = LockManager::new();
+
+        assert!(locks.try_acquire(1, row("1"), LockMode::Exclusive));
+        assert!(locks.try_acquire(1, row("3"), LockMode::Shared));
+
+        let taken: Vec<&str> = ["1", "2", "3", "4"]
+            .into_iter()
+            .filter(|key| locks.try_acquire(2, row(key), LockMode::Exclusive))
+            .collect();
+        assert_eq!(taken, ["2", "4"]);
+
+        // Shared locks only skip rows locked exclusively
+        assert!(!locks.try_acquire(3, row("1"), LockMode::Shared));
+        assert!(locks.try_acquire(3, row("3"), LockMode::Shared));
+
+        // Locks already held are taken again
+        assert!(locks.try_acquire(2, row("2"), LockMode::Shared));
+
+        locks.release_all(1);
+        assert!(locks.try_acquire(2, row("1"), LockMode::Exclusive));
+    }
+
+    #[test]
+    fn try_acquire_doesnt_jump_the_queue() {
+        let locks = Arc::new(LockManager::new());
+
+        assert!(locks.try_acquire(1, row("1"), LockMode::Shared));
+        let writer = acquire_queued(&locks, 2, row("1"), LockMode::Exclusive);
+
+        // Compatible with the holder, but not with the writer queued first. Nothing is queued
+        // for the skipped request.
+        assert!(!locks.try_acquire(3, row("1"), LockMode::Shared));
+        assert!(!locks.state.lock().unwrap().waiting.contains_key(&3));
+
+        locks.release_all(1);
+        assert_eq!(writer.join().unwrap(), Ok(()));
+    }
+}
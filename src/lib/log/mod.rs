@@ -0,0 +1,585 @@
+use std::fmt::Debug;
+use std::io::Result;
+use std::sync::atomic::{AtomicU64, AtomicU8, Ordering};
+use std::sync::mpsc::{self, Receiver, SyncSender, TrySendError};
+use std::sync::Arc;
+use std::thread;
+
+use chrono::{DateTime, Local, SecondsFormat};
+use serde::Deserialize;
+
+mod output;
+
+pub use output::{LogOutput, RotatingFile, Rotation, StreamOutput, SyslogOutput};
+
+pub struct Logger {
+    name: String,
+    log_level: LogLevel,
+    format: LogFormat,
+    output: Box<dyn LogOutput>,
+}
+
+// const RED_FG : &str = "\x1b[31m";
+// const GREEN_FG : &str = "\x1b[32m";
+// const YELLOW_FG : &str = "\x1b[33m";
+// const RESET : &str = "\x1b[0m";
+
+const INFO_PREFIX: &str = "[\x1b[32mINFO\x1b[0m]";
+const DEBUG_PREFIX: &str = "[\x1b[34mDBUG\x1b[0m]";
+const WARN_PREFIX: &str = "[\x1b[33mWARN\x1b[0m]";
+const ERR_PREFIX: &str = "[\x1b[31mERRR\x1b[0m]";
+
+impl Logger {
+    pub fn new(
+        name: String,
+        log_level: LogLevel,
+        format: LogFormat,
+        output: Box<dyn LogOutput>,
+    ) -> Logger {
+        Logger {
+            name,
+            log_level,
+            format,
+            output,
+        }
+    }
+
+    fn log(
+        &mut self,
+        log_level: LogLevel,
+        t: &DateTime<Local>,
+        context: &LogContext,
+        msg: &str,
+    ) -> Result<()> {
+        // Text formats show the context as a `[key=value ...]` prefix to the message
+        let text_msg = match context.is_empty() {
+            true => String::from(msg),
+            false => {
+                let fields: Vec<String> = context
+                    .iter()
+                    .map(|(key, value)| format!("{}={}", key, value))
+                    .collect();
+
+                format!("[{}] {}", fields.join(" "), msg)
+            }
+        };
+
+        let record = match self.format {
+            LogFormat::Color => {
+                let prefix = match log_level {
+                    LogLevel::DEBUG => DEBUG_PREFIX,
+                    LogLevel::INFO => INFO_PREFIX,
+                    LogLevel::WARN => WARN_PREFIX,
+                    LogLevel::ERROR => ERR_PREFIX,
+                };
+
+                format!(
+                    "{}{} {}\n",
+                    prefix,
+                    t.format("[%Y-%m-%d %H:%M:%S]"),
+                    text_msg
+                )
+            }
+            LogFormat::Text => format!(
+                "[{}]{} {}\n",
+                log_level.label(),
+                t.format("[%Y-%m-%d %H:%M:%S]"),
+                text_msg
+            ),
+            LogFormat::Json => {
+                let timestamp = t.to_rfc3339_opts(SecondsFormat::Millis, false);
+
+                // Built field by field to keep the fields in this order
+                let fields: Vec<String> = [
+                    ("timestamp", timestamp.as_str()),
+                    ("level", log_level.name()),
+                    ("logger", &self.name),
+                ]
+                .into_iter()
+                .chain(context.iter().map(|(key, value)| (*key, value.as_str())))
+                .chain([("message", msg)])
+                .map(|(key, value)| format!("{}:{}", json_string(key), json_string(value)))
+                .collect();
+
+                format!("{{{}}}\n", fields.join(","))
+            }
+        };
+
+        return self.output.write_record(log_level, &record);
+    }
+}
+
+impl Debug for Logger {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Logger[{}]", self.name)
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, PartialOrd, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum LogLevel {
+    DEBUG = 0x00,
+    INFO = 0x01,
+    WARN = 0x02,
+    ERROR = 0x03,
+}
+
+impl LogLevel {
+    /// Fixed width label used by the text formats
+    fn label(&self) -> &'static str {
+        return match self {
+            LogLevel::DEBUG => "DBUG",
+            LogLevel::INFO => "INFO",
+            LogLevel::WARN => "WARN",
+            LogLevel::ERROR => "ERRR",
+        };
+    }
+
+    pub fn name(&self) -> &'static str {
+        return match self {
+            LogLevel::DEBUG => "debug",
+            LogLevel::INFO => "info",
+            LogLevel::WARN => "warn",
+            LogLevel::ERROR => "error",
+        };
+    }
+}
+
+/// How a logger renders its records
+#[derive(Clone, Copy, Debug, PartialEq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum LogFormat {
+    /// Text with the level highlighted by ANSI colour codes, for terminals
+    Color,
+    /// Text without escape codes
+    Text,
+    /// One JSON object per line
+    Json,
+}
+
+/// What `Loggers::log` does when the queue feeding the logging thread is full
+#[derive(Clone, Copy, Debug, PartialEq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum OverflowPolicy {
+    /// Discard the record and count it in `Loggers::dropped_count`
+    Drop,
+    /// Wait for the logging thread to catch up
+    Block,
+}
+
+const DEFAULT_QUEUE_SIZE: usize = 4096;
+
+/// Fields attached to a record, e.g. the connection it was logged from. Rendered as a
+/// `[key=value ...]` prefix by the text formats and as extra fields by the JSON format.
+pub type LogContext = Vec<(&'static str, String)>;
+
+enum LogMsg {
+    Record {
+        log_level: LogLevel,
+        t: DateTime<Local>,
+        context: LogContext,
+        msg: String,
+    },
+    SetLogLevel {
+        name: String,
+        log_level: LogLevel,
+    },
+    Flush(SyncSender<()>),
+}
+
+/// Counters shared between the `Loggers` handles and the logging thread
+#[derive(Debug, Default)]
+struct LogStats {
+    /// Lowest level any logger accepts, so records nobody wants aren't queued
+    min_log_level: AtomicU8,
+    dropped: AtomicU64,
+    write_errors: AtomicU64,
+}
+
+/// Handle to the logging thread, which owns the `Logger`s and writes the records queued by `log`.
+/// Cloning the handle is cheap; the thread stops once every handle is dropped.
+#[derive(Debug, Clone)]
+pub struct Loggers {
+    sender: SyncSender<LogMsg>,
+    overflow_policy: OverflowPolicy,
+    stats: Arc<LogStats>,
+}
+
+impl Loggers {
+    pub fn new(
+        loggers: Vec<Logger>,
+        queue_size: usize,
+        overflow_policy: OverflowPolicy,
+    ) -> Loggers {
+        let (sender, receiver) = mpsc::sync_channel(queue_size);
+
+        let stats = Arc::new(LogStats::default());
+        stats
+            .min_log_level
+            .store(min_log_level(&loggers) as u8, Ordering::Relaxed);
+
+        let thread_stats = stats.clone();
+
+        thread::Builder::new()
+            .name(String::from("squeef-log"))
+            .spawn(move || run_log_thread(loggers, receiver, thread_stats))
+            .unwrap();
+
+        return Loggers {
+            sender,
+            overflow_policy,
+            stats,
+        };
+    }
+
+    pub fn log(&self, log_level: LogLevel, msg: &str) -> () {
+        self.log_with_context(log_level, Vec::new, msg);
+    }
+
+    /// Log `msg` with the fields returned by `context`, which is only called if a logger accepts
+    /// `log_level`
+    pub fn log_with_context(
+        &self,
+        log_level: LogLevel,
+        context: impl FnOnce() -> LogContext,
+        msg: &str,
+    ) -> () {
+        if (log_level as u8) < self.stats.min_log_level.load(Ordering::Relaxed) {
+            return;
+        }
+
+        let record = LogMsg::Record {
+            log_level,
+            t: Local::now(),
+            context: context(),
+            msg: String::from(msg),
+        };
+
+        match self.overflow_policy {
+            OverflowPolicy::Block => {
+                // Only fails once the logging thread is gone, and then there is nowhere to log to
+                let _ = self.sender.send(record);
+            }
+            OverflowPolicy::Drop => {
+                if let Err(TrySendError::Full(_)) = self.sender.try_send(record) {
+                    self.stats.dropped.fetch_add(1, Ordering::Relaxed);
+                }
+            }
+        }
+    }
+
+    /// Change the level of the logger called `name`
+    pub fn set_log_level(&self, name: &str, log_level: LogLevel) -> () {
+        let _ = self.sender.send(LogMsg::SetLogLevel {
+            name: String::from(name),
+            log_level,
+        });
+    }
+
+    /// Block until every record queued so far is written and flushed
+    pub fn flush(&self) -> () {
+        let (ack_sender, ack_receiver) = mpsc::sync_channel(1);
+
+        if self.sender.send(LogMsg::Flush(ack_sender)).is_ok() {
+            let _ = ack_receiver.recv();
+        }
+    }
+
+    /// Records discarded because the queue was full
+    pub fn dropped_count(&self) -> u64 {
+        return self.stats.dropped.load(Ordering::Relaxed);
+    }
+
+    /// Records which a logger failed to write
+    pub fn write_error_count(&self) -> u64 {
+        return self.stats.write_errors.load(Ordering::Relaxed);
+    }
+}
+
+impl From<Vec<Logger>> for Loggers {
+    fn from(v: Vec<Logger>) -> Loggers {
+        Loggers::new(v, DEFAULT_QUEUE_SIZE, OverflowPolicy::Drop)
+    }
+}
+
+fn min_log_level(loggers: &[Logger]) -> LogLevel {
+    return loggers
+        .iter()
+        .map(|l| l.log_level)
+        .reduce(|a, b| if b < a { b } else { a })
+        .unwrap_or(LogLevel::ERROR);
+}
+
+fn run_log_thread(mut loggers: Vec<Logger>, receiver: Receiver<LogMsg>, stats: Arc<LogStats>) {
+    // Number of dropped records already reported
+    let mut reported_dropped = 0;
+
+    // Loggers whose last write failed, so a broken output is reported once rather than per record
+    let mut failing: Vec<bool> = vec![false; loggers.len()];
+
+    for msg in receiver {
+        match msg {
+            LogMsg::Record {
+                log_level,
+                t,
+                context,
+                msg,
+            } => {
+                let dropped = stats.dropped.load(Ordering::Relaxed);
+
+                let mut records = vec![];
+
+                if dropped > reported_dropped {
+                    records.push((
+                        LogLevel::WARN,
+                        vec![],
+                        format!(
+                            "Log queue full. Dropped {} record(s)",
+                            dropped - reported_dropped
+                        ),
+                    ));
+                    reported_dropped = dropped;
+                }
+
+                records.push((log_level, context, msg));
+
+                for (log_level, context, msg) in &records {
+                    for (l, failing) in loggers.iter_mut().zip(failing.iter_mut()) {
+                        if l.log_level > *log_level {
+                            continue;
+                        }
+
+                        match l.log(*log_level, &t, context, msg) {
+                            Ok(()) => *failing = false,
+                            Err(e) => {
+                                stats.write_errors.fetch_add(1, Ordering::Relaxed);
+
+                                if !*failing {
+                                    eprintln!("Logger [{}] failed to write: {}", l.name, e);
+                                    *failing = true;
+                                }
+                            }
+                        }
+                    }
+                }
+            }
+            LogMsg::SetLogLevel { name, log_level } => {
+                if let Some(l) = loggers.iter_mut().find(|l| l.name == name) {
+                    l.log_level = log_level;
+                }
+
+                stats
+                    .min_log_level
+                    .store(min_log_level(&loggers) as u8, Ordering::Relaxed);
+            }
+            LogMsg::Flush(ack) => {
+                for l in &mut loggers {
+                    if let Err(e) = l.output.flush() {
+                        stats.write_errors.fetch_add(1, Ordering::Relaxed);
+                        eprintln!("Logger [{}] failed to flush: {}", l.name, e);
+                    }
+                }
+
+                let _ = ack.send(());
+            }
+        }
+    }
+}
+
+fn json_string(s: &str) -> String {
+    return serde_json::to_string(s).unwrap();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::io;
+    use std::sync::atomic::AtomicBool;
+    use std::sync::Mutex;
+    use std::time::Duration;
+
+    type Records = Arc<Mutex<Vec<String>>>;
+
+    /// Output keeping its records in memory
+    struct Collect(Records);
+
+    impl LogOutput for Collect {
+        fn write_record(&mut self, _log_level: LogLevel, record: &str) -> io::Result<()> {
+            self.0.lock().unwrap().push(String::from(record));
+            return Ok(());
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            return Ok(());
+        }
+    }
+
+    /// Output failing every write
+    struct Broken;
+
+    impl LogOutput for Broken {
+        fn write_record(&mut self, _log_level: LogLevel, _record: &str) -> io::Result<()> {
+            return Err(io::Error::other("disk full"));
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            return Ok(());
+        }
+    }
+
+    /// Output announcing each write on `entered`, then holding it until `gate` gets a message
+    /// or is closed
+    struct Gated {
+        entered: SyncSender<()>,
+        gate: Receiver<()>,
+        records: Records,
+    }
+
+    impl LogOutput for Gated {
+        fn write_record(&mut self, _log_level: LogLevel, record: &str) -> io::Result<()> {
+            let _ = self.entered.send(());
+            let _ = self.gate.recv();
+            self.records.lock().unwrap().push(String::from(record));
+            return Ok(());
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            return Ok(());
+        }
+    }
+
+    fn logger(name: &str, format: LogFormat, output: impl LogOutput + 'static) -> Logger {
+        return Logger::new(
+            String::from(name),
+            LogLevel::DEBUG,
+            format,
+            Box::new(output),
+        );
+    }
+
+    /// `Loggers` whose only output is stuck writing its first record, `"first"`. Closing the
+    /// returned gate lets it carry on.
+    fn stuck(
+        queue_size: usize,
+        overflow_policy: OverflowPolicy,
+    ) -> (Loggers, SyncSender<()>, Records) {
+        let (entered_sender, entered) = mpsc::sync_channel(16);
+        let (gate, gate_receiver) = mpsc::sync_channel(0);
+        let records = Records::default();
+
+        let output = Gated {
+            entered: entered_sender,
+            gate: gate_receiver,
+            records: records.clone(),
+        };
+        let loggers = Loggers::new(
+            vec![logger("gated", LogFormat::Text, output)],
+            queue_size,
+            overflow_policy,
+        );
+
+        loggers.log(LogLevel::INFO, "first");
+        entered.recv().unwrap();
+
+        return (loggers, gate, records);
+    }
+
+    fn messages(records: &Records) -> Vec<String> {
+        return records
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|r| String::from(r.trim_end().split_once("] ").unwrap().1))
+            .collect();
+    }
+
+    #[test]
+    fn drop_policy_counts_and_reports_dropped_records() {
+        let (loggers, gate, records) = stuck(1, OverflowPolicy::Drop);
+
+        loggers.log(LogLevel::INFO, "second");
+        loggers.log(LogLevel::INFO, "third");
+        loggers.log(LogLevel::INFO, "fourth");
+        assert_eq!(loggers.dropped_count(), 2);
+
+        drop(gate);
+        loggers.flush();
+
+        assert_eq!(
+            messages(&records),
+            ["first", "Log queue full. Dropped 2 record(s)", "second"]
+        );
+    }
+
+    #[test]
+    fn block_policy_waits_for_room_in_the_queue() {
+        let (loggers, gate, records) = stuck(1, OverflowPolicy::Block);
+        loggers.log(LogLevel::INFO, "second");
+
+        let logged = Arc::new(AtomicBool::new(false));
+        let writer = {
+            let loggers = loggers.clone();
+            let logged = logged.clone();
+
+            thread::spawn(move || {
+                loggers.log(LogLevel::INFO, "third");
+                logged.store(true, Ordering::SeqCst);
+            })
+        };
+
+        thread::sleep(Duration::from_millis(100));
+        assert!(!logged.load(Ordering::SeqCst));
+
+        drop(gate);
+        writer.join().unwrap();
+        loggers.flush();
+
+        assert_eq!(loggers.dropped_count(), 0);
+        assert_eq!(messages(&records), ["first", "second", "third"]);
+    }
+
+    #[test]
+    fn failing_output_doesnt_stop_other_loggers() {
+        let records = Records::default();
+        let loggers = Loggers::from(vec![
+            logger("broken", LogFormat::Text, Broken),
+            logger("memory", LogFormat::Text, Collect(records.clone())),
+        ]);
+
+        loggers.log(LogLevel::INFO, "one");
+        loggers.log(LogLevel::WARN, "two");
+        loggers.flush();
+
+        assert_eq!(loggers.write_error_count(), 2);
+        assert_eq!(messages(&records), ["one", "two"]);
+    }
+
+    #[test]
+    fn json_records_are_one_object_per_line() {
+        let records = Records::default();
+        let loggers = Loggers::from(vec![logger(
+            "json",
+            LogFormat::Json,
+            Collect(records.clone()),
+        )]);
+
+        loggers.log_with_context(
+            LogLevel::WARN,
+            || vec![("client", String::from("tab\t\"quoted\""))],
+            "line\nbreak \\ \u{1}",
+        );
+        loggers.flush();
+
+        let records = records.lock().unwrap();
+        assert!(records[0].ends_with("}\n"));
+        assert_eq!(records[0].matches('\n').count(), 1);
+
+        let record: serde_json::Value = serde_json::from_str(&records[0]).unwrap();
+        assert_eq!(record["level"], "warn");
+        assert_eq!(record["logger"], "json");
+        assert_eq!(record["client"], "tab\t\"quoted\"");
+        assert_eq!(record["message"], "line\nbreak \\ \u{1}");
+        assert!(DateTime::parse_from_rfc3339(record["timestamp"].as_str().unwrap()).is_ok());
+    }
+}
@@ -0,0 +1,336 @@
+use std::ffi::OsString;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Write};
+use std::net::{Ipv4Addr, SocketAddr, UdpSocket};
+use std::path::{Path, PathBuf};
+
+use chrono::{DateTime, Local, NaiveDate};
+
+use super::LogLevel;
+
+/// Destination of a logger's formatted records
+pub trait LogOutput: Send {
+    fn write_record(&mut self, log_level: LogLevel, record: &str) -> io::Result<()>;
+
+    fn flush(&mut self) -> io::Result<()>;
+}
+
+/// Output writing records as they are to any writer, e.g. stderr
+pub struct StreamOutput<W: Write + Send>(pub W);
+
+impl<W: Write + Send> LogOutput for StreamOutput<W> {
+    fn write_record(&mut self, _log_level: LogLevel, record: &str) -> io::Result<()> {
+        return self.0.write_all(record.as_bytes());
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        return self.0.flush();
+    }
+}
+
+/// When a `RotatingFile` moves the current file aside and starts a new one
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Rotation {
+    /// Rotate before the file grows beyond this many bytes
+    pub max_size: Option<u64>,
+
+    /// Rotate on the first record of a new day
+    pub daily: bool,
+
+    /// Number of rotated files kept, as `<path>.1` (most recent) to `<path>.<keep>`
+    pub keep: usize,
+}
+
+/// Log file which rotates according to a `Rotation`
+pub struct RotatingFile {
+    path: PathBuf,
+    rotation: Rotation,
+    file: File,
+    size: u64,
+    opened_on: NaiveDate,
+}
+
+impl RotatingFile {
+    pub fn open(path: PathBuf, rotation: Rotation) -> io::Result<RotatingFile> {
+        let file = OpenOptions::new().create(true).append(true).open(&path)?;
+        let metadata = file.metadata()?;
+
+        // An existing file is considered to date from its last write
+        let opened_on = match metadata.modified() {
+            Ok(modified) => DateTime::<Local>::from(modified).date_naive(),
+            Err(_) => Local::now().date_naive(),
+        };
+
+        return Ok(RotatingFile {
+            path,
+            rotation,
+            file,
+            size: metadata.len(),
+            opened_on,
+        });
+    }
+
+    fn needs_rotation(&self, record_len: u64) -> bool {
+        if self.size == 0 {
+            return false;
+        }
+
+        if let Some(max_size) = self.rotation.max_size {
+            if self.size + record_len > max_size {
+                return true;
+            }
+        }
+
+        return self.rotation.daily && Local::now().date_naive() != self.opened_on;
+    }
+
+    fn rotate(&mut self) -> io::Result<()> {
+        self.file.flush()?;
+
+        if self.rotation.keep == 0 {
+            fs::remove_file(&self.path)?;
+        } else {
+            for i in (1..self.rotation.keep).rev() {
+                let from = rotated_path(&self.path, i);
+
+                if from.exists() {
+                    fs::rename(&from, rotated_path(&self.path, i + 1))?;
+                }
+            }
+
+            fs::rename(&self.path, rotated_path(&self.path, 1))?;
+        }
+
+        self.file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.path)?;
+        self.size = 0;
+        self.opened_on = Local::now().date_naive();
+
+        return Ok(());
+    }
+}
+
+impl LogOutput for RotatingFile {
+    fn write_record(&mut self, _log_level: LogLevel, record: &str) -> io::Result<()> {
+        if self.needs_rotation(record.len() as u64) {
+            self.rotate()?;
+        }
+
+        self.file.write_all(record.as_bytes())?;
+        self.size += record.len() as u64;
+
+        return Ok(());
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        return self.file.flush();
+    }
+}
+
+fn rotated_path(path: &Path, i: usize) -> PathBuf {
+    let mut rotated = OsString::from(path.as_os_str());
+    rotated.push(format!(".{}", i));
+
+    return PathBuf::from(rotated);
+}
+
+/// Facility code of user-level messages (RFC 3164)
+const SYSLOG_FACILITY_USER: u8 = 1;
+
+/// Output sending each record as a BSD syslog (RFC 3164) datagram to a local syslog daemon
+pub struct SyslogOutput {
+    socket: UdpSocket,
+    target: SocketAddr,
+}
+
+impl SyslogOutput {
+    pub fn new(port: u16) -> io::Result<SyslogOutput> {
+        return Ok(SyslogOutput {
+            socket: UdpSocket::bind((Ipv4Addr::LOCALHOST, 0))?,
+            target: SocketAddr::from((Ipv4Addr::LOCALHOST, port)),
+        });
+    }
+}
+
+impl LogOutput for SyslogOutput {
+    fn write_record(&mut self, log_level: LogLevel, record: &str) -> io::Result<()> {
+        let severity = match log_level {
+            LogLevel::DEBUG => 7,
+            LogLevel::INFO => 6,
+            LogLevel::WARN => 4,
+            LogLevel::ERROR => 3,
+        };
+
+        let datagram = format!(
+            "<{}>{} squeef[{}]: {}",
+            SYSLOG_FACILITY_USER * 8 + severity,
+            Local::now().format("%b %e %H:%M:%S"),
+            std::process::id(),
+            record.trim_end()
+        );
+
+        self.socket.send_to(datagram.as_bytes(), self.target)?;
+
+        return Ok(());
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        return Ok(());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::time::{Duration, SystemTime};
+
+    use super::*;
+
+    /// Directory for a log file and its rotations, removed when dropped
+    struct LogDir {
+        root: PathBuf,
+    }
+
+    impl LogDir {
+        fn new() -> LogDir {
+            static NEXT: AtomicUsize = AtomicUsize::new(0);
+
+            let root = std::env::temp_dir().join(format!(
+                "squeef-rotation-{}-{}",
+                std::process::id(),
+                NEXT.fetch_add(1, Ordering::SeqCst)
+            ));
+            fs::create_dir_all(&root).unwrap();
+
+            return LogDir { root };
+        }
+
+        fn log(&self) -> PathBuf {
+            return self.root.join("squeef.log");
+        }
+
+        fn open(&self, max_size: Option<u64>, daily: bool, keep: usize) -> RotatingFile {
+            let rotation = Rotation {
+                max_size,
+                daily,
+                keep,
+            };
+            return RotatingFile::open(self.log(), rotation).unwrap();
+        }
+
+        /// Contents of the log (0) or its `i`th rotation, `None` if there is no such file
+        fn read(&self, i: usize) -> Option<String> {
+            let path = match i {
+                0 => self.log(),
+                i => rotated_path(&self.log(), i),
+            };
+            return fs::read_to_string(path).ok();
+        }
+    }
+
+    impl Drop for LogDir {
+        fn drop(&mut self) {
+            let _ = fs::remove_dir_all(&self.root);
+        }
+    }
+
+    /// Write 10 byte records made of each letter in `letters`
+    fn write(file: &mut RotatingFile, letters: &str) -> () {
+        for c in letters.chars() {
+            let record = format!("{}\n", c.to_string().repeat(9));
+            file.write_record(LogLevel::INFO, &record).unwrap();
+        }
+        file.flush().unwrap();
+    }
+
+    fn records(letters: &str) -> Option<String> {
+        return Some(
+            letters
+                .chars()
+                .map(|c| format!("{}\n", c.to_string().repeat(9)))
+                .collect(),
+        );
+    }
+
+    #[test]
+    fn rotates_before_exceeding_max_size() {
+        let dir = LogDir::new();
+        let mut file = dir.open(Some(25), false, 3);
+
+        write(&mut file, "abcde");
+
+        assert_eq!(dir.read(0), records("e"));
+        assert_eq!(dir.read(1), records("cd"));
+        assert_eq!(dir.read(2), records("ab"));
+        assert_eq!(dir.read(3), None);
+    }
+
+    #[test]
+    fn oversized_records_still_go_to_an_empty_file() {
+        let dir = LogDir::new();
+        let mut file = dir.open(Some(5), false, 3);
+
+        write(&mut file, "ab");
+
+        assert_eq!(dir.read(0), records("b"));
+        assert_eq!(dir.read(1), records("a"));
+        assert_eq!(dir.read(2), None);
+    }
+
+    #[test]
+    fn keeps_only_the_newest_rotations() {
+        let dir = LogDir::new();
+        let mut file = dir.open(Some(25), false, 1);
+
+        write(&mut file, "abcdef");
+
+        assert_eq!(dir.read(0), records("ef"));
+        assert_eq!(dir.read(1), records("cd"));
+        assert_eq!(dir.read(2), None);
+    }
+
+    #[test]
+    fn keep_zero_discards_rotated_records() {
+        let dir = LogDir::new();
+        let mut file = dir.open(Some(25), false, 0);
+
+        write(&mut file, "abc");
+
+        assert_eq!(dir.read(0), records("c"));
+        assert_eq!(dir.read(1), None);
+    }
+
+    #[test]
+    fn rotates_daily_files_from_an_earlier_day() {
+        let dir = LogDir::new();
+        fs::write(dir.log(), "old\n").unwrap();
+        let yesterday = SystemTime::now() - Duration::from_secs(24 * 60 * 60);
+        File::options()
+            .write(true)
+            .open(dir.log())
+            .unwrap()
+            .set_modified(yesterday)
+            .unwrap();
+
+        let mut file = dir.open(None, true, 2);
+        write(&mut file, "ab");
+
+        assert_eq!(dir.read(0), records("ab"));
+        assert_eq!(dir.read(1), Some(String::from("old\n")));
+        assert_eq!(dir.read(2), None);
+    }
+
+    #[test]
+    fn keeps_daily_files_from_today() {
+        let dir = LogDir::new();
+        fs::write(dir.log(), "old\n").unwrap();
+
+        let mut file = dir.open(None, true, 2);
+        write(&mut file, "a");
+
+        assert_eq!(dir.read(0), Some(format!("old\n{}", records("a").unwrap())));
+        assert_eq!(dir.read(1), None);
+    }
+}
@@ -0,0 +1,524 @@
+use std::collections::BTreeMap;
+use std::fmt::Write as _;
+use std::io::{self, BufRead, BufReader, ErrorKind, Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::sync::atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use crate::log::{LogLevel, Loggers};
+
+/// Time a scrape has to send its request and read the response
+const SCRAPE_DEADLINE: Duration = Duration::from_secs(5);
+
+/// Largest request line and headers of a scrape, in bytes
+const MAX_REQUEST_SIZE: u64 = 8 * 1024;
+
+/// Scrapes answered at once, each on its own thread. Connections beyond that are closed.
+const MAX_CONCURRENT_SCRAPES: usize = 4;
+
+/// Upper bounds, in seconds, of the command latency histogram buckets
+const LATENCY_BUCKETS: [f64; 10] = [0.0001, 0.0005, 0.001, 0.005, 0.01, 0.05, 0.1, 0.5, 1.0, 5.0];
+
+#[derive(Debug, Default)]
+struct CommandStats {
+    count: u64,
+    /// Cumulative count per bucket of `LATENCY_BUCKETS`
+    buckets: [u64; LATENCY_BUCKETS.len()],
+    duration_sum: Duration,
+}
+
+/// Server metrics, rendered in the Prometheus text exposition format by `Metrics::render`.
+///
+/// There is no buffer pool or WAL yet, so there are no cache hit rate or fsync latency metrics.
+#[derive(Debug, Default)]
+pub struct Metrics {
+    connections_active: AtomicU64,
+    connections_total: AtomicU64,
+    connections_refused: AtomicU64,
+    bytes_received: AtomicU64,
+    bytes_sent: AtomicU64,
+    commands: Mutex<BTreeMap<&'static str, CommandStats>>,
+    errors: Mutex<BTreeMap<&'static str, u64>>,
+}
+
+impl Metrics {
+    pub fn connection_opened(&self) -> () {
+        self.connections_active.fetch_add(1, Ordering::Relaxed);
+        self.connections_total.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn connection_closed(&self) -> () {
+        self.connections_active.fetch_sub(1, Ordering::Relaxed);
+    }
+
+    pub fn connection_refused(&self) -> () {
+        self.connections_refused.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn command_executed(&self, command: &'static str, duration: Duration) -> () {
+        let mut commands = self.commands.lock().unwrap();
+        let stats = commands.entry(command).or_default();
+
+        stats.count += 1;
+        stats.duration_sum += duration;
+
+        for (i, bound) in LATENCY_BUCKETS.iter().enumerate() {
+            if duration.as_secs_f64() <= *bound {
+                stats.buckets[i] += 1;
+            }
+        }
+    }
+
+    pub fn error(&self, code: &'static str) -> () {
+        *self.errors.lock().unwrap().entry(code).or_default() += 1;
+    }
+
+    /// Render every metric, plus the log pipeline counters of `loggers`
+    pub fn render(&self, loggers: &Loggers) -> String {
+        let mut out = String::new();
+
+        render_metric(
+            &mut out,
+            "squeef_connections_active",
+            "gauge",
+            "Connections currently open",
+            &[("", self.connections_active.load(Ordering::Relaxed))],
+        );
+        render_metric(
+            &mut out,
+            "squeef_connections_total",
+            "counter",
+            "Connections accepted",
+            &[("", self.connections_total.load(Ordering::Relaxed))],
+        );
+        render_metric(
+            &mut out,
+            "squeef_connections_refused_total",
+            "counter",
+            "Connections refused because the connection limit was reached",
+            &[("", self.connections_refused.load(Ordering::Relaxed))],
+        );
+        render_metric(
+            &mut out,
+            "squeef_received_bytes_total",
+            "counter",
+            "Bytes received from clients",
+            &[("", self.bytes_received.load(Ordering::Relaxed))],
+        );
+        render_metric(
+            &mut out,
+            "squeef_sent_bytes_total",
+            "counter",
+            "Bytes sent to clients",
+            &[("", self.bytes_sent.load(Ordering::Relaxed))],
+        );
+
+        {
+            let commands = self.commands.lock().unwrap();
+
+            let counts: Vec<(String, u64)> = commands
+                .iter()
+                .map(|(command, stats)| (format!("command=\"{}\"", command), stats.count))
+                .collect();
+
+            render_metric(
+                &mut out,
+                "squeef_commands_total",
+                "counter",
+                "Commands executed, by command",
+                &counts
+                    .iter()
+                    .map(|(labels, count)| (labels.as_str(), *count))
+                    .collect::<Vec<_>>(),
+            );
+
+            let name = "squeef_command_duration_seconds";
+            let _ = writeln!(out, "# HELP {} Command execution time, by command", name);
+            let _ = writeln!(out, "# TYPE {} histogram", name);
+
+            for (command, stats) in commands.iter() {
+                for (bound, count) in LATENCY_BUCKETS.iter().zip(stats.buckets) {
+                    let _ = writeln!(
+                        out,
+                        "{}_bucket{{command=\"{}\",le=\"{}\"}} {}",
+                        name, command, bound, count
+                    );
+                }
+
+                let _ = writeln!(
+                    out,
+                    "{}_bucket{{command=\"{}\",le=\"+Inf\"}} {}",
+                    name, command, stats.count
+                );
+                let _ = writeln!(
+                    out,
+                    "{}_sum{{command=\"{}\"}} {}",
+                    name,
+                    command,
+                    stats.duration_sum.as_secs_f64()
+                );
+                let _ = writeln!(
+                    out,
+                    "{}_count{{command=\"{}\"}} {}",
+                    name, command, stats.count
+                );
+            }
+        }
+
+        {
+            let errors = self.errors.lock().unwrap();
+
+            let counts: Vec<(String, u64)> = errors
+                .iter()
+                .map(|(code, count)| (format!("code=\"{}\"", code), *count))
+                .collect();
+
+            render_metric(
+                &mut out,
+                "squeef_errors_total",
+                "counter",
+                "Failed requests, by error code",
+                &counts
+                    .iter()
+                    .map(|(labels, count)| (labels.as_str(), *count))
+                    .collect::<Vec<_>>(),
+            );
+        }
+
+        render_metric(
+            &mut out,
+            "squeef_log_dropped_total",
+            "counter",
+            "Log records dropped because the log queue was full",
+            &[("", loggers.dropped_count())],
+        );
+        render_metric(
+            &mut out,
+            "squeef_log_write_errors_total",
+            "counter",
+            "Log records which failed to be written",
+            &[("", loggers.write_error_count())],
+        );
+
+        return out;
+    }
+}
+
+/// Render one metric family. Each sample is given as its labels, without braces, and its value.
+fn render_metric(out: &mut String, name: &str, kind: &str, help: &str, samples: &[(&str, u64)]) {
+    let _ = writeln!(out, "# HELP {} {}", name, help);
+    let _ = writeln!(out, "# TYPE {} {}", name, kind);
+
+    for (labels, value) in samples {
+        match labels.is_empty() {
+            true => {
+                let _ = writeln!(out, "{} {}", name, value);
+            }
+            false => {
+                let _ = writeln!(out, "{}{{{}}} {}", name, labels, value);
+            }
+        }
+    }
+}
+
+/// Stream counting the bytes going through it in `Metrics`
+#[derive(Debug)]
+pub struct MeteredStream<S> {
+    inner: S,
+    metrics: Arc<Metrics>,
+}
+
+impl<S> MeteredStream<S> {
+    pub fn new(inner: S, metrics: Arc<Metrics>) -> MeteredStream<S> {
+        MeteredStream { inner, metrics }
+    }
+
+    pub fn get_ref(&self) -> &S {
+        return &self.inner;
+    }
+}
+
+impl<S: Read> Read for MeteredStream<S> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let n = self.inner.read(buf)?;
+        self.metrics
+            .bytes_received
+            .fetch_add(n as u64, Ordering::Relaxed);
+        return Ok(n);
+    }
+}
+
+impl<S: Write> Write for MeteredStream<S> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let n = self.inner.write(buf)?;
+        self.metrics
+            .bytes_sent
+            .fetch_add(n as u64, Ordering::Relaxed);
+        return Ok(n);
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        return self.inner.flush();
+    }
+}
+
+/// Bind the listener of the `/metrics` HTTP endpoint
+pub fn bind(addr: &str) -> io::Result<TcpListener> {
+    let listener = TcpListener::bind(addr)?;
+    listener.set_nonblocking(true)?;
+    return Ok(listener);
+}
+
+/// Answer scrapes on `listener` until `shutdown` is set. Each scrape gets a thread, so that a
+/// slow client can't hold up the others.
+pub fn serve(
+    listener: TcpListener,
+    metrics: Arc<Metrics>,
+    loggers: Loggers,
+    shutdown: Arc<AtomicBool>,
+    poll_interval: Duration,
+) -> () {
+    let active = Arc::new(AtomicUsize::new(0));
+
+    while !shutdown.load(Ordering::SeqCst) {
+        match listener.accept() {
+            Ok((stream, peer_addr)) => {
+                if active.load(Ordering::SeqCst) >= MAX_CONCURRENT_SCRAPES {
+                    loggers.log(
+                        LogLevel::DEBUG,
+                        &format!(
+                            "[{}] Refused metrics scrape, {} already in progress",
+                            peer_addr, MAX_CONCURRENT_SCRAPES
+                        ),
+                    );
+                    continue;
+                }
+
+                active.fetch_add(1, Ordering::SeqCst);
+
+                let scrape_active = active.clone();
+                let scrape_metrics = metrics.clone();
+                let scrape_loggers = loggers.clone();
+
+                let spawned = thread::Builder::new()
+                    .name(String::from("squeef-scrape"))
+                    .spawn(move || {
+                        if let Err(e) = answer_scrape(stream, &scrape_metrics, &scrape_loggers) {
+                            scrape_loggers.log(
+                                LogLevel::WARN,
+                                &format!("[{}] Failed to serve metrics: {}", peer_addr, e),
+                            );
+                        }
+
+                        scrape_active.fetch_sub(1, Ordering::SeqCst);
+                    });
+
+                if let Err(e) = spawned {
+                    active.fetch_sub(1, Ordering::SeqCst);
+                    loggers.log(
+                        LogLevel::WARN,
+                        &format!("[{}] Failed to serve metrics: {}", peer_addr, e),
+                    );
+                }
+            }
+            Err(e) if e.kind() == ErrorKind::WouldBlock => thread::sleep(poll_interval),
+            Err(e) => {
+                loggers.log(LogLevel::ERROR, &format!("Metrics listener failed: {}", e));
+                return;
+            }
+        }
+    }
+}
+
+fn answer_scrape(stream: TcpStream, metrics: &Metrics, loggers: &Loggers) -> io::Result<()> {
+    stream.set_nonblocking(false)?;
+
+    let deadline = Instant::now() + SCRAPE_DEADLINE;
+    let mut reader = BufReader::new(DeadlineReader { stream, deadline }.take(MAX_REQUEST_SIZE));
+
+    let mut request_line = String::new();
+    reader.read_line(&mut request_line)?;
+
+    // Skip the headers, nothing in them matters here
+    loop {
+        let mut header = String::new();
+
+        if reader.read_line(&mut header)? == 0 || header.trim_end().is_empty() {
+            break;
+        }
+    }
+
+    if reader.get_ref().limit() == 0 {
+        return Err(io::Error::new(
+            ErrorKind::InvalidData,
+            format!("Request larger than {} bytes", MAX_REQUEST_SIZE),
+        ));
+    }
+
+    let mut parts = request_line.split_whitespace();
+
+    let (status, content_type, body) = match (parts.next(), parts.next()) {
+        (Some("GET"), Some("/metrics")) => (
+            "200 OK",
+            "text/plain; version=0.0.4",
+            metrics.render(loggers),
+        ),
+        (Some("GET"), Some(_)) => ("404 Not Found", "text/plain", String::from("Not Found\n")),
+        _ => (
+            "405 Method Not Allowed",
+            "text/plain",
+            String::from("Method Not Allowed\n"),
+        ),
+    };
+
+    let mut stream = reader.into_inner().into_inner().stream;
+    stream.set_write_timeout(Some(remaining(deadline)?))?;
+
+    write!(
+        stream,
+        "HTTP/1.1 {}\r\nContent-Type: {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+        status,
+        content_type,
+        body.len(),
+        body
+    )?;
+
+    return stream.flush();
+}
+
+/// Reads which fail once `deadline` passes, however slowly the peer sends
+struct DeadlineReader {
+    stream: TcpStream,
+    deadline: Instant,
+}
+
+impl Read for DeadlineReader {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        self.stream
+            .set_read_timeout(Some(remaining(self.deadline)?))?;
+
+        return match self.stream.read(buf) {
+            // How a read timeout is reported depends on the platform
+            Err(e) if matches!(e.kind(), ErrorKind::WouldBlock | ErrorKind::TimedOut) => {
+                Err(deadline_passed())
+            }
+            res => res,
+        };
+    }
+}
+
+fn remaining(deadline: Instant) -> io::Result<Duration> {
+    return match deadline.checked_duration_since(Instant::now()) {
+        Some(remaining) if !remaining.is_zero() => Ok(remaining),
+        _ => Err(deadline_passed()),
+    };
+}
+
+fn deadline_passed() -> io::Error {
+    return io::Error::new(
+        ErrorKind::TimedOut,
+        format!("Scrape took longer than {}s", SCRAPE_DEADLINE.as_secs()),
+    );
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn lines(rendered: &str, name: &str) -> Vec<String> {
+        return rendered
+            .lines()
+            .filter(|line| line.starts_with(name) || line.contains(&format!(" {} ", name)))
+            .map(String::from)
+            .collect();
+    }
+
+    #[test]
+    fn renders_counters_and_gauges() {
+        let metrics = Metrics::default();
+        metrics.connection_opened();
+        metrics.connection_opened();
+        metrics.connection_closed();
+        metrics.error("bad_request");
+        metrics.error("bad_request");
+        metrics.error("permission_denied");
+
+        let rendered = metrics.render(&Loggers::from(vec![]));
+
+        assert_eq!(
+            lines(&rendered, "squeef_connections_active"),
+            [
+                "# HELP squeef_connections_active Connections currently open",
+                "# TYPE squeef_connections_active gauge",
+                "squeef_connections_active 1",
+            ]
+        );
+        assert_eq!(
+            lines(&rendered, "squeef_connections_total"),
+            [
+                "# HELP squeef_connections_total Connections accepted",
+                "# TYPE squeef_connections_total counter",
+                "squeef_connections_total 2",
+            ]
+        );
+        assert_eq!(
+            lines(&rendered, "squeef_errors_total"),
+            [
+                "# HELP squeef_errors_total Failed requests, by error code",
+                "# TYPE squeef_errors_total counter",
+                "squeef_errors_total{code=\"bad_request\"} 2",
+                "squeef_errors_total{code=\"permission_denied\"} 1",
+            ]
+        );
+    }
+
+    #[test]
+    fn renders_latency_histogram() {
+        let metrics = Metrics::default();
+        metrics.command_executed("begin", Duration::from_millis(2));
+        metrics.command_executed("begin", Duration::from_millis(300));
+
+        let rendered = metrics.render(&Loggers::from(vec![]));
+
+        assert_eq!(
+            lines(&rendered, "squeef_commands_total"),
+            [
+                "# HELP squeef_commands_total Commands executed, by command",
+                "# TYPE squeef_commands_total counter",
+                "squeef_commands_total{command=\"begin\"} 2",
+            ]
+        );
+
+        let histogram = "squeef_command_duration_seconds";
+        let expected_buckets = [
+            ("0.0001", 0),
+            ("0.0005", 0),
+            ("0.001", 0),
+            ("0.005", 1),
+            ("0.01", 1),
+            ("0.05", 1),
+            ("0.1", 1),
+            ("0.5", 2),
+            ("1", 2),
+            ("5", 2),
+            ("+Inf", 2),
+        ];
+
+        let mut expected = vec![
+            format!("# HELP {} Command execution time, by command", histogram),
+            format!("# TYPE {} histogram", histogram),
+        ];
+        for (le, count) in expected_buckets {
+            expected.push(format!(
+                "{}_bucket{{command=\"begin\",le=\"{}\"}} {}",
+                histogram, le, count
+            ));
+        }
+        expected.push(format!("{}_sum{{command=\"begin\"}} 0.302", histogram));
+        expected.push(format!("{}_count{{command=\"begin\"}} 2", histogram));
+
+        assert_eq!(lines(&rendered, histogram), expected);
+    }
+}
@@ -0,0 +1,230 @@
+use std::collections::{BTreeMap, BTreeSet};
+use std::sync::Mutex;
+
+/// Transaction IDs are handed out in increasing order, starting at 1
+pub type TxnId = u64;
+
+/// Which transactions' changes a reader sees: those which had finished when the snapshot was
+/// taken, plus the reader's own. Aborted transactions remove their versions as they roll back, so
+/// a finished transaction whose versions are still around committed.
+#[derive(Debug, Clone)]
+pub struct Snapshot {
+    /// Transaction the snapshot belongs to, `None` for reads outside of a transaction
+    txn: Option<TxnId>,
+
+    /// Transactions from this ID on started after the snapshot was taken
+    xmax: TxnId,
+
+    /// Transactions still running when the snapshot was taken
+    active: BTreeSet<TxnId>,
+}
+
+impl Snapshot {
+    pub fn txn(&self) -> Option<TxnId> {
+        return self.txn;
+    }
+
+    /// Whether changes made by `id` are visible
+    pub fn sees(&self, id: TxnId) -> bool {
+        return Some(id) == self.txn || (id < self.xmax && !self.active.contains(&id));
+    }
+
+    /// Oldest transaction whose changes may be invisible to this snapshot
+    fn xmin(&self) -> TxnId {
+        return self.active.first().copied().unwrap_or(self.xmax);
+    }
+}
+
+/// Version of a value, created by one transaction and possibly deleted by a later one
+#[derive(Debug, Clone)]
+pub struct Version<T> {
+    pub value: T,
+    pub created_by: TxnId,
+    pub deleted_by: Option<TxnId>,
+}
+
+impl<T> Version<T> {
+    pub fn new(value: T, created_by: TxnId) -> Version<T> {
+        Version {
+            value,
+            created_by,
+            deleted_by: None,
+        }
+    }
+
+    pub fn visible_to(&self, snapshot: &Snapshot) -> bool {
+        return snapshot.sees(self.created_by)
+            && !self.deleted_by.is_some_and(|id| snapshot.sees(id));
+    }
+}
+
+#[derive(Debug)]
+struct TxnState {
+    next_id: TxnId,
+
+    /// Running transactions, with the `xmin` of their latest snapshot
+    active: BTreeMap<TxnId, TxnId>,
+}
+
+/// Hands out transaction IDs and snapshots, and keeps track of the running transactions
+#[derive(Debug)]
+pub struct TxnManager {
+    state: Mutex<TxnState>,
+}
+
+impl TxnManager {
+    pub fn new() -> TxnManager {
+        TxnManager {
+            state: Mutex::new(TxnState {
+                next_id: 1,
+                active: BTreeMap::new(),
+            }),
+        }
+    }
+
+    /// Start a transaction and return its first snapshot
+    pub fn begin(&self) -> Snapshot {
+        let mut state = self.state.lock().unwrap();
+
+        let id = state.next_id;
+        state.next_id += 1;
+
+        let snapshot = Snapshot {
+            txn: Some(id),
+            xmax: id,
+            active: state.active.keys().copied().collect(),
+        };
+
+        state.active.insert(id, snapshot.xmin());
+
+        return snapshot;
+    }
+
+    /// New snapshot for the running transaction `id`, seeing everything committed so far
+    pub fn refresh(&self, id: TxnId) -> Snapshot {
+        let mut state = self.state.lock().unwrap();
+
+        let snapshot = Snapshot {
+            txn: Some(id),
+            xmax: state.next_id,
+            active: state.active.keys().copied().filter(|a| *a != id).collect(),
+        };
+
+        state.active.insert(id, snapshot.xmin().min(id));
+
+        return snapshot;
+    }
+
+    /// Snapshot of the changes committed so far, for reads outside of a transaction
+    pub fn snapshot(&self) -> Snapshot {
+        let state = self.state.lock().unwrap();
+
+        return Snapshot {
+            txn: None,
+            xmax: state.next_id,
+            active: state.active.keys().copied().collect(),
+        };
+    }
+
+    /// Mark `id` as finished. Its versions must already be removed if it aborted.
+    pub fn finish(&self, id: TxnId) -> () {
+        self.state.lock().unwrap().active.remove(&id);
+    }
+
+    pub fn is_active(&self, id: TxnId) -> bool {
+        return self.state.lock().unwrap().active.contains_key(&id);
+    }
+
+    /// Versions deleted by a transaction older than this are invisible to every running
+    /// transaction, and can be removed
+    pub fn horizon(&self) -> TxnId {
+        let state = self.state.lock().unwrap();
+
+        return state
+            .active
+            .values()
+            .copied()
+            .min()
+            .unwrap_or(state.next_id);
+    }
+}
+
+impl Default for TxnManager {
+    fn default() -> TxnManager {
+        TxnManager::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn snapshot_sees_finished_transactions_only() {
+        let txns = TxnManager::new();
+
+        let finished = txns.begin().txn().unwrap();
+        txns.finish(finished);
+        let running = txns.begin().txn().unwrap();
+
+        let snapshot = txns.begin();
+        let later = txns.begin().txn().unwrap();
+
+        assert!(snapshot.sees(finished));
+        assert!(!snapshot.sees(running));
+        assert!(!snapshot.sees(later));
+        assert!(snapshot.sees(snapshot.txn().unwrap()));
+    }
+
+    #[test]
+    fn refresh_sees_transactions_finished_since() {
+        let txns = TxnManager::new();
+
+        let other = txns.begin().txn().unwrap();
+        let snapshot = txns.begin();
+        assert!(!snapshot.sees(other));
+
+        txns.finish(other);
+        assert!(!snapshot.sees(other));
+        assert!(txns.refresh(snapshot.txn().unwrap()).sees(other));
+    }
+
+    #[test]
+    fn deleted_version_stays_visible_to_older_snapshots() {
+        let txns = TxnManager::new();
+
+        let creator = txns.begin().txn().unwrap();
+        txns.finish(creator);
+
+        let reader = txns.begin();
+        let deleter = txns.begin().txn().unwrap();
+
+        let version = Version {
+            value: (),
+            created_by: creator,
+            deleted_by: Some(deleter),
+        };
+        txns.finish(deleter);
+
+        assert!(version.visible_to(&reader));
+        assert!(!version.visible_to(&txns.snapshot()));
+    }
+
+    #[test]
+    fn horizon_is_oldest_running_snapshot() {
+        let txns = TxnManager::new();
+
+        let first = txns.begin().txn().unwrap();
+        let second = txns.begin().txn().unwrap();
+        assert_eq!(txns.horizon(), first);
+
+        txns.finish(first);
+        assert_eq!(txns.horizon(), first);
+
+        txns.refresh(second);
+        assert_eq!(txns.horizon(), second);
+
+        txns.finish(second);
+        assert_eq!(txns.horizon(), second + 1);
+    }
+}
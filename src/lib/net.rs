@@ -0,0 +1,214 @@
+use std::fmt::Display;
+use std::fs;
+use std::io::{self, Read, Write};
+use std::net::{SocketAddr, TcpListener, TcpStream};
+use std::os::unix::fs::FileTypeExt;
+use std::os::unix::net::{UnixListener, UnixStream};
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::sync::Arc;
+use std::time::Duration;
+
+use rustls::{ServerConfig, ServerConnection, StreamOwned};
+use serde::Deserialize;
+
+const UNIX_PREFIX: &str = "unix:";
+
+/// Address the server listens on. Parsed from `host:port` for TCP or `unix:/path/to.sock` for a
+/// Unix domain socket.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(try_from = "String")]
+pub enum BindAddress {
+    Tcp(String),
+    Unix(PathBuf),
+}
+
+impl FromStr for BindAddress {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<BindAddress, String> {
+        if let Some(path) = s.strip_prefix(UNIX_PREFIX) {
+            if path.is_empty() {
+                return Err(format!("Missing socket path in bind address [{}]", s));
+            }
+
+            return Ok(BindAddress::Unix(PathBuf::from(path)));
+        }
+
+        match s.rsplit_once(':') {
+            Some((host, port)) if !host.is_empty() && port.parse::<u16>().is_ok() => {
+                return Ok(BindAddress::Tcp(String::from(s)));
+            }
+            _ => {
+                return Err(format!(
+                    "Invalid bind address [{}]. Expected host:port or unix:/path",
+                    s
+                ))
+            }
+        }
+    }
+}
+
+impl TryFrom<String> for BindAddress {
+    type Error = String;
+
+    fn try_from(s: String) -> Result<BindAddress, String> {
+        return BindAddress::from_str(&s);
+    }
+}
+
+impl Display for BindAddress {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            BindAddress::Tcp(addr) => write!(f, "{}", addr),
+            BindAddress::Unix(path) => write!(f, "{}{}", UNIX_PREFIX, path.display()),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum Listener {
+    Tcp(TcpListener),
+    Unix(UnixListener, PathBuf),
+}
+
+impl Listener {
+    /// Bind a non-blocking listener. A stale socket file left over by a previous run is replaced,
+    /// but one which another process still accepts connections on is left alone.
+    pub fn bind(addr: &BindAddress) -> io::Result<Listener> {
+        let listener = match addr {
+            BindAddress::Tcp(addr) => Listener::Tcp(TcpListener::bind(addr.as_str())?),
+            BindAddress::Unix(path) => {
+                if let Ok(metadata) = fs::symlink_metadata(path) {
+                    if metadata.file_type().is_socket() {
+                        remove_stale_socket(path)?;
+                    }
+                }
+
+                Listener::Unix(UnixListener::bind(path)?, path.clone())
+            }
+        };
+
+        match &listener {
+            Listener::Tcp(l) => l.set_nonblocking(true)?,
+            Listener::Unix(l, _) => l.set_nonblocking(true)?,
+        }
+
+        return Ok(listener);
+    }
+
+    /// Address actually bound, with the port resolved if 0 was requested
+    pub fn local_addr(&self) -> io::Result<BindAddress> {
+        return match self {
+            Listener::Tcp(l) => Ok(BindAddress::Tcp(l.local_addr()?.to_string())),
+            Listener::Unix(_, path) => Ok(BindAddress::Unix(path.clone())),
+        };
+    }
+
+    pub fn tcp_addr(&self) -> Option<SocketAddr> {
+        return match self {
+            Listener::Tcp(l) => l.local_addr().ok(),
+            Listener::Unix(..) => None,
+        };
+    }
+
+    /// Accept a connection and put it back in blocking mode. Returns the stream and a printable
+    /// description of the peer.
+    pub fn accept(&self) -> io::Result<(Stream, String)> {
+        return match self {
+            Listener::Tcp(l) => {
+                let (stream, peer_addr) = l.accept()?;
+                stream.set_nonblocking(false)?;
+                Ok((Stream::Tcp(stream), peer_addr.to_string()))
+            }
+            Listener::Unix(l, path) => {
+                let (stream, _) = l.accept()?;
+                stream.set_nonblocking(false)?;
+                // Unix clients are usually unnamed, so identify them by the socket they came in on
+                Ok((
+                    Stream::Unix(stream),
+                    format!("{}{}", UNIX_PREFIX, path.display()),
+                ))
+            }
+        };
+    }
+}
+
+/// Remove the socket file at `path` if nothing is listening on it any more
+fn remove_stale_socket(path: &Path) -> io::Result<()> {
+    return match UnixStream::connect(path) {
+        Ok(_) => Err(io::Error::new(
+            io::ErrorKind::AddrInUse,
+            format!("Another process is listening on [{}]", path.display()),
+        )),
+        Err(e) if e.kind() == io::ErrorKind::ConnectionRefused => fs::remove_file(path),
+        Err(e) => Err(e),
+    };
+}
+
+impl Drop for Listener {
+    fn drop(&mut self) {
+        if let Listener::Unix(_, path) = self {
+            let _ = fs::remove_file(path);
+        }
+    }
+}
+
+/// Connection to a client, whichever kind of listener it came in on
+#[derive(Debug)]
+pub enum Stream {
+    Tcp(TcpStream),
+    Unix(UnixStream),
+    Tls(Box<StreamOwned<ServerConnection, TcpStream>>),
+}
+
+impl Stream {
+    /// Encrypt a TCP stream with TLS. The handshake happens on the first read or write. Unix
+    /// domain sockets never leave the host and stay as they are.
+    pub fn into_tls(self, config: Arc<ServerConfig>) -> io::Result<Stream> {
+        return match self {
+            Stream::Tcp(s) => {
+                let conn = ServerConnection::new(config).map_err(io::Error::other)?;
+
+                Ok(Stream::Tls(Box::new(StreamOwned::new(conn, s))))
+            }
+            stream => Ok(stream),
+        };
+    }
+
+    pub fn set_read_timeout(&self, timeout: Option<Duration>) -> io::Result<()> {
+        return match self {
+            Stream::Tcp(s) => s.set_read_timeout(timeout),
+            Stream::Unix(s) => s.set_read_timeout(timeout),
+            Stream::Tls(s) => s.sock.set_read_timeout(timeout),
+        };
+    }
+}
+
+impl Read for Stream {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        return match self {
+            Stream::Tcp(s) => s.read(buf),
+            Stream::Unix(s) => s.read(buf),
+            Stream::Tls(s) => s.read(buf),
+        };
+    }
+}
+
+impl Write for Stream {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        return match self {
+            Stream::Tcp(s) => s.write(buf),
+            Stream::Unix(s) => s.write(buf),
+            Stream::Tls(s) => s.write(buf),
+        };
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        return match self {
+            Stream::Tcp(s) => s.flush(),
+            Stream::Unix(s) => s.flush(),
+            Stream::Tls(s) => s.flush(),
+        };
+    }
+}
@@ -0,0 +1,172 @@
+use crate::acl::PUBLIC;
+use crate::column::ColumnType;
+use crate::constraint::Check;
+
+/// Commands a row-level security policy applies to
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum PolicyCommand {
+    All,
+    Select,
+    Insert,
+    Update,
+    Delete,
+}
+
+impl PolicyCommand {
+    pub const ALL: [PolicyCommand; 5] = [
+        PolicyCommand::All,
+        PolicyCommand::Select,
+        PolicyCommand::Insert,
+        PolicyCommand::Update,
+        PolicyCommand::Delete,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        return match self {
+            PolicyCommand::All => "ALL",
+            PolicyCommand::Select => "SELECT",
+            PolicyCommand::Insert => "INSERT",
+            PolicyCommand::Update => "UPDATE",
+            PolicyCommand::Delete => "DELETE",
+        };
+    }
+
+    pub fn from_name(name: &str) -> Option<PolicyCommand> {
+        return PolicyCommand::ALL.into_iter().find(|c| c.name() == name);
+    }
+
+    /// Tag of the command on the wire
+    pub fn tag(&self) -> u8 {
+        return match self {
+            PolicyCommand::All => 0x00,
+            PolicyCommand::Select => 0x01,
+            PolicyCommand::Insert => 0x02,
+            PolicyCommand::Update => 0x03,
+            PolicyCommand::Delete => 0x04,
+        };
+    }
+
+    pub fn from_tag(tag: u8) -> Option<PolicyCommand> {
+        return PolicyCommand::ALL.into_iter().find(|c| c.tag() == tag);
+    }
+
+    /// Whether a policy for `self` applies to `command`
+    pub fn covers(&self, command: PolicyCommand) -> bool {
+        return *self == PolicyCommand::All || *self == command;
+    }
+}
+
+/// Row-level security policy of a table, created by its owner. Once a table has policies, a
+/// user only sees and changes the rows which a policy applying to one of their roles allows.
+/// SELECT, UPDATE and DELETE skip the other rows, and INSERT and UPDATE refuse to write them.
+/// The owner of the table and superusers aren't subject to policies.
+///
+/// There's no row storage yet, so policies only describe the schema. DESCRIBE marks them NOT
+/// ENFORCED.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Policy {
+    pub name: String,
+    pub command: PolicyCommand,
+
+    /// Role the policy applies to, PUBLIC for everyone
+    pub role: String,
+
+    /// Rows the policy allows
+    pub predicate: Check,
+}
+
+impl Policy {
+    /// Whether the policy applies to `command` run by a member of `roles`
+    pub fn applies_to(&self, roles: &[&str], command: PolicyCommand) -> bool {
+        return self.command.covers(command)
+            && (self.role == PUBLIC || roles.contains(&self.role.as_str()));
+    }
+
+    /// Whether a row holding `value` in the predicate's column, of `column_type`, is allowed.
+    /// Unlike a CHECK constraint, a NULL allows nothing.
+    pub fn allows(&self, column_type: ColumnType, value: Option<&str>) -> Result<bool, String> {
+        return match value {
+            Some(value) => self.predicate.holds(column_type, Some(value)),
+            None => Ok(false),
+        };
+    }
+
+    /// Fail unless the predicate's value is a literal of `column_type`, the type of its column
+    pub fn check_type(&self, column_type: ColumnType) -> Result<(), String> {
+        if !column_type.accepts(&self.predicate.value) {
+            return Err(format!(
+                "Policy [{}]: value [{}] isn't a {} like column [{}]",
+                self.name,
+                self.predicate.value,
+                column_type.name(),
+                self.predicate.column
+            ));
+        }
+
+        return Ok(());
+    }
+
+    pub fn describe(&self) -> String {
+        return format!(
+            "{} FOR {} TO {} USING ({})",
+            self.name,
+            self.command.name(),
+            self.role,
+            self.predicate.describe()
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::constraint::CompareOp;
+
+    fn policy(command: PolicyCommand, role: &str) -> Policy {
+        return Policy {
+            name: String::from("tenant"),
+            command,
+            role: String::from(role),
+            predicate: Check {
+                column: String::from("tenant_id"),
+                op: CompareOp::Eq,
+                value: String::from("7"),
+            },
+        };
+    }
+
+    #[test]
+    fn applies_to_its_role_and_commands() {
+        let select = policy(PolicyCommand::Select, "tenant_7");
+
+        assert!(select.applies_to(&["tenant_7"], PolicyCommand::Select));
+        assert!(!select.applies_to(&["tenant_7"], PolicyCommand::Delete));
+        assert!(!select.applies_to(&["tenant_8"], PolicyCommand::Select));
+        assert!(!select.applies_to(&[], PolicyCommand::Select));
+
+        let all = policy(PolicyCommand::All, PUBLIC);
+
+        for command in PolicyCommand::ALL {
+            assert!(all.applies_to(&[], command));
+        }
+    }
+
+    #[test]
+    fn allows_rows_matching_the_predicate() {
+        let policy = policy(PolicyCommand::All, PUBLIC);
+
+        assert_eq!(policy.allows(ColumnType::UINT32, Some("7")), Ok(true));
+        assert_eq!(policy.allows(ColumnType::UINT32, Some("8")), Ok(false));
+        assert_eq!(policy.allows(ColumnType::UINT32, None), Ok(false));
+        assert!(policy.allows(ColumnType::UINT32, Some("seven")).is_err());
+    }
+
+    #[test]
+    fn describes_itself() {
+        assert_eq!(
+            policy(PolicyCommand::Insert, "tenant_7").describe(),
+            "tenant FOR INSERT TO tenant_7 USING (tenant_id = 7)"
+        );
+    }
+}
@@ -1,6 +1,20 @@
-use crate::command::Command;
+use crate::acl::{GrantOn, Privilege};
+use crate::auth::{Challenge, Credentials};
+use crate::column::{Column, ColumnType, DefaultValue};
+use crate::command::{AlterTableAction, Command};
+use crate::constraint::{
+    Check, CompareOp, Constraint, ConstraintKind, ForeignKey, ReferentialAction,
+};
+use crate::error::{Error, ErrorCode};
+use crate::lock::LockMode;
+use crate::policy::{Policy, PolicyCommand};
+use crate::table::Table;
+use crate::transaction::IsolationLevel;
 use crate::utils;
 
+/// First byte of an error frame, sent instead of a command's response when it fails
+const ERROR_FRAME: u8 = 0xFF;
+
 #[repr(u8)]
 enum CommandDiscriminant {
     CreateDatabase = 0x00,
@@ -8,6 +22,34 @@ enum CommandDiscriminant {
     CreateTable = 0x02,
     ListDatabases = 0x03,
     ListTables = 0x04,
+    SetClientName = 0x05,
+    Begin = 0x06,
+    Commit = 0x07,
+    Rollback = 0x08,
+    SetIsolationLevel = 0x09,
+    LockTable = 0x0A,
+    Savepoint = 0x0B,
+    ReleaseSavepoint = 0x0C,
+    RollbackToSavepoint = 0x0D,
+    Authenticate = 0x0E,
+    AuthProof = 0x0F,
+    CreateUser = 0x10,
+    AlterUser = 0x11,
+    DropUser = 0x12,
+    CreateRole = 0x13,
+    DropRole = 0x14,
+    GrantRole = 0x15,
+    RevokeRole = 0x16,
+    Grant = 0x17,
+    Revoke = 0x18,
+    DropDatabase = 0x19,
+    DropTable = 0x1A,
+    RenameDatabase = 0x1B,
+    RenameTable = 0x1C,
+    DescribeTable = 0x1D,
+    AlterTable = 0x1E,
+    CreatePolicy = 0x1F,
+    DropPolicy = 0x20,
 }
 
 impl From<u8> for CommandDiscriminant {
@@ -18,6 +60,34 @@ impl From<u8> for CommandDiscriminant {
             0x02 => CommandDiscriminant::CreateTable,
             0x03 => CommandDiscriminant::ListDatabases,
             0x04 => CommandDiscriminant::ListTables,
+            0x05 => CommandDiscriminant::SetClientName,
+            0x06 => CommandDiscriminant::Begin,
+            0x07 => CommandDiscriminant::Commit,
+            0x08 => CommandDiscriminant::Rollback,
+            0x09 => CommandDiscriminant::SetIsolationLevel,
+            0x0A => CommandDiscriminant::LockTable,
+            0x0B => CommandDiscriminant::Savepoint,
+            0x0C => CommandDiscriminant::ReleaseSavepoint,
+            0x0D => CommandDiscriminant::RollbackToSavepoint,
+            0x0E => CommandDiscriminant::Authenticate,
+            0x0F => CommandDiscriminant::AuthProof,
+            0x10 => CommandDiscriminant::CreateUser,
+            0x11 => CommandDiscriminant::AlterUser,
+            0x12 => CommandDiscriminant::DropUser,
+            0x13 => CommandDiscriminant::CreateRole,
+            0x14 => CommandDiscriminant::DropRole,
+            0x15 => CommandDiscriminant::GrantRole,
+            0x16 => CommandDiscriminant::RevokeRole,
+            0x17 => CommandDiscriminant::Grant,
+            0x18 => CommandDiscriminant::Revoke,
+            0x19 => CommandDiscriminant::DropDatabase,
+            0x1A => CommandDiscriminant::DropTable,
+            0x1B => CommandDiscriminant::RenameDatabase,
+            0x1C => CommandDiscriminant::RenameTable,
+            0x1D => CommandDiscriminant::DescribeTable,
+            0x1E => CommandDiscriminant::AlterTable,
+            0x1F => CommandDiscriminant::CreatePolicy,
+            0x20 => CommandDiscriminant::DropPolicy,
             _ => panic!("Unknown command discriminant [{:x}]", byte),
         };
     }
@@ -31,14 +101,49 @@ impl From<CommandDiscriminant> for u8 {
             CommandDiscriminant::CreateTable => 0x02,
             CommandDiscriminant::ListDatabases => 0x03,
             CommandDiscriminant::ListTables => 0x04,
+            CommandDiscriminant::SetClientName => 0x05,
+            CommandDiscriminant::Begin => 0x06,
+            CommandDiscriminant::Commit => 0x07,
+            CommandDiscriminant::Rollback => 0x08,
+            CommandDiscriminant::SetIsolationLevel => 0x09,
+            CommandDiscriminant::LockTable => 0x0A,
+            CommandDiscriminant::Savepoint => 0x0B,
+            CommandDiscriminant::ReleaseSavepoint => 0x0C,
+            CommandDiscriminant::RollbackToSavepoint => 0x0D,
+            CommandDiscriminant::Authenticate => 0x0E,
+            CommandDiscriminant::AuthProof => 0x0F,
+            CommandDiscriminant::CreateUser => 0x10,
+            CommandDiscriminant::AlterUser => 0x11,
+            CommandDiscriminant::DropUser => 0x12,
+            CommandDiscriminant::CreateRole => 0x13,
+            CommandDiscriminant::DropRole => 0x14,
+            CommandDiscriminant::GrantRole => 0x15,
+            CommandDiscriminant::RevokeRole => 0x16,
+            CommandDiscriminant::Grant => 0x17,
+            CommandDiscriminant::Revoke => 0x18,
+            CommandDiscriminant::DropDatabase => 0x19,
+            CommandDiscriminant::DropTable => 0x1A,
+            CommandDiscriminant::RenameDatabase => 0x1B,
+            CommandDiscriminant::RenameTable => 0x1C,
+            CommandDiscriminant::DescribeTable => 0x1D,
+            CommandDiscriminant::AlterTable => 0x1E,
+            CommandDiscriminant::CreatePolicy => 0x1F,
+            CommandDiscriminant::DropPolicy => 0x20,
         };
     }
 }
 
 pub mod request {
     use super::utils;
-    use super::Command;
     use super::CommandDiscriminant;
+    use super::Credentials;
+    use super::IsolationLevel;
+    use super::LockMode;
+    use super::{AlterTableAction, Command};
+    use super::{Check, CompareOp, Constraint, ConstraintKind, ForeignKey, ReferentialAction};
+    use super::{Column, ColumnType, DefaultValue};
+    use super::{GrantOn, Privilege};
+    use super::{Policy, PolicyCommand};
 
     pub fn parse(bytes: &[u8]) -> Result<Command, String> {
         let cmd = CommandDiscriminant::from(bytes[0]);
@@ -49,6 +154,71 @@ pub mod request {
             CommandDiscriminant::CreateTable => parse_create_table(&bytes[1..]),
             CommandDiscriminant::ListDatabases => Ok(Command::ListDatabases),
             CommandDiscriminant::ListTables => Ok(Command::ListTables),
+            CommandDiscriminant::SetClientName => parse_set_client_name(&bytes[1..]),
+            CommandDiscriminant::Begin => Ok(Command::Begin),
+            CommandDiscriminant::Commit => Ok(Command::Commit),
+            CommandDiscriminant::Rollback => Ok(Command::Rollback),
+            CommandDiscriminant::SetIsolationLevel => parse_set_isolation_level(&bytes[1..]),
+            CommandDiscriminant::LockTable => parse_lock_table(&bytes[1..]),
+            CommandDiscriminant::Savepoint => {
+                parse_savepoint_name(&bytes[1..]).map(|name| Command::Savepoint { name })
+            }
+            CommandDiscriminant::ReleaseSavepoint => {
+                parse_savepoint_name(&bytes[1..]).map(|name| Command::ReleaseSavepoint { name })
+            }
+            CommandDiscriminant::RollbackToSavepoint => {
+                parse_savepoint_name(&bytes[1..]).map(|name| Command::RollbackToSavepoint { name })
+            }
+            CommandDiscriminant::Authenticate => parse_authenticate(&bytes[1..]),
+            CommandDiscriminant::AuthProof => parse_auth_proof(&bytes[1..]),
+            CommandDiscriminant::CreateUser => parse_user_credentials(&bytes[1..])
+                .map(|(name, credentials)| Command::CreateUser { name, credentials }),
+            CommandDiscriminant::AlterUser => parse_user_credentials(&bytes[1..])
+                .map(|(name, credentials)| Command::AlterUser { name, credentials }),
+            CommandDiscriminant::DropUser => parse_drop_user(&bytes[1..]),
+            CommandDiscriminant::CreateRole => parse_create_role(&bytes[1..]),
+            CommandDiscriminant::DropRole => parse_drop_role(&bytes[1..]),
+            CommandDiscriminant::GrantRole => {
+                parse_role_member(&bytes[1..]).map(|(role, user)| Command::GrantRole { role, user })
+            }
+            CommandDiscriminant::RevokeRole => parse_role_member(&bytes[1..])
+                .map(|(role, user)| Command::RevokeRole { role, user }),
+            CommandDiscriminant::Grant => {
+                parse_grant(&bytes[1..]).map(|(privileges, on, role)| Command::Grant {
+                    privileges,
+                    on,
+                    role,
+                })
+            }
+            CommandDiscriminant::Revoke => {
+                parse_grant(&bytes[1..]).map(|(privileges, on, role)| Command::Revoke {
+                    privileges,
+                    on,
+                    role,
+                })
+            }
+            CommandDiscriminant::DropDatabase => {
+                parse_drop(&bytes[1..]).map(|(name, if_exists, cascade)| Command::DropDatabase {
+                    name,
+                    if_exists,
+                    cascade,
+                })
+            }
+            CommandDiscriminant::DropTable => {
+                parse_drop(&bytes[1..]).map(|(name, if_exists, cascade)| Command::DropTable {
+                    name,
+                    if_exists,
+                    cascade,
+                })
+            }
+            CommandDiscriminant::RenameDatabase => parse_rename(&bytes[1..])
+                .map(|(name, new_name)| Command::RenameDatabase { name, new_name }),
+            CommandDiscriminant::RenameTable => parse_rename(&bytes[1..])
+                .map(|(name, new_name)| Command::RenameTable { name, new_name }),
+            CommandDiscriminant::DescribeTable => parse_describe_table(&bytes[1..]),
+            CommandDiscriminant::AlterTable => parse_alter_table(&bytes[1..]),
+            CommandDiscriminant::CreatePolicy => parse_create_policy(&bytes[1..]),
+            CommandDiscriminant::DropPolicy => parse_drop_policy(&bytes[1..]),
         };
     }
 
@@ -58,9 +228,142 @@ pub mod request {
         match cmd {
             Command::CreateDatabase { name } => serialise_create_db(name, &mut bytes),
             Command::OpenDatabase { name } => serialise_open_db(name, &mut bytes),
-            Command::CreateTable { name, .. } => serialise_create_table(name, &mut bytes),
+            Command::CreateTable {
+                name,
+                cols,
+                constraints,
+            } => serialise_create_table(name, cols, constraints, &mut bytes),
             Command::ListDatabases => serialise_list_databases(&mut bytes),
             Command::ListTables => serialise_list_tables(&mut bytes),
+            Command::SetClientName { name } => serialise_set_client_name(name, &mut bytes),
+            Command::Begin => bytes.push(CommandDiscriminant::Begin.into()),
+            Command::Commit => bytes.push(CommandDiscriminant::Commit.into()),
+            Command::Rollback => bytes.push(CommandDiscriminant::Rollback.into()),
+            Command::SetIsolationLevel { level } => {
+                serialise_set_isolation_level(level, &mut bytes)
+            }
+            Command::LockTable { name, mode, nowait } => {
+                serialise_lock_table(name, mode, nowait, &mut bytes)
+            }
+            Command::Savepoint { name } => {
+                serialise_savepoint(CommandDiscriminant::Savepoint, name, &mut bytes)
+            }
+            Command::ReleaseSavepoint { name } => {
+                serialise_savepoint(CommandDiscriminant::ReleaseSavepoint, name, &mut bytes)
+            }
+            Command::RollbackToSavepoint { name } => {
+                serialise_savepoint(CommandDiscriminant::RollbackToSavepoint, name, &mut bytes)
+            }
+            Command::Authenticate { user } => {
+                bytes.push(CommandDiscriminant::Authenticate.into());
+                utils::serialise_string(&user, &mut bytes);
+            }
+            Command::AuthProof { proof } => {
+                bytes.push(CommandDiscriminant::AuthProof.into());
+                utils::serialise_bytes(&proof, &mut bytes);
+            }
+            Command::CreateUser { name, credentials } => serialise_user_credentials(
+                CommandDiscriminant::CreateUser,
+                name,
+                credentials,
+                &mut bytes,
+            ),
+            Command::AlterUser { name, credentials } => serialise_user_credentials(
+                CommandDiscriminant::AlterUser,
+                name,
+                credentials,
+                &mut bytes,
+            ),
+            Command::DropUser { name } => {
+                bytes.push(CommandDiscriminant::DropUser.into());
+                utils::serialise_string(&name, &mut bytes);
+            }
+            Command::CreateRole { name } => {
+                bytes.push(CommandDiscriminant::CreateRole.into());
+                utils::serialise_string(&name, &mut bytes);
+            }
+            Command::DropRole { name } => {
+                bytes.push(CommandDiscriminant::DropRole.into());
+                utils::serialise_string(&name, &mut bytes);
+            }
+            Command::GrantRole { role, user } => {
+                bytes.push(CommandDiscriminant::GrantRole.into());
+                utils::serialise_string(&role, &mut bytes);
+                utils::serialise_string(&user, &mut bytes);
+            }
+            Command::RevokeRole { role, user } => {
+                bytes.push(CommandDiscriminant::RevokeRole.into());
+                utils::serialise_string(&role, &mut bytes);
+                utils::serialise_string(&user, &mut bytes);
+            }
+            Command::Grant {
+                privileges,
+                on,
+                role,
+            } => serialise_grant(CommandDiscriminant::Grant, privileges, on, role, &mut bytes),
+            Command::Revoke {
+                privileges,
+                on,
+                role,
+            } => serialise_grant(
+                CommandDiscriminant::Revoke,
+                privileges,
+                on,
+                role,
+                &mut bytes,
+            ),
+            Command::DropDatabase {
+                name,
+                if_exists,
+                cascade,
+            } => serialise_drop(
+                CommandDiscriminant::DropDatabase,
+                name,
+                if_exists,
+                cascade,
+                &mut bytes,
+            ),
+            Command::DropTable {
+                name,
+                if_exists,
+                cascade,
+            } => serialise_drop(
+                CommandDiscriminant::DropTable,
+                name,
+                if_exists,
+                cascade,
+                &mut bytes,
+            ),
+            Command::RenameDatabase { name, new_name } => {
+                bytes.push(CommandDiscriminant::RenameDatabase.into());
+                utils::serialise_string(&name, &mut bytes);
+                utils::serialise_string(&new_name, &mut bytes);
+            }
+            Command::RenameTable { name, new_name } => {
+                bytes.push(CommandDiscriminant::RenameTable.into());
+                utils::serialise_string(&name, &mut bytes);
+                utils::serialise_string(&new_name, &mut bytes);
+            }
+            Command::DescribeTable { name } => {
+                bytes.push(CommandDiscriminant::DescribeTable.into());
+                utils::serialise_string(&name, &mut bytes);
+            }
+            Command::AlterTable { name, action } => serialise_alter_table(name, action, &mut bytes),
+            Command::CreatePolicy { table, policy } => {
+                bytes.push(CommandDiscriminant::CreatePolicy.into());
+                utils::serialise_string(&table, &mut bytes);
+                utils::serialise_string(&policy.name, &mut bytes);
+                bytes.push(policy.command.tag());
+                utils::serialise_string(&policy.role, &mut bytes);
+                utils::serialise_string(&policy.predicate.column, &mut bytes);
+                bytes.push(policy.predicate.op.tag());
+                utils::serialise_string(&policy.predicate.value, &mut bytes);
+            }
+            Command::DropPolicy { table, name } => {
+                bytes.push(CommandDiscriminant::DropPolicy.into());
+                utils::serialise_string(&table, &mut bytes);
+                utils::serialise_string(&name, &mut bytes);
+            }
         }
 
         return bytes;
@@ -69,7 +372,7 @@ pub mod request {
     fn parse_create_db(bytes: &[u8]) -> Result<Command, String> {
         let (bytes, name) = utils::parse_string(bytes)?;
 
-        if bytes.len() > 0 {
+        if !bytes.is_empty() {
             return Err(format!(
                 "Remaining data after CREATE_DB command. Got [{:x?}]",
                 bytes
@@ -82,7 +385,7 @@ pub mod request {
     fn parse_open_db(bytes: &[u8]) -> Result<Command, String> {
         let (bytes, name) = utils::parse_string(bytes)?;
 
-        if bytes.len() > 0 {
+        if !bytes.is_empty() {
             return Err(format!(
                 "Remaining data after OPEN DATABASE command. Got [{:x?}]",
                 bytes
@@ -93,16 +396,546 @@ pub mod request {
     }
 
     fn parse_create_table(bytes: &[u8]) -> Result<Command, String> {
-        let (bytes, name) = utils::parse_string(bytes)?;
+        let (mut bytes, name) = utils::parse_string(bytes)?;
+
+        let mut cols = vec![];
+        let mut constraints = vec![];
+
+        // Tables created by older clients have no columns
+        if !bytes.is_empty() {
+            let (new_bytes, col_count) = utils::parse_u32(bytes)?;
+            bytes = new_bytes;
+
+            for _ in 0..col_count {
+                let (new_bytes, col) = parse_column(bytes)?;
+                bytes = new_bytes;
+                cols.push(col);
+            }
+
+            let (new_bytes, constraint_count) = utils::parse_u32(bytes)?;
+            bytes = new_bytes;
 
-        if bytes.len() > 0 {
+            for _ in 0..constraint_count {
+                let (new_bytes, constraint) = parse_constraint(bytes)?;
+                bytes = new_bytes;
+                constraints.push(constraint);
+            }
+        }
+
+        if !bytes.is_empty() {
             return Err(format!(
                 "Remaining data after CREATE_TABLE command. Got [{:x?}]",
                 bytes
             ));
         }
 
-        return Ok(Command::CreateTable { name, cols: vec![] });
+        return Ok(Command::CreateTable {
+            name,
+            cols,
+            constraints,
+        });
+    }
+
+    fn parse_column(bytes: &[u8]) -> Result<(&[u8], Column), String> {
+        let (bytes, name) = utils::parse_string(bytes)?;
+        let (bytes, column_type) = parse_column_type(bytes)?;
+        let (bytes, is_optional) = utils::parse_bool(bytes)?;
+        let (bytes, is_primary_key) = utils::parse_bool(bytes)?;
+        let (bytes, is_foreign_key) = utils::parse_bool(bytes)?;
+        let (bytes, default) = parse_default(bytes)?;
+
+        return Ok((
+            bytes,
+            Column {
+                name,
+                column_type,
+                is_optional,
+                is_primary_key,
+                is_foreign_key,
+                default,
+            },
+        ));
+    }
+
+    fn parse_column_type(bytes: &[u8]) -> Result<(&[u8], ColumnType), String> {
+        return match bytes.first() {
+            Some(tag) => match ColumnType::from_tag(*tag) {
+                Some(column_type) => Ok((&bytes[1..], column_type)),
+                None => Err(format!("Unknown column type [{:x}]", tag)),
+            },
+            None => Err(String::from("Data too short to hold a column type")),
+        };
+    }
+
+    fn parse_default(bytes: &[u8]) -> Result<(&[u8], Option<DefaultValue>), String> {
+        return match bytes.first() {
+            Some(0x00) => Ok((&bytes[1..], None)),
+            Some(0x01) => {
+                let (bytes, literal) = utils::parse_string(&bytes[1..])?;
+                Ok((bytes, Some(DefaultValue::Literal(literal))))
+            }
+            Some(0x02) => Ok((&bytes[1..], Some(DefaultValue::Now))),
+            Some(byte) => Err(format!("Unknown default value [{:x}]", byte)),
+            None => Err(String::from("Data too short to hold a default value")),
+        };
+    }
+
+    fn parse_constraint(bytes: &[u8]) -> Result<(&[u8], Constraint), String> {
+        let (mut bytes, has_name) = utils::parse_bool(bytes)?;
+
+        let name = match has_name {
+            true => {
+                let (new_bytes, name) = utils::parse_string(bytes)?;
+                bytes = new_bytes;
+                Some(name)
+            }
+            false => None,
+        };
+
+        let (bytes, kind) = match bytes.first() {
+            Some(0x00) => {
+                let (bytes, columns) = parse_names(&bytes[1..])?;
+
+                (bytes, ConstraintKind::Unique { columns })
+            }
+            Some(0x01) => {
+                let (bytes, column) = utils::parse_string(&bytes[1..])?;
+
+                let op = match bytes.first() {
+                    Some(tag) => CompareOp::from_tag(*tag)
+                        .ok_or_else(|| format!("Unknown comparison [{:x}]", tag))?,
+                    None => return Err(String::from("Data too short to hold a comparison")),
+                };
+
+                let (bytes, value) = utils::parse_string(&bytes[1..])?;
+
+                (bytes, ConstraintKind::Check(Check { column, op, value }))
+            }
+            Some(0x02) => {
+                let (bytes, columns) = parse_names(&bytes[1..])?;
+                let (bytes, ref_table) = utils::parse_string(bytes)?;
+                let (bytes, ref_columns) = parse_names(bytes)?;
+                let (bytes, on_delete) = parse_referential_action(bytes)?;
+                let (bytes, on_update) = parse_referential_action(bytes)?;
+
+                let fk = ForeignKey {
+                    columns,
+                    ref_table,
+                    ref_columns,
+                    on_delete,
+                    on_update,
+                };
+
+                (bytes, ConstraintKind::ForeignKey(fk))
+            }
+            Some(byte) => return Err(format!("Unknown constraint [{:x}]", byte)),
+            None => return Err(String::from("Data too short to hold a constraint")),
+        };
+
+        return Ok((bytes, Constraint { name, kind }));
+    }
+
+    /// u32 count followed by the names
+    fn parse_names(bytes: &[u8]) -> Result<(&[u8], Vec<String>), String> {
+        let (mut bytes, count) = utils::parse_u32(bytes)?;
+        let mut names = vec![];
+
+        for _ in 0..count {
+            let (new_bytes, name) = utils::parse_string(bytes)?;
+            bytes = new_bytes;
+            names.push(name);
+        }
+
+        return Ok((bytes, names));
+    }
+
+    fn parse_referential_action(bytes: &[u8]) -> Result<(&[u8], ReferentialAction), String> {
+        return match bytes.first() {
+            Some(tag) => match ReferentialAction::from_tag(*tag) {
+                Some(action) => Ok((&bytes[1..], action)),
+                None => Err(format!("Unknown referential action [{:x}]", tag)),
+            },
+            None => Err(String::from("Data too short to hold a referential action")),
+        };
+    }
+
+    fn parse_alter_table(bytes: &[u8]) -> Result<Command, String> {
+        let (bytes, name) = utils::parse_string(bytes)?;
+
+        let (bytes, action) = match bytes.first() {
+            Some(0x00) => {
+                let (mut bytes, column) = parse_column(&bytes[1..])?;
+                let (new_bytes, constraint_count) = utils::parse_u32(bytes)?;
+                bytes = new_bytes;
+
+                let mut constraints = vec![];
+
+                for _ in 0..constraint_count {
+                    let (new_bytes, constraint) = parse_constraint(bytes)?;
+                    bytes = new_bytes;
+                    constraints.push(constraint);
+                }
+
+                (
+                    bytes,
+                    AlterTableAction::AddColumn {
+                        column,
+                        constraints,
+                    },
+                )
+            }
+            Some(0x01) => {
+                let (bytes, name) = utils::parse_string(&bytes[1..])?;
+                (bytes, AlterTableAction::DropColumn { name })
+            }
+            Some(0x02) => {
+                let (bytes, name) = utils::parse_string(&bytes[1..])?;
+                let (bytes, new_name) = utils::parse_string(bytes)?;
+                (bytes, AlterTableAction::RenameColumn { name, new_name })
+            }
+            Some(0x03) => {
+                let (bytes, name) = utils::parse_string(&bytes[1..])?;
+                let (bytes, column_type) = parse_column_type(bytes)?;
+                (
+                    bytes,
+                    AlterTableAction::AlterColumnType { name, column_type },
+                )
+            }
+            Some(0x04) => {
+                let (bytes, name) = utils::parse_string(&bytes[1..])?;
+                let (bytes, default) = parse_default(bytes)?;
+                (bytes, AlterTableAction::SetDefault { name, default })
+            }
+            Some(0x05) => {
+                let (bytes, name) = utils::parse_string(&bytes[1..])?;
+                let (bytes, not_null) = utils::parse_bool(bytes)?;
+                (bytes, AlterTableAction::SetNotNull { name, not_null })
+            }
+            Some(0x06) => {
+                let (bytes, constraint) = parse_constraint(&bytes[1..])?;
+                (bytes, AlterTableAction::AddConstraint { constraint })
+            }
+            Some(0x07) => {
+                let (bytes, name) = utils::parse_string(&bytes[1..])?;
+                (bytes, AlterTableAction::DropConstraint { name })
+            }
+            Some(byte) => return Err(format!("Unknown ALTER TABLE action [{:x}]", byte)),
+            None => return Err(String::from("Data too short to hold an ALTER TABLE action")),
+        };
+
+        if !bytes.is_empty() {
+            return Err(format!(
+                "Remaining data after ALTER TABLE command. Got [{:x?}]",
+                bytes
+            ));
+        }
+
+        return Ok(Command::AlterTable { name, action });
+    }
+
+    fn parse_create_policy(bytes: &[u8]) -> Result<Command, String> {
+        let (bytes, table) = utils::parse_string(bytes)?;
+        let (bytes, name) = utils::parse_string(bytes)?;
+
+        let command = match bytes.first() {
+            Some(tag) => PolicyCommand::from_tag(*tag)
+                .ok_or_else(|| format!("Unknown policy command [{:x}]", tag))?,
+            None => return Err(String::from("Data too short to hold a policy command")),
+        };
+
+        let (bytes, role) = utils::parse_string(&bytes[1..])?;
+        let (bytes, column) = utils::parse_string(bytes)?;
+
+        let op = match bytes.first() {
+            Some(tag) => CompareOp::from_tag(*tag)
+                .ok_or_else(|| format!("Unknown comparison [{:x}]", tag))?,
+            None => return Err(String::from("Data too short to hold a comparison")),
+        };
+
+        let (bytes, value) = utils::parse_string(&bytes[1..])?;
+
+        if !bytes.is_empty() {
+            return Err(format!(
+                "Remaining data after CREATE POLICY command. Got [{:x?}]",
+                bytes
+            ));
+        }
+
+        let policy = Policy {
+            name,
+            command,
+            role,
+            predicate: Check { column, op, value },
+        };
+
+        return Ok(Command::CreatePolicy { table, policy });
+    }
+
+    fn parse_drop_policy(bytes: &[u8]) -> Result<Command, String> {
+        let (bytes, table) = utils::parse_string(bytes)?;
+        let (bytes, name) = utils::parse_string(bytes)?;
+
+        if !bytes.is_empty() {
+            return Err(format!(
+                "Remaining data after DROP POLICY command. Got [{:x?}]",
+                bytes
+            ));
+        }
+
+        return Ok(Command::DropPolicy { table, name });
+    }
+
+    fn parse_describe_table(bytes: &[u8]) -> Result<Command, String> {
+        let (bytes, name) = utils::parse_string(bytes)?;
+
+        if !bytes.is_empty() {
+            return Err(format!(
+                "Remaining data after DESCRIBE command. Got [{:x?}]",
+                bytes
+            ));
+        }
+
+        return Ok(Command::DescribeTable { name });
+    }
+
+    fn parse_set_client_name(bytes: &[u8]) -> Result<Command, String> {
+        let (bytes, name) = utils::parse_string(bytes)?;
+
+        if !bytes.is_empty() {
+            return Err(format!(
+                "Remaining data after SET CLIENT NAME command. Got [{:x?}]",
+                bytes
+            ));
+        }
+
+        return Ok(Command::SetClientName { name });
+    }
+
+    fn parse_set_isolation_level(bytes: &[u8]) -> Result<Command, String> {
+        if bytes.len() != 1 {
+            return Err(format!(
+                "Expected a single isolation level byte in SET ISOLATION LEVEL command. Got [{:x?}]",
+                bytes
+            ));
+        }
+
+        let level = match bytes[0] {
+            0x00 => IsolationLevel::ReadCommitted,
+            0x01 => IsolationLevel::RepeatableRead,
+            0x02 => IsolationLevel::Serializable,
+            byte => return Err(format!("Unknown isolation level [{:x}]", byte)),
+        };
+
+        return Ok(Command::SetIsolationLevel { level });
+    }
+
+    /// Payload shared by SAVEPOINT, RELEASE SAVEPOINT and ROLLBACK TO SAVEPOINT
+    fn parse_savepoint_name(bytes: &[u8]) -> Result<String, String> {
+        let (bytes, name) = utils::parse_string(bytes)?;
+
+        if !bytes.is_empty() {
+            return Err(format!(
+                "Remaining data after savepoint command. Got [{:x?}]",
+                bytes
+            ));
+        }
+
+        return Ok(name);
+    }
+
+    fn parse_authenticate(bytes: &[u8]) -> Result<Command, String> {
+        let (bytes, user) = utils::parse_string(bytes)?;
+
+        if !bytes.is_empty() {
+            return Err(format!(
+                "Remaining data after AUTHENTICATE command. Got [{:x?}]",
+                bytes
+            ));
+        }
+
+        return Ok(Command::Authenticate { user });
+    }
+
+    fn parse_auth_proof(bytes: &[u8]) -> Result<Command, String> {
+        let (bytes, proof) = utils::parse_bytes(bytes)?;
+
+        if !bytes.is_empty() {
+            return Err(format!(
+                "Remaining data after AUTH PROOF command. Got [{:x?}]",
+                bytes
+            ));
+        }
+
+        return Ok(Command::AuthProof { proof });
+    }
+
+    /// Payload shared by CREATE USER and ALTER USER
+    fn parse_user_credentials(bytes: &[u8]) -> Result<(String, Credentials), String> {
+        let (bytes, name) = utils::parse_string(bytes)?;
+        let (bytes, salt) = utils::parse_bytes(bytes)?;
+        let (bytes, iterations) = utils::parse_u32(bytes)?;
+        let (bytes, stored_key) = utils::parse_bytes(bytes)?;
+
+        if !bytes.is_empty() {
+            return Err(format!(
+                "Remaining data after user command. Got [{:x?}]",
+                bytes
+            ));
+        }
+
+        let credentials = Credentials {
+            salt,
+            iterations,
+            stored_key,
+        };
+
+        return Ok((name, credentials));
+    }
+
+    fn parse_drop_user(bytes: &[u8]) -> Result<Command, String> {
+        let (bytes, name) = utils::parse_string(bytes)?;
+
+        if !bytes.is_empty() {
+            return Err(format!(
+                "Remaining data after DROP USER command. Got [{:x?}]",
+                bytes
+            ));
+        }
+
+        return Ok(Command::DropUser { name });
+    }
+
+    fn parse_create_role(bytes: &[u8]) -> Result<Command, String> {
+        let (bytes, name) = utils::parse_string(bytes)?;
+
+        if !bytes.is_empty() {
+            return Err(format!(
+                "Remaining data after CREATE ROLE command. Got [{:x?}]",
+                bytes
+            ));
+        }
+
+        return Ok(Command::CreateRole { name });
+    }
+
+    fn parse_drop_role(bytes: &[u8]) -> Result<Command, String> {
+        let (bytes, name) = utils::parse_string(bytes)?;
+
+        if !bytes.is_empty() {
+            return Err(format!(
+                "Remaining data after DROP ROLE command. Got [{:x?}]",
+                bytes
+            ));
+        }
+
+        return Ok(Command::DropRole { name });
+    }
+
+    /// Payload shared by GRANT and REVOKE of a role membership
+    fn parse_role_member(bytes: &[u8]) -> Result<(String, String), String> {
+        let (bytes, role) = utils::parse_string(bytes)?;
+        let (bytes, user) = utils::parse_string(bytes)?;
+
+        if !bytes.is_empty() {
+            return Err(format!(
+                "Remaining data after role membership command. Got [{:x?}]",
+                bytes
+            ));
+        }
+
+        return Ok((role, user));
+    }
+
+    /// Payload shared by GRANT and REVOKE of privileges: a mask of `Privilege::bit`s, the kind of
+    /// object (0 for a database, 1 for a table), its name and the role
+    fn parse_grant(bytes: &[u8]) -> Result<(Vec<Privilege>, GrantOn, String), String> {
+        if bytes.len() < 2 {
+            return Err(format!(
+                "Data too short to hold privileges. Got data length {}",
+                bytes.len()
+            ));
+        }
+
+        let mask = bytes[0];
+        let kind = bytes[1];
+
+        let (bytes, name) = utils::parse_string(&bytes[2..])?;
+        let (bytes, role) = utils::parse_string(bytes)?;
+
+        if !bytes.is_empty() {
+            return Err(format!(
+                "Remaining data after privilege command. Got [{:x?}]",
+                bytes
+            ));
+        }
+
+        let privileges: Vec<Privilege> = Privilege::ALL
+            .into_iter()
+            .filter(|p| mask & p.bit() != 0)
+            .collect();
+
+        if privileges.is_empty() {
+            return Err(String::from("No privilege given"));
+        }
+
+        let on = match kind {
+            0x00 => GrantOn::Database { name },
+            0x01 => GrantOn::Table { name },
+            _ => return Err(format!("Unknown privilege object kind [{:x}]", kind)),
+        };
+
+        return Ok((privileges, on, role));
+    }
+
+    /// Payload shared by DROP DATABASE and DROP TABLE
+    fn parse_drop(bytes: &[u8]) -> Result<(String, bool, bool), String> {
+        let (bytes, name) = utils::parse_string(bytes)?;
+        let (bytes, if_exists) = utils::parse_bool(bytes)?;
+        let (bytes, cascade) = utils::parse_bool(bytes)?;
+
+        if !bytes.is_empty() {
+            return Err(format!(
+                "Remaining data after DROP command. Got [{:x?}]",
+                bytes
+            ));
+        }
+
+        return Ok((name, if_exists, cascade));
+    }
+
+    /// Payload shared by the RENAME of databases and tables
+    fn parse_rename(bytes: &[u8]) -> Result<(String, String), String> {
+        let (bytes, name) = utils::parse_string(bytes)?;
+        let (bytes, new_name) = utils::parse_string(bytes)?;
+
+        if !bytes.is_empty() {
+            return Err(format!(
+                "Remaining data after RENAME command. Got [{:x?}]",
+                bytes
+            ));
+        }
+
+        return Ok((name, new_name));
+    }
+
+    fn parse_lock_table(bytes: &[u8]) -> Result<Command, String> {
+        let (bytes, name) = utils::parse_string(bytes)?;
+        let (bytes, exclusive) = utils::parse_bool(bytes)?;
+        let (bytes, nowait) = utils::parse_bool(bytes)?;
+
+        if !bytes.is_empty() {
+            return Err(format!(
+                "Remaining data after LOCK TABLE command. Got [{:x?}]",
+                bytes
+            ));
+        }
+
+        let mode = match exclusive {
+            true => LockMode::Exclusive,
+            false => LockMode::Shared,
+        };
+
+        return Ok(Command::LockTable { name, mode, nowait });
     }
 
     fn serialise_create_db(name: String, bytes: &mut Vec<u8>) {
@@ -115,9 +948,134 @@ pub mod request {
         utils::serialise_string(&name, bytes);
     }
 
-    fn serialise_create_table(name: String, bytes: &mut Vec<u8>) {
+    fn serialise_create_table(
+        name: String,
+        cols: Vec<Column>,
+        constraints: Vec<Constraint>,
+        bytes: &mut Vec<u8>,
+    ) {
         bytes.push(CommandDiscriminant::CreateTable.into());
         utils::serialise_string(&name, bytes);
+        utils::serialise_u32(cols.len() as u32, bytes);
+
+        for col in cols {
+            serialise_column(col, bytes);
+        }
+
+        serialise_constraints(constraints, bytes);
+    }
+
+    fn serialise_column(col: Column, bytes: &mut Vec<u8>) {
+        utils::serialise_string(&col.name, bytes);
+        bytes.push(col.column_type.tag());
+        utils::serialise_bool(col.is_optional, bytes);
+        utils::serialise_bool(col.is_primary_key, bytes);
+        utils::serialise_bool(col.is_foreign_key, bytes);
+        serialise_default(col.default, bytes);
+    }
+
+    fn serialise_default(default: Option<DefaultValue>, bytes: &mut Vec<u8>) {
+        match default {
+            None => bytes.push(0x00),
+            Some(DefaultValue::Literal(literal)) => {
+                bytes.push(0x01);
+                utils::serialise_string(&literal, bytes);
+            }
+            Some(DefaultValue::Now) => bytes.push(0x02),
+        }
+    }
+
+    fn serialise_constraints(constraints: Vec<Constraint>, bytes: &mut Vec<u8>) {
+        utils::serialise_u32(constraints.len() as u32, bytes);
+
+        for constraint in constraints {
+            serialise_constraint(constraint, bytes);
+        }
+    }
+
+    fn serialise_constraint(constraint: Constraint, bytes: &mut Vec<u8>) {
+        utils::serialise_bool(constraint.name.is_some(), bytes);
+
+        if let Some(name) = constraint.name {
+            utils::serialise_string(&name, bytes);
+        }
+
+        match constraint.kind {
+            ConstraintKind::Unique { columns } => {
+                bytes.push(0x00);
+                serialise_names(&columns, bytes);
+            }
+            ConstraintKind::Check(check) => {
+                bytes.push(0x01);
+                utils::serialise_string(&check.column, bytes);
+                bytes.push(check.op.tag());
+                utils::serialise_string(&check.value, bytes);
+            }
+            ConstraintKind::ForeignKey(fk) => {
+                bytes.push(0x02);
+                serialise_names(&fk.columns, bytes);
+                utils::serialise_string(&fk.ref_table, bytes);
+                serialise_names(&fk.ref_columns, bytes);
+                bytes.push(fk.on_delete.tag());
+                bytes.push(fk.on_update.tag());
+            }
+        }
+    }
+
+    fn serialise_names(names: &[String], bytes: &mut Vec<u8>) {
+        utils::serialise_u32(names.len() as u32, bytes);
+
+        for name in names {
+            utils::serialise_string(name, bytes);
+        }
+    }
+
+    fn serialise_alter_table(name: String, action: AlterTableAction, bytes: &mut Vec<u8>) {
+        bytes.push(CommandDiscriminant::AlterTable.into());
+        utils::serialise_string(&name, bytes);
+
+        match action {
+            AlterTableAction::AddColumn {
+                column,
+                constraints,
+            } => {
+                bytes.push(0x00);
+                serialise_column(column, bytes);
+                serialise_constraints(constraints, bytes);
+            }
+            AlterTableAction::DropColumn { name } => {
+                bytes.push(0x01);
+                utils::serialise_string(&name, bytes);
+            }
+            AlterTableAction::RenameColumn { name, new_name } => {
+                bytes.push(0x02);
+                utils::serialise_string(&name, bytes);
+                utils::serialise_string(&new_name, bytes);
+            }
+            AlterTableAction::AlterColumnType { name, column_type } => {
+                bytes.push(0x03);
+                utils::serialise_string(&name, bytes);
+                bytes.push(column_type.tag());
+            }
+            AlterTableAction::SetDefault { name, default } => {
+                bytes.push(0x04);
+                utils::serialise_string(&name, bytes);
+                serialise_default(default, bytes);
+            }
+            AlterTableAction::SetNotNull { name, not_null } => {
+                bytes.push(0x05);
+                utils::serialise_string(&name, bytes);
+                utils::serialise_bool(not_null, bytes);
+            }
+            AlterTableAction::AddConstraint { constraint } => {
+                bytes.push(0x06);
+                serialise_constraint(constraint, bytes);
+            }
+            AlterTableAction::DropConstraint { name } => {
+                bytes.push(0x07);
+                utils::serialise_string(&name, bytes);
+            }
+        }
     }
 
     fn serialise_list_databases(bytes: &mut Vec<u8>) {
@@ -127,14 +1085,110 @@ pub mod request {
     fn serialise_list_tables(bytes: &mut Vec<u8>) {
         bytes.push(CommandDiscriminant::ListTables.into());
     }
+
+    fn serialise_set_client_name(name: String, bytes: &mut Vec<u8>) {
+        bytes.push(CommandDiscriminant::SetClientName.into());
+        utils::serialise_string(&name, bytes);
+    }
+
+    fn serialise_savepoint(cmd: CommandDiscriminant, name: String, bytes: &mut Vec<u8>) {
+        bytes.push(cmd.into());
+        utils::serialise_string(&name, bytes);
+    }
+
+    fn serialise_user_credentials(
+        cmd: CommandDiscriminant,
+        name: String,
+        credentials: Credentials,
+        bytes: &mut Vec<u8>,
+    ) {
+        bytes.push(cmd.into());
+        utils::serialise_string(&name, bytes);
+        utils::serialise_bytes(&credentials.salt, bytes);
+        utils::serialise_u32(credentials.iterations, bytes);
+        utils::serialise_bytes(&credentials.stored_key, bytes);
+    }
+
+    fn serialise_grant(
+        cmd: CommandDiscriminant,
+        privileges: Vec<Privilege>,
+        on: GrantOn,
+        role: String,
+        bytes: &mut Vec<u8>,
+    ) {
+        bytes.push(cmd.into());
+        bytes.push(privileges.iter().fold(0, |mask, p| mask | p.bit()));
+
+        match on {
+            GrantOn::Database { name } => {
+                bytes.push(0x00);
+                utils::serialise_string(&name, bytes);
+            }
+            GrantOn::Table { name } => {
+                bytes.push(0x01);
+                utils::serialise_string(&name, bytes);
+            }
+        }
+
+        utils::serialise_string(&role, bytes);
+    }
+
+    fn serialise_drop(
+        cmd: CommandDiscriminant,
+        name: String,
+        if_exists: bool,
+        cascade: bool,
+        bytes: &mut Vec<u8>,
+    ) {
+        bytes.push(cmd.into());
+        utils::serialise_string(&name, bytes);
+        utils::serialise_bool(if_exists, bytes);
+        utils::serialise_bool(cascade, bytes);
+    }
+
+    fn serialise_lock_table(name: String, mode: LockMode, nowait: bool, bytes: &mut Vec<u8>) {
+        bytes.push(CommandDiscriminant::LockTable.into());
+        utils::serialise_string(&name, bytes);
+        utils::serialise_bool(mode == LockMode::Exclusive, bytes);
+        utils::serialise_bool(nowait, bytes);
+    }
+
+    fn serialise_set_isolation_level(level: IsolationLevel, bytes: &mut Vec<u8>) {
+        bytes.push(CommandDiscriminant::SetIsolationLevel.into());
+        bytes.push(match level {
+            IsolationLevel::ReadCommitted => 0x00,
+            IsolationLevel::RepeatableRead => 0x01,
+            IsolationLevel::Serializable => 0x02,
+        });
+    }
 }
 
 pub mod response {
 
     use super::utils;
+    use super::Challenge;
     use super::CommandDiscriminant;
+    use super::Table;
+    use super::{Error, ErrorCode, ERROR_FRAME};
 
     pub fn parse(bytes: &[u8]) -> Result<String, String> {
+        if bytes.is_empty() {
+            return Err(String::from("Empty response"));
+        }
+
+        if bytes[0] == ERROR_FRAME {
+            let err = parse_error(&bytes[1..])?;
+
+            return match err.code.is_retryable() {
+                true => Err(format!(
+                    "{} [{}, retry the transaction]",
+                    err.msg,
+                    err.code.name()
+                )),
+                false => Err(format!("{} [{}]", err.msg, err.code.name())),
+            };
+        }
+
         let cmd = CommandDiscriminant::from(bytes[0]);
 
         return match cmd {
@@ -143,13 +1197,246 @@ pub mod response {
             CommandDiscriminant::CreateTable => parse_create_table(&bytes[1..]),
             CommandDiscriminant::ListDatabases => parse_list_databases(&bytes[1..]),
             CommandDiscriminant::ListTables => parse_list_tables(&bytes[1..]),
+            CommandDiscriminant::SetClientName => parse_set_client_name(&bytes[1..]),
+            CommandDiscriminant::Begin => parse_begin(&bytes[1..]),
+            CommandDiscriminant::Commit => parse_commit(&bytes[1..]),
+            CommandDiscriminant::Rollback => parse_rollback(&bytes[1..]),
+            CommandDiscriminant::SetIsolationLevel => parse_set_isolation_level(&bytes[1..]),
+            CommandDiscriminant::LockTable => parse_lock_table(&bytes[1..]),
+            CommandDiscriminant::Savepoint => parse_savepoint(&bytes[1..]),
+            CommandDiscriminant::ReleaseSavepoint => parse_release_savepoint(&bytes[1..]),
+            CommandDiscriminant::RollbackToSavepoint => parse_rollback_to_savepoint(&bytes[1..]),
+            CommandDiscriminant::Authenticate => {
+                parse_challenge(bytes).map(|_| String::from("Challenge received"))
+            }
+            CommandDiscriminant::AuthProof => parse_auth_proof(&bytes[1..]),
+            CommandDiscriminant::CreateUser => parse_create_user(&bytes[1..]),
+            CommandDiscriminant::AlterUser => parse_alter_user(&bytes[1..]),
+            CommandDiscriminant::DropUser => parse_drop_user(&bytes[1..]),
+            CommandDiscriminant::CreateRole => parse_create_role(&bytes[1..]),
+            CommandDiscriminant::DropRole => parse_drop_role(&bytes[1..]),
+            CommandDiscriminant::GrantRole => parse_grant_role(&bytes[1..]),
+            CommandDiscriminant::RevokeRole => parse_revoke_role(&bytes[1..]),
+            CommandDiscriminant::Grant => parse_grant(&bytes[1..]),
+            CommandDiscriminant::Revoke => parse_revoke(&bytes[1..]),
+            CommandDiscriminant::DropDatabase => parse_drop_db(&bytes[1..]),
+            CommandDiscriminant::DropTable => parse_drop_table(&bytes[1..]),
+            CommandDiscriminant::RenameDatabase => parse_rename_db(&bytes[1..]),
+            CommandDiscriminant::RenameTable => parse_rename_table(&bytes[1..]),
+            CommandDiscriminant::DescribeTable => parse_describe_table(&bytes[1..]),
+            CommandDiscriminant::AlterTable => parse_alter_table(&bytes[1..]),
+            CommandDiscriminant::CreatePolicy => parse_create_policy(&bytes[1..]),
+            CommandDiscriminant::DropPolicy => parse_drop_policy(&bytes[1..]),
         };
     }
 
+    /// Response to `Command::Authenticate`
+    pub fn parse_challenge(bytes: &[u8]) -> Result<Challenge, String> {
+        if bytes.is_empty() {
+            return Err(String::from("Empty response to AUTHENTICATE"));
+        }
+
+        if bytes[0] != u8::from(CommandDiscriminant::Authenticate) {
+            return Err(parse(bytes)
+                .err()
+                .unwrap_or(String::from("Unexpected response to AUTHENTICATE")));
+        }
+
+        let (bytes, salt) = utils::parse_bytes(&bytes[1..])?;
+        let (bytes, iterations) = utils::parse_u32(bytes)?;
+        let (_, nonce) = utils::parse_bytes(bytes)?;
+
+        return Ok(Challenge {
+            salt,
+            iterations,
+            nonce,
+        });
+    }
+
+    pub fn serialise_challenge(challenge: &Challenge) -> Vec<u8> {
+        let mut bytes = vec![CommandDiscriminant::Authenticate.into()];
+        utils::serialise_bytes(&challenge.salt, &mut bytes);
+        utils::serialise_u32(challenge.iterations, &mut bytes);
+        utils::serialise_bytes(&challenge.nonce, &mut bytes);
+
+        return bytes;
+    }
+
     pub fn serialise() {
         todo!()
     }
 
+    pub fn serialise_list_databases(names: &[String]) -> Vec<u8> {
+        let mut bytes = vec![CommandDiscriminant::ListDatabases.into()];
+        utils::serialise_u32(names.len() as u32, &mut bytes);
+
+        for name in names {
+            utils::serialise_string(name, &mut bytes);
+        }
+
+        return bytes;
+    }
+
+    pub fn serialise_list_tables(names: &[String]) -> Vec<u8> {
+        let mut bytes = vec![CommandDiscriminant::ListTables.into()];
+        utils::serialise_u32(names.len() as u32, &mut bytes);
+
+        for name in names {
+            utils::serialise_string(name, &mut bytes);
+        }
+
+        return bytes;
+    }
+
+    /// One row per column of the table. Constraints and policies are marked NOT ENFORCED, as
+    /// there are no rows to enforce them on yet. There are no indexes yet either, so the indexes
+    /// column is always empty.
+    pub fn serialise_describe_table(table: &Table) -> Vec<u8> {
+        let rows: Vec<Vec<String>> = table
+            .columns
+            .iter()
+            .map(|col| {
+                let constraints: Vec<String> = table
+                    .constraints_on(&col.name)
+                    .map(|constraint| {
+                        format!(
+                            "{} {} NOT ENFORCED",
+                            constraint.name.as_deref().unwrap_or_default(),
+                            constraint.kind.describe()
+                        )
+                    })
+                    .collect();
+
+                let policies: Vec<String> = table
+                    .policies_on(&col.name)
+                    .map(|policy| format!("{} NOT ENFORCED", policy.describe()))
+                    .collect();
+
+                vec![
+                    col.name.clone(),
+                    String::from(col.column_type.name()),
+                    String::from(yes_no(col.is_optional)),
+                    col.default
+                        .as_ref()
+                        .map(|d| d.describe())
+                        .unwrap_or_default(),
+                    String::from(yes_no(col.is_primary_key)),
+                    String::from(yes_no(col.is_foreign_key)),
+                    constraints.join(", "),
+                    policies.join(", "),
+                    String::new(),
+                ]
+            })
+            .collect();
+
+        let mut bytes = vec![CommandDiscriminant::DescribeTable.into()];
+        serialise_result_set(
+            &[
+                "column",
+                "type",
+                "nullable",
+                "default",
+                "primary_key",
+                "foreign_key",
+                "constraints",
+                "policies",
+                "indexes",
+            ],
+            &rows,
+            &mut bytes,
+        );
+
+        return bytes;
+    }
+
+    /// Column count, column names, row count, then every row's values as strings
+    fn serialise_result_set(header: &[&str], rows: &[Vec<String>], bytes: &mut Vec<u8>) {
+        utils::serialise_u32(header.len() as u32, bytes);
+
+        for name in header {
+            utils::serialise_string(&String::from(*name), bytes);
+        }
+
+        utils::serialise_u32(rows.len() as u32, bytes);
+
+        for row in rows {
+            for value in row {
+                utils::serialise_string(value, bytes);
+            }
+        }
+    }
+
+    /// Result set laid out as a text table
+    fn parse_result_set(bytes: &[u8]) -> Result<String, String> {
+        let (mut bytes, col_count) = utils::parse_u32(bytes)?;
+
+        let mut rows = vec![];
+        let mut header = vec![];
+
+        for _ in 0..col_count {
+            let (new_bytes, name) = utils::parse_string(bytes)?;
+            bytes = new_bytes;
+            header.push(name);
+        }
+
+        rows.push(header);
+
+        let (mut bytes, row_count) = utils::parse_u32(bytes)?;
+
+        for _ in 0..row_count {
+            let mut row = vec![];
+
+            for _ in 0..col_count {
+                let (new_bytes, value) = utils::parse_string(bytes)?;
+                bytes = new_bytes;
+                row.push(value);
+            }
+
+            rows.push(row);
+        }
+
+        let widths: Vec<usize> = (0..col_count as usize)
+            .map(|i| rows.iter().map(|row| row[i].len()).max().unwrap_or(0))
+            .collect();
+
+        let lines: Vec<String> = rows
+            .iter()
+            .map(|row| {
+                let cells: Vec<String> = row
+                    .iter()
+                    .zip(&widths)
+                    .map(|(value, width)| format!("{:width$}", value, width = width))
+                    .collect();
+
+                cells.join(" | ").trim_end().to_string()
+            })
+            .collect();
+
+        return Ok(format!("{}\n({} row(s))", lines.join("\n"), row_count));
+    }
+
+    fn yes_no(flag: bool) -> &'static str {
+        return match flag {
+            true => "YES",
+            false => "NO",
+        };
+    }
+
+    pub fn serialise_error(err: &Error) -> Vec<u8> {
+        let mut bytes = vec![ERROR_FRAME];
+        utils::serialise_u16(err.code as u16, &mut bytes);
+        utils::serialise_string(&err.msg, &mut bytes);
+
+        return bytes;
+    }
+
+    fn parse_error(bytes: &[u8]) -> Result<Error, String> {
+        let (bytes, code) = utils::parse_u16(bytes)?;
+        let (_, msg) = utils::parse_string(bytes)?;
+
+        return Ok(Error::new(ErrorCode::try_from(code)?, msg));
+    }
+
     fn parse_create_db(bytes: &[u8]) -> Result<String, String> {
         let (_, success) = utils::parse_bool(bytes)?;
         match success {
@@ -158,8 +1445,12 @@ pub mod response {
         }
     }
 
-    fn parse_open_db(_bytes: &[u8]) -> Result<String, String> {
-        return Ok(String::from("TODO: RESP OPEN_DB"));
+    fn parse_open_db(bytes: &[u8]) -> Result<String, String> {
+        let (_, success) = utils::parse_bool(bytes)?;
+        match success {
+            true => return Ok(String::from("Database opened")),
+            false => return Err(String::from("Failed to open database")),
+        }
     }
 
     fn parse_create_table(_bytes: &[u8]) -> Result<String, String> {
@@ -170,6 +1461,40 @@ pub mod response {
         }
     }
 
+    /// The flag tells whether there was a database to drop, it's only unset with IF EXISTS
+    fn parse_drop_db(bytes: &[u8]) -> Result<String, String> {
+        let (_, dropped) = utils::parse_bool(bytes)?;
+        match dropped {
+            true => return Ok(String::from("Database dropped")),
+            false => return Ok(String::from("Database doesn't exist, skipped")),
+        }
+    }
+
+    /// The flag tells whether there was a table to drop, it's only unset with IF EXISTS
+    fn parse_drop_table(bytes: &[u8]) -> Result<String, String> {
+        let (_, dropped) = utils::parse_bool(bytes)?;
+        match dropped {
+            true => return Ok(String::from("Table dropped")),
+            false => return Ok(String::from("Table doesn't exist, skipped")),
+        }
+    }
+
+    fn parse_rename_db(bytes: &[u8]) -> Result<String, String> {
+        let (_, success) = utils::parse_bool(bytes)?;
+        match success {
+            true => return Ok(String::from("Database renamed")),
+            false => return Err(String::from("Failed to rename database")),
+        }
+    }
+
+    fn parse_rename_table(bytes: &[u8]) -> Result<String, String> {
+        let (_, success) = utils::parse_bool(bytes)?;
+        match success {
+            true => return Ok(String::from("Table renamed")),
+            false => return Err(String::from("Failed to rename table")),
+        }
+    }
+
     fn parse_list_databases(bytes: &[u8]) -> Result<String, String> {
         let (mut bytes, db_count) = utils::parse_u32(bytes)?;
 
@@ -188,7 +1513,317 @@ pub mod response {
         return Ok(res);
     }
 
-    fn parse_list_tables(_bytes: &[u8]) -> Result<String, String> {
-        return Ok(String::from("TODO: RESP LIST_TABLES"));
+    fn parse_list_tables(bytes: &[u8]) -> Result<String, String> {
+        let (mut bytes, table_count) = utils::parse_u32(bytes)?;
+
+        let mut names = vec![];
+
+        for _ in 0..table_count {
+            let (new_bytes, name) = utils::parse_string(bytes)?;
+            bytes = new_bytes;
+            names.push(name);
+        }
+
+        return Ok(format!("Tables: [{}]", names.join(",")));
+    }
+
+    fn parse_describe_table(bytes: &[u8]) -> Result<String, String> {
+        return parse_result_set(bytes);
+    }
+
+    fn parse_alter_table(bytes: &[u8]) -> Result<String, String> {
+        let (_, success) = utils::parse_bool(bytes)?;
+        match success {
+            true => return Ok(String::from("Table altered")),
+            false => return Err(String::from("Failed to alter table")),
+        }
+    }
+
+    fn parse_create_policy(bytes: &[u8]) -> Result<String, String> {
+        let (_, success) = utils::parse_bool(bytes)?;
+        match success {
+            true => return Ok(String::from("Policy created")),
+            false => return Err(String::from("Failed to create policy")),
+        }
+    }
+
+    fn parse_drop_policy(bytes: &[u8]) -> Result<String, String> {
+        let (_, success) = utils::parse_bool(bytes)?;
+        match success {
+            true => return Ok(String::from("Policy dropped")),
+            false => return Err(String::from("Failed to drop policy")),
+        }
+    }
+
+    fn parse_set_client_name(bytes: &[u8]) -> Result<String, String> {
+        let (_, success) = utils::parse_bool(bytes)?;
+        match success {
+            true => return Ok(String::from("Client name set")),
+            false => return Err(String::from("Failed to set client name")),
+        }
+    }
+
+    fn parse_begin(bytes: &[u8]) -> Result<String, String> {
+        let (_, success) = utils::parse_bool(bytes)?;
+        match success {
+            true => return Ok(String::from("Transaction started")),
+            false => return Err(String::from("Failed to start transaction")),
+        }
+    }
+
+    fn parse_commit(bytes: &[u8]) -> Result<String, String> {
+        let (_, success) = utils::parse_bool(bytes)?;
+        match success {
+            true => return Ok(String::from("Transaction committed")),
+            false => return Err(String::from("Failed to commit transaction")),
+        }
+    }
+
+    fn parse_rollback(bytes: &[u8]) -> Result<String, String> {
+        let (_, success) = utils::parse_bool(bytes)?;
+        match success {
+            true => return Ok(String::from("Transaction rolled back")),
+            false => return Err(String::from("No transaction to roll back")),
+        }
+    }
+
+    fn parse_set_isolation_level(bytes: &[u8]) -> Result<String, String> {
+        let (_, success) = utils::parse_bool(bytes)?;
+        match success {
+            true => return Ok(String::from("Isolation level set")),
+            false => return Err(String::from("Failed to set isolation level")),
+        }
+    }
+
+    fn parse_lock_table(bytes: &[u8]) -> Result<String, String> {
+        let (_, success) = utils::parse_bool(bytes)?;
+        match success {
+            true => return Ok(String::from("Table locked")),
+            false => return Err(String::from("Failed to lock table")),
+        }
+    }
+
+    fn parse_savepoint(bytes: &[u8]) -> Result<String, String> {
+        let (_, success) = utils::parse_bool(bytes)?;
+        match success {
+            true => return Ok(String::from("Savepoint set")),
+            false => return Err(String::from("Failed to set savepoint")),
+        }
+    }
+
+    fn parse_release_savepoint(bytes: &[u8]) -> Result<String, String> {
+        let (_, success) = utils::parse_bool(bytes)?;
+        match success {
+            true => return Ok(String::from("Savepoint released")),
+            false => return Err(String::from("Failed to release savepoint")),
+        }
+    }
+
+    fn parse_rollback_to_savepoint(bytes: &[u8]) -> Result<String, String> {
+        let (_, success) = utils::parse_bool(bytes)?;
+        match success {
+            true => return Ok(String::from("Rolled back to savepoint")),
+            false => return Err(String::from("Failed to roll back to savepoint")),
+        }
+    }
+
+    fn parse_auth_proof(bytes: &[u8]) -> Result<String, String> {
+        let (_, success) = utils::parse_bool(bytes)?;
+        match success {
+            true => return Ok(String::from("Authenticated")),
+            false => return Err(String::from("Authentication failed")),
+        }
+    }
+
+    fn parse_create_user(bytes: &[u8]) -> Result<String, String> {
+        let (_, success) = utils::parse_bool(bytes)?;
+        match success {
+            true => return Ok(String::from("User created")),
+            false => return Err(String::from("Failed to create user")),
+        }
+    }
+
+    fn parse_alter_user(bytes: &[u8]) -> Result<String, String> {
+        let (_, success) = utils::parse_bool(bytes)?;
+        match success {
+            true => return Ok(String::from("User altered")),
+            false => return Err(String::from("Failed to alter user")),
+        }
+    }
+
+    fn parse_drop_user(bytes: &[u8]) -> Result<String, String> {
+        let (_, success) = utils::parse_bool(bytes)?;
+        match success {
+            true => return Ok(String::from("User dropped")),
+            false => return Err(String::from("Failed to drop user")),
+        }
+    }
+
+    fn parse_create_role(bytes: &[u8]) -> Result<String, String> {
+        let (_, success) = utils::parse_bool(bytes)?;
+        match success {
+            true => return Ok(String::from("Role created")),
+            false => return Err(String::from("Failed to create role")),
+        }
+    }
+
+    fn parse_drop_role(bytes: &[u8]) -> Result<String, String> {
+        let (_, success) = utils::parse_bool(bytes)?;
+        match success {
+            true => return Ok(String::from("Role dropped")),
+            false => return Err(String::from("Failed to drop role")),
+        }
+    }
+
+    fn parse_grant_role(bytes: &[u8]) -> Result<String, String> {
+        let (_, success) = utils::parse_bool(bytes)?;
+        match success {
+            true => return Ok(String::from("Role granted")),
+            false => return Err(String::from("Failed to grant role")),
+        }
+    }
+
+    fn parse_revoke_role(bytes: &[u8]) -> Result<String, String> {
+        let (_, success) = utils::parse_bool(bytes)?;
+        match success {
+            true => return Ok(String::from("Role revoked")),
+            false => return Err(String::from("Failed to revoke role")),
+        }
+    }
+
+    fn parse_grant(bytes: &[u8]) -> Result<String, String> {
+        let (_, success) = utils::parse_bool(bytes)?;
+        match success {
+            true => return Ok(String::from("Privileges granted")),
+            false => return Err(String::from("Failed to grant privileges")),
+        }
+    }
+
+    fn parse_revoke(bytes: &[u8]) -> Result<String, String> {
+        let (_, success) = utils::parse_bool(bytes)?;
+        match success {
+            true => return Ok(String::from("Privileges revoked")),
+            false => return Err(String::from("Failed to revoke privileges")),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn empty_responses_are_errors() {
+        assert_eq!(response::parse(&[]), Err(String::from("Empty response")));
+        assert_eq!(
+            response::parse_challenge(&[]),
+            Err(String::from("Empty response to AUTHENTICATE"))
+        );
+    }
+
+    #[test]
+    fn challenge_round_trips() {
+        let challenge = Challenge {
+            salt: vec![1, 2, 3],
+            iterations: 1000,
+            nonce: vec![4, 5],
+        };
+
+        let bytes = response::serialise_challenge(&challenge);
+        assert_eq!(response::parse_challenge(&bytes), Ok(challenge));
+    }
+
+    #[test]
+    fn list_tables_round_trips() {
+        let names = [String::from("orders"), String::from("users")];
+        let bytes = response::serialise_list_tables(&names);
+        assert_eq!(
+            response::parse(&bytes),
+            Ok(String::from("Tables: [orders,users]"))
+        );
+
+        let bytes = response::serialise_list_tables(&[]);
+        assert_eq!(response::parse(&bytes), Ok(String::from("Tables: []")));
+    }
+
+    #[test]
+    fn describe_table_round_trips() {
+        let id = Column {
+            name: String::from("id"),
+            column_type: ColumnType::SINT32,
+            is_optional: false,
+            is_primary_key: true,
+            is_foreign_key: false,
+            default: None,
+        };
+        let email = Column {
+            name: String::from("email"),
+            column_type: ColumnType::STRING,
+            is_optional: true,
+            is_primary_key: false,
+            is_foreign_key: false,
+            default: Some(DefaultValue::Literal(String::from("none"))),
+        };
+        let unique = Constraint {
+            name: Some(String::from("unique_email")),
+            kind: ConstraintKind::Unique {
+                columns: vec![String::from("email")],
+            },
+        };
+        let mut table = Table::new(String::from("users"), vec![id, email], vec![unique]).unwrap();
+        table.add_policy(own_rows()).unwrap();
+
+        let bytes = response::serialise_describe_table(&table);
+        let described = response::parse(&bytes).unwrap();
+
+        let expected = [
+            "column | type   | nullable | default | primary_key | foreign_key | constraints                              | policies                                             | indexes",
+            "id     | SINT32 | NO       |         | YES         | NO          |                                          | own FOR SELECT TO PUBLIC USING (id = 1) NOT ENFORCED |",
+            "email  | STRING | YES      | none    | NO          | NO          | unique_email UNIQUE (email) NOT ENFORCED |                                                      |",
+            "(2 row(s))",
+        ];
+        assert_eq!(described, expected.join("\n"));
+    }
+
+    fn own_rows() -> Policy {
+        return Policy {
+            name: String::from("own"),
+            command: PolicyCommand::Select,
+            role: String::from("PUBLIC"),
+            predicate: Check {
+                column: String::from("id"),
+                op: CompareOp::Eq,
+                value: String::from("1"),
+            },
+        };
+    }
+
+    #[test]
+    fn policy_commands_round_trip() {
+        let bytes = request::serialise(Command::CreatePolicy {
+            table: String::from("users"),
+            policy: own_rows(),
+        });
+        match request::parse(&bytes) {
+            Ok(Command::CreatePolicy { table, policy }) => {
+                assert_eq!(table, "users");
+                assert_eq!(policy, own_rows());
+            }
+            other => panic!("Expected CREATE POLICY, got {:?}", other),
+        }
+
+        let bytes = request::serialise(Command::DropPolicy {
+            table: String::from("users"),
+            name: String::from("own"),
+        });
+        match request::parse(&bytes) {
+            Ok(Command::DropPolicy { table, name }) => {
+                assert_eq!(table, "users");
+                assert_eq!(name, "own");
+            }
+            other => panic!("Expected DROP POLICY, got {:?}", other),
+        }
+
+        assert!(request::parse(&[bytes, vec![0x00]].concat()).is_err());
     }
 }
@@ -0,0 +1,2759 @@
+use std::collections::HashMap;
+use std::io::{self, ErrorKind, Read, Write};
+use std::net::{SocketAddr, TcpListener};
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex, RwLock};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+
+use crate::acl::{Acl, GrantOn, Object, Privilege};
+use crate::audit::AuditLog;
+use crate::auth::{self, Challenge, Credentials, Users};
+use crate::column::Column;
+use crate::command::{AlterTableAction, Command};
+use crate::constraint::{Constraint, ConstraintKind};
+use crate::error::{Error, ErrorCode};
+use crate::lock::{LockError, LockManager, LockMode, LockTarget, Wait};
+use crate::log::{LogContext, LogLevel, Loggers};
+use crate::metrics::{self, MeteredStream, Metrics};
+use crate::mvcc::{Snapshot, TxnId, TxnManager};
+use crate::net::{BindAddress, Listener, Stream};
+use crate::policy::Policy;
+use crate::protocol::v0;
+use crate::table::Table;
+use crate::tls;
+use crate::transaction::{self, Change, Databases, IsolationLevel, Transaction};
+use crate::utils;
+
+/// How often idle loops (accept loop, idle connections, drain) check for a shutdown request
+const POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+/// How often versions no transaction can see anymore are removed
+const GC_INTERVAL: Duration = Duration::from_secs(10);
+
+/// Settings which can be changed while the server is running, through `Server::settings`
+#[derive(Debug, Clone)]
+pub struct ServerSettings {
+    /// Connections accepted beyond this many are closed straight away
+    pub max_concurrent_connections: usize,
+
+    /// Time given to in-flight requests to complete once a shutdown is requested
+    pub shutdown_grace_period: Duration,
+
+    /// Commands taking at least this long are recorded in the slow query log, if there is one
+    pub slow_query_threshold: Option<Duration>,
+
+    /// How long a command waits for a lock held by another transaction before failing
+    pub lock_timeout: Duration,
+
+    /// Whether connections can run commands without authenticating. Only checked when a connection
+    /// opens.
+    pub allow_anonymous: bool,
+}
+
+impl Default for ServerSettings {
+    fn default() -> ServerSettings {
+        ServerSettings {
+            max_concurrent_connections: 8,
+            shutdown_grace_period: Duration::from_secs(10),
+            slow_query_threshold: None,
+            lock_timeout: Duration::from_secs(10),
+            allow_anonymous: false,
+        }
+    }
+}
+
+/// State shared by the server and its connections
+#[derive(Debug)]
+struct Shared {
+    settings: Arc<RwLock<ServerSettings>>,
+
+    /// Only locked for the duration of a read or a write, never for a whole transaction
+    databases: RwLock<Databases>,
+
+    transactions: TxnManager,
+
+    locks: LockManager,
+
+    /// Not versioned like the databases. User changes apply straight away, in or out of a
+    /// transaction.
+    users: RwLock<Users>,
+
+    /// Roles and privileges, applied straight away like `users`
+    acl: RwLock<Acl>,
+
+    /// Database each connection has open, by connection ID, so databases in use aren't dropped or
+    /// renamed from under them
+    open_databases: Mutex<HashMap<u64, String>>,
+
+    loggers: Loggers,
+
+    /// Where commands slower than `ServerSettings::slow_query_threshold` are recorded
+    slow_query_loggers: Option<Loggers>,
+
+    /// Where logins, DDL and privilege changes are recorded
+    audit: Option<AuditLog>,
+
+    shutdown: Arc<AtomicBool>,
+
+    active_connections: AtomicUsize,
+
+    metrics: Arc<Metrics>,
+}
+
+#[derive(Debug)]
+pub struct Server {
+    listeners: Vec<Listener>,
+
+    local_addrs: Vec<BindAddress>,
+
+    /// Listener of the `/metrics` HTTP endpoint, served on its own thread once the server runs
+    metrics_listener: Option<TcpListener>,
+
+    metrics_addr: Option<SocketAddr>,
+
+    /// TLS settings TCP connections are encrypted with. Unix domain socket connections aren't.
+    tls: Option<Arc<rustls::ServerConfig>>,
+
+    shared: Arc<Shared>,
+
+    next_connection_id: u64,
+}
+
+/// Builder for `Server`. Binding port 0 lets the OS pick a free port, which `Server::local_addr`
+/// and `ServerHandle::local_addr` then report.
+#[derive(Debug)]
+pub struct ServerBuilder {
+    port: u16,
+    bind: Vec<BindAddress>,
+    settings: ServerSettings,
+    loggers: Loggers,
+    slow_query_loggers: Option<Loggers>,
+    metrics_bind: Option<String>,
+    tls: Option<TlsFiles>,
+    audit_log: Option<(PathBuf, PathBuf)>,
+    users: Users,
+}
+
+#[derive(Debug)]
+struct TlsFiles {
+    cert: PathBuf,
+    key: PathBuf,
+    client_ca: Option<PathBuf>,
+}
+
+impl ServerBuilder {
+    pub fn new() -> ServerBuilder {
+        ServerBuilder {
+            port: 6870,
+            bind: vec![],
+            settings: ServerSettings::default(),
+            loggers: Loggers::from(vec![]),
+            slow_query_loggers: None,
+            metrics_bind: None,
+            tls: None,
+            audit_log: None,
+            users: Users::new(),
+        }
+    }
+
+    /// Port of the default `127.0.0.1` listener, used when no address is given to `bind`
+    pub fn port(mut self, port: u16) -> ServerBuilder {
+        self.port = port;
+        return self;
+    }
+
+    /// Listen on `addr`, in addition to any address already added
+    pub fn bind(mut self, addr: BindAddress) -> ServerBuilder {
+        self.bind.push(addr);
+        return self;
+    }
+
+    pub fn max_concurrent_connections(
+        mut self,
+        max_concurrent_connections: usize,
+    ) -> ServerBuilder {
+        self.settings.max_concurrent_connections = max_concurrent_connections;
+        return self;
+    }
+
+    pub fn shutdown_grace_period(mut self, shutdown_grace_period: Duration) -> ServerBuilder {
+        self.settings.shutdown_grace_period = shutdown_grace_period;
+        return self;
+    }
+
+    pub fn lock_timeout(mut self, lock_timeout: Duration) -> ServerBuilder {
+        self.settings.lock_timeout = lock_timeout;
+        return self;
+    }
+
+    pub fn allow_anonymous(mut self, allow_anonymous: bool) -> ServerBuilder {
+        self.settings.allow_anonymous = allow_anonymous;
+        return self;
+    }
+
+    /// Create the user `name` when the server starts, e.g. to bootstrap an administrator
+    pub fn user(
+        mut self,
+        name: String,
+        credentials: Credentials,
+        superuser: bool,
+    ) -> io::Result<ServerBuilder> {
+        self.users
+            .create(name, credentials, superuser)
+            .map_err(|e| io::Error::new(ErrorKind::AlreadyExists, e.msg))?;
+
+        return Ok(self);
+    }
+
+    pub fn loggers(mut self, loggers: Loggers) -> ServerBuilder {
+        self.loggers = loggers;
+        return self;
+    }
+
+    /// Record commands taking longer than `threshold` to `loggers`
+    pub fn slow_query_log(mut self, loggers: Loggers, threshold: Duration) -> ServerBuilder {
+        self.slow_query_loggers = Some(loggers);
+        self.settings.slow_query_threshold = Some(threshold);
+        return self;
+    }
+
+    /// Serve Prometheus metrics over HTTP at `http://<addr>/metrics`
+    pub fn metrics_bind(mut self, addr: String) -> ServerBuilder {
+        self.metrics_bind = Some(addr);
+        return self;
+    }
+
+    /// Append a record of every login attempt, DDL command, privilege change and denied command
+    /// to the audit log at `path`, chained with the key in `key_file`
+    pub fn audit_log(mut self, path: PathBuf, key_file: PathBuf) -> ServerBuilder {
+        self.audit_log = Some((path, key_file));
+        return self;
+    }
+
+    /// Encrypt TCP connections with the PEM certificate chain `cert` and private key `key`. With
+    /// `client_ca`, clients must present a certificate signed by one of the CAs in that file.
+    pub fn tls(mut self, cert: PathBuf, key: PathBuf, client_ca: Option<PathBuf>) -> ServerBuilder {
+        self.tls = Some(TlsFiles {
+            cert,
+            key,
+            client_ca,
+        });
+        return self;
+    }
+
+    /// Bind the listeners. The server doesn't accept connections until `Server::run` is called.
+    pub fn build(mut self) -> io::Result<Server> {
+        if self.bind.is_empty() {
+            self.bind
+                .push(BindAddress::Tcp(format!("127.0.0.1:{}", self.port)));
+        }
+
+        let mut listeners = vec![];
+        let mut local_addrs = vec![];
+
+        for addr in &self.bind {
+            let listener = Listener::bind(addr)
+                .map_err(|e| io::Error::new(e.kind(), format!("Failed to bind {}: {}", addr, e)))?;
+
+            local_addrs.push(listener.local_addr()?);
+            listeners.push(listener);
+        }
+
+        let metrics_listener = match &self.metrics_bind {
+            Some(addr) => Some(metrics::bind(addr).map_err(|e| {
+                io::Error::new(
+                    e.kind(),
+                    format!("Failed to bind metrics endpoint {}: {}", addr, e),
+                )
+            })?),
+            None => None,
+        };
+
+        let metrics_addr = match &metrics_listener {
+            Some(listener) => Some(listener.local_addr()?),
+            None => None,
+        };
+
+        let audit = match self.audit_log {
+            Some((path, key_file)) => Some(AuditLog::open(path, &key_file)?),
+            None => None,
+        };
+
+        let tls = match &self.tls {
+            Some(files) => Some(tls::server_config(
+                &files.cert,
+                &files.key,
+                files.client_ca.as_deref(),
+            )?),
+            None => None,
+        };
+
+        return Ok(Server {
+            listeners,
+            local_addrs,
+            metrics_listener,
+            metrics_addr,
+            tls,
+            shared: Arc::new(Shared {
+                settings: Arc::new(RwLock::new(self.settings)),
+                databases: RwLock::new(vec![]),
+                transactions: TxnManager::new(),
+                locks: LockManager::new(),
+                users: RwLock::new(self.users),
+                open_databases: Mutex::new(HashMap::new()),
+                acl: RwLock::new(Acl::new()),
+                loggers: self.loggers,
+                slow_query_loggers: self.slow_query_loggers,
+                audit,
+                shutdown: Arc::new(AtomicBool::new(false)),
+                active_connections: AtomicUsize::new(0),
+                metrics: Arc::new(Metrics::default()),
+            }),
+            next_connection_id: 1,
+        });
+    }
+
+    /// Bind the listener and run the server on a background thread
+    pub fn spawn(self) -> io::Result<ServerHandle> {
+        let mut server = self.build()?;
+
+        let local_addrs = server.local_addrs().to_vec();
+        let metrics_addr = server.metrics_addr();
+        let shutdown = server.shutdown_flag();
+
+        let thread = thread::Builder::new()
+            .name(String::from("squeef-server"))
+            .spawn(move || server.run())?;
+
+        return Ok(ServerHandle {
+            local_addrs,
+            metrics_addr,
+            shutdown,
+            thread: Some(thread),
+        });
+    }
+}
+
+impl Default for ServerBuilder {
+    fn default() -> ServerBuilder {
+        ServerBuilder::new()
+    }
+}
+
+impl Server {
+    pub fn builder() -> ServerBuilder {
+        return ServerBuilder::new();
+    }
+
+    /// Addresses the server listens on, with ports resolved if 0 was requested
+    pub fn local_addrs(&self) -> &[BindAddress] {
+        return &self.local_addrs;
+    }
+
+    /// First TCP address the server listens on
+    pub fn local_addr(&self) -> Option<SocketAddr> {
+        return self.listeners.iter().find_map(|l| l.tcp_addr());
+    }
+
+    /// Address of the `/metrics` HTTP endpoint, if one was requested with `metrics_bind`
+    pub fn metrics_addr(&self) -> Option<SocketAddr> {
+        return self.metrics_addr;
+    }
+
+    pub fn metrics(&self) -> Arc<Metrics> {
+        return self.shared.metrics.clone();
+    }
+
+    /// Settings in effect. Changes made through the lock apply to the running server.
+    pub fn settings(&self) -> Arc<RwLock<ServerSettings>> {
+        return self.shared.settings.clone();
+    }
+
+    pub fn loggers(&self) -> Loggers {
+        return self.shared.loggers.clone();
+    }
+
+    /// Flag which, once set, makes `run` stop accepting connections, drain the open ones and return
+    pub fn shutdown_flag(&self) -> Arc<AtomicBool> {
+        return self.shared.shutdown.clone();
+    }
+
+    pub fn run(&mut self) -> () {
+        let listeners = std::mem::take(&mut self.listeners);
+        let loggers = &self.shared.loggers;
+
+        for addr in &self.local_addrs {
+            loggers.log(LogLevel::INFO, &format!("Started listening on {}", addr));
+        }
+
+        if self.tls.is_some() {
+            loggers.log(LogLevel::INFO, "TCP connections are encrypted with TLS");
+        }
+
+        if let Some(audit) = &self.shared.audit {
+            if audit.truncated() > 0 {
+                loggers.log(
+                    LogLevel::WARN,
+                    &format!(
+                        "Dropped a partial last record of {} byte(s) from audit log {}",
+                        audit.truncated(),
+                        audit.path().display()
+                    ),
+                );
+            }
+
+            loggers.log(
+                LogLevel::INFO,
+                &format!("Writing audit log to {}", audit.path().display()),
+            );
+        }
+
+        if let Some(metrics_listener) = self.metrics_listener.take() {
+            loggers.log(
+                LogLevel::INFO,
+                &format!(
+                    "Serving metrics on http://{}/metrics",
+                    self.metrics_addr.unwrap()
+                ),
+            );
+
+            let metrics = self.shared.metrics.clone();
+            let metrics_loggers = loggers.clone();
+            let shutdown = self.shared.shutdown.clone();
+
+            thread::Builder::new()
+                .name(String::from("squeef-metrics"))
+                .spawn(move || {
+                    metrics::serve(
+                        metrics_listener,
+                        metrics,
+                        metrics_loggers,
+                        shutdown,
+                        POLL_INTERVAL,
+                    )
+                })
+                .unwrap();
+        }
+
+        let mut total_connections: usize = 0;
+        let mut last_gc = Instant::now();
+
+        'accept: while !self.shared.shutdown.load(Ordering::SeqCst) {
+            let mut accepted = false;
+
+            for listener in &listeners {
+                match listener.accept() {
+                    Ok((stream, peer)) => {
+                        accepted = true;
+
+                        let id = self.next_connection_id;
+                        self.next_connection_id += 1;
+
+                        let context = || vec![("conn", id.to_string()), ("peer", peer.clone())];
+
+                        loggers.log_with_context(LogLevel::INFO, context, "Incoming connection");
+
+                        let max_concurrent_connections = self
+                            .shared
+                            .settings
+                            .read()
+                            .unwrap()
+                            .max_concurrent_connections;
+
+                        if self.shared.active_connections.load(Ordering::SeqCst)
+                            >= max_concurrent_connections
+                        {
+                            self.shared.metrics.connection_refused();
+
+                            loggers.log_with_context(
+                                LogLevel::WARN,
+                                context,
+                                &format!(
+                                    "Connection refused. Limit of {} concurrent connections reached",
+                                    max_concurrent_connections
+                                ),
+                            );
+                            continue;
+                        }
+
+                        let stream = match &self.tls {
+                            Some(config) => match stream.into_tls(config.clone()) {
+                                Ok(stream) => stream,
+                                Err(e) => {
+                                    loggers.log_with_context(
+                                        LogLevel::ERROR,
+                                        context,
+                                        &format!("Failed to set up TLS: {}", e),
+                                    );
+                                    continue;
+                                }
+                            },
+                            None => stream,
+                        };
+
+                        total_connections += 1;
+
+                        let mut client_connection =
+                            ClientConnection::new(id, stream, peer, self.shared.clone());
+
+                        thread::spawn(move || client_connection.run());
+                    }
+                    Err(e) if e.kind() == ErrorKind::WouldBlock => {}
+                    Err(e) => {
+                        loggers.log(LogLevel::ERROR, &e.to_string());
+                        break 'accept;
+                    }
+                }
+            }
+
+            if last_gc.elapsed() >= GC_INTERVAL {
+                self.collect_garbage();
+                last_gc = Instant::now();
+            }
+
+            if !accepted {
+                thread::sleep(POLL_INTERVAL);
+            }
+        }
+
+        // Stop accepting new connections before draining the existing ones
+        drop(listeners);
+
+        self.shared.shutdown.store(true, Ordering::SeqCst);
+
+        self.drain(total_connections);
+    }
+
+    /// Remove the versions of databases and tables no running transaction can see anymore
+    fn collect_garbage(&self) -> () {
+        let horizon = self.shared.transactions.horizon();
+        let removed =
+            transaction::collect_garbage(&mut self.shared.databases.write().unwrap(), horizon);
+
+        if removed > 0 {
+            self.shared.loggers.log(
+                LogLevel::DEBUG,
+                &format!("Removed {} dead version(s)", removed),
+            );
+        }
+    }
+
+    fn drain(&mut self, total_connections: usize) -> () {
+        let loggers = &self.shared.loggers;
+        let active_connections = &self.shared.active_connections;
+
+        let shutdown_start = Instant::now();
+        let shutdown_grace_period = self.shared.settings.read().unwrap().shutdown_grace_period;
+        let in_flight = active_connections.load(Ordering::SeqCst);
+
+        loggers.log(
+            LogLevel::INFO,
+            &format!(
+                "Shutting down. Waiting up to {}s for {} connection(s) to close",
+                shutdown_grace_period.as_secs(),
+                in_flight
+            ),
+        );
+
+        while active_connections.load(Ordering::SeqCst) > 0
+            && shutdown_start.elapsed() < shutdown_grace_period
+        {
+            thread::sleep(POLL_INTERVAL);
+        }
+
+        let abandoned = active_connections.load(Ordering::SeqCst);
+
+        if abandoned > 0 {
+            loggers.log(
+                LogLevel::WARN,
+                &format!(
+                    "Grace period expired. Abandoning {} connection(s) still in flight",
+                    abandoned
+                ),
+            );
+        }
+
+        // Nothing is persisted under `persistent_storage_dir` yet, so the only buffered output left
+        // to flush are the loggers
+        loggers.log(
+            LogLevel::INFO,
+            &format!(
+                "Shutdown complete in {}ms. Served {} connection(s), drained {}, abandoned {}",
+                shutdown_start.elapsed().as_millis(),
+                total_connections,
+                in_flight.saturating_sub(abandoned),
+                abandoned
+            ),
+        );
+
+        if let Some(slow_query_loggers) = &self.shared.slow_query_loggers {
+            slow_query_loggers.flush();
+        }
+
+        loggers.flush();
+    }
+}
+
+/// Handle to a server running on a background thread, see `ServerBuilder::spawn`. Dropping the
+/// handle stops the server.
+#[derive(Debug)]
+pub struct ServerHandle {
+    local_addrs: Vec<BindAddress>,
+    metrics_addr: Option<SocketAddr>,
+    shutdown: Arc<AtomicBool>,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl ServerHandle {
+    pub fn local_addrs(&self) -> &[BindAddress] {
+        return &self.local_addrs;
+    }
+
+    /// First TCP address the server listens on
+    pub fn local_addr(&self) -> Option<SocketAddr> {
+        return self.local_addrs.iter().find_map(|addr| match addr {
+            BindAddress::Tcp(addr) => addr.parse().ok(),
+            BindAddress::Unix(_) => None,
+        });
+    }
+
+    /// Address of the `/metrics` HTTP endpoint, if one was requested with `metrics_bind`
+    pub fn metrics_addr(&self) -> Option<SocketAddr> {
+        return self.metrics_addr;
+    }
+
+    /// Stop accepting connections, wait for the open ones to drain and join the server thread
+    pub fn stop(mut self) -> () {
+        self.stop_and_join();
+    }
+
+    fn stop_and_join(&mut self) -> () {
+        self.shutdown.store(true, Ordering::SeqCst);
+
+        if let Some(thread) = self.thread.take() {
+            // A panic on the server thread was already reported by the panic hook
+            let _ = thread.join();
+        }
+    }
+}
+
+impl Drop for ServerHandle {
+    fn drop(&mut self) {
+        self.stop_and_join();
+    }
+}
+
+pub struct ClientConnection {
+    id: u64,
+    stream: MeteredStream<Stream>,
+    peer: String,
+    client_name: Option<String>,
+    shared: Arc<Shared>,
+    open_db: Option<String>,
+
+    /// Open transaction, between BEGIN and COMMIT or ROLLBACK. Commands outside of one apply
+    /// straight away.
+    transaction: Option<Transaction>,
+
+    /// User the client authenticated as, `None` for anonymous connections
+    user: Option<String>,
+
+    /// Whether the client can run commands, either as `user` or anonymously
+    authenticated: bool,
+
+    /// Challenge sent in answer to AUTHENTICATE, and the user it was for, until the proof comes
+    pending_auth: Option<(String, Challenge, Option<Credentials>)>,
+
+    /// Why sending to the client failed, which closes the connection
+    write_error: Option<io::Error>,
+
+    /// Responses of the audited command running, only sent once its audit record is written
+    held_responses: Option<Vec<u8>>,
+
+    /// Whether the open transaction ran audited commands, which its COMMIT or ROLLBACK decides
+    audited_in_transaction: bool,
+}
+
+impl ClientConnection {
+    fn new(id: u64, stream: Stream, peer: String, shared: Arc<Shared>) -> ClientConnection {
+        shared.active_connections.fetch_add(1, Ordering::SeqCst);
+        shared.metrics.connection_opened();
+
+        let authenticated = shared.settings.read().unwrap().allow_anonymous;
+
+        ClientConnection {
+            id,
+            stream: MeteredStream::new(stream, shared.metrics.clone()),
+            peer,
+            client_name: None,
+            shared,
+            open_db: None,
+            transaction: None,
+            user: None,
+            authenticated,
+            pending_auth: None,
+            write_error: None,
+            held_responses: None,
+            audited_in_transaction: false,
+        }
+    }
+
+    /// Fields identifying this connection in its log records
+    fn log_context(&self) -> LogContext {
+        let mut context = vec![("conn", self.id.to_string()), ("peer", self.peer.clone())];
+
+        if let Some(client_name) = &self.client_name {
+            context.push(("client", client_name.clone()));
+        }
+
+        if let Some(user) = &self.user {
+            context.push(("user", user.clone()));
+        }
+
+        if let Some(open_db) = &self.open_db {
+            context.push(("db", open_db.clone()));
+        }
+
+        return context;
+    }
+
+    fn log(&self, log_level: LogLevel, msg: &str) -> () {
+        self.shared
+            .loggers
+            .log_with_context(log_level, || self.log_context(), msg);
+    }
+
+    fn run(&mut self) -> () {
+        loop {
+            let first_byte = match self.wait_for_msg() {
+                Ok(Some(byte)) => byte,
+                Ok(None) => {
+                    self.log(LogLevel::INFO, "Closing connection for shutdown");
+                    return;
+                }
+                Err(e) if e.kind() == ErrorKind::UnexpectedEof => {
+                    self.log(LogLevel::INFO, "Connection closed");
+                    return;
+                }
+                Err(e) => {
+                    self.shared.metrics.error("io");
+                    self.log(LogLevel::ERROR, &e.to_string());
+                    return;
+                }
+            };
+
+            match utils::read_msg(&mut [first_byte].as_slice().chain(&mut self.stream)) {
+                Ok(msg) => {
+                    if let Err(e) = self.process_msg(msg.as_slice()) {
+                        self.log(LogLevel::ERROR, &e.to_string());
+                    }
+
+                    if let Some(e) = self.write_error.take() {
+                        self.shared.metrics.error("io");
+                        self.log(
+                            LogLevel::ERROR,
+                            &format!("Failed to send a response, closing connection. {}", e),
+                        );
+                        return;
+                    }
+                }
+                Err(e) => match e.kind() {
+                    ErrorKind::UnexpectedEof => {
+                        self.log(LogLevel::INFO, "Connection closed");
+                        return;
+                    }
+                    _ => {
+                        self.shared.metrics.error("io");
+                        self.log(LogLevel::ERROR, &e.to_string());
+                        return;
+                    }
+                },
+            }
+        }
+    }
+
+    /// Block until the client starts sending a message and return its first byte, or `None` if a
+    /// shutdown is requested first. Requests which already started arriving are always served.
+    fn wait_for_msg(&mut self) -> Result<Option<u8>, io::Error> {
+        let mut first_byte = [0; 1];
+
+        self.stream
+            .get_ref()
+            .set_read_timeout(Some(POLL_INTERVAL))?;
+
+        let res = loop {
+            match self.stream.read(&mut first_byte) {
+                Ok(0) => break Err(io::Error::from(ErrorKind::UnexpectedEof)),
+                Ok(_) => break Ok(Some(first_byte[0])),
+                Err(e) if e.kind() == ErrorKind::WouldBlock || e.kind() == ErrorKind::TimedOut => {
+                    if self.shared.shutdown.load(Ordering::SeqCst) {
+                        break Ok(None);
+                    }
+                }
+                Err(e) if e.kind() == ErrorKind::Interrupted => {}
+                Err(e) => break Err(e),
+            }
+        };
+
+        // Don't time out halfway through reading a message
+        self.stream.get_ref().set_read_timeout(None)?;
+
+        return res;
+    }
+
+    /// Run the command in `msg`. A command which fails answers with an error frame rather than
+    /// its usual response.
+    fn process_msg(&mut self, msg: &[u8]) -> Result<(), String> {
+        let res = match msg.len() {
+            0 => Err(Error::new(
+                ErrorCode::BadRequest,
+                String::from("Invalid message: incomplete header"),
+            )),
+            _ => match v0::request::parse(msg) {
+                Ok(cmd) => self.run_command(cmd),
+                Err(e) => Err(Error::new(ErrorCode::BadRequest, e)),
+            },
+        };
+
+        if let Err(e) = &res {
+            self.shared.metrics.error(e.code.name());
+            self.write_msg(&v0::response::serialise_error(e));
+        }
+
+        return res.map_err(|e| e.to_string());
+    }
+
+    fn run_command(&mut self, cmd: Command) -> Result<(), Error> {
+        let cmd_name = cmd.name();
+
+        let slow_query_threshold = match self.shared.slow_query_loggers {
+            Some(_) => self.shared.settings.read().unwrap().slow_query_threshold,
+            None => None,
+        };
+
+        // Only pay for describing the command when it may end up in the slow query log
+        let cmd_desc = slow_query_threshold.map(|_| describe_command(&cmd));
+
+        let needs_authentication = !matches!(
+            cmd,
+            Command::SetClientName { .. }
+                | Command::Authenticate { .. }
+                | Command::AuthProof { .. }
+        );
+
+        // Commands which aren't audited are still recorded when they're denied
+        let audit_command = match self.shared.audit {
+            Some(_) => Some(match self.audit_command(&cmd) {
+                Some((user, command)) => (user, command, true),
+                None => (
+                    self.user.clone().unwrap_or_default(),
+                    self.describe_denied(&cmd),
+                    false,
+                ),
+            }),
+            None => None,
+        };
+
+        if needs_authentication && !self.authenticated {
+            let res = Err(Error::new(
+                ErrorCode::AuthenticationFailed,
+                String::from("Authentication required"),
+            ));
+
+            if let Some((user, command, _)) = audit_command {
+                let _ = self.audit(&user, &command, &res);
+            }
+
+            return res;
+        }
+
+        // Audited commands don't run while their audit record can't be written, and don't answer
+        // until it is. Rolling back is let through, as it only undoes changes.
+        let rolls_back = matches!(cmd, Command::Rollback | Command::RollbackToSavepoint { .. });
+
+        if let Some((_, command, true)) = &audit_command {
+            let writable = self.shared.audit.as_ref().unwrap().check_writable();
+
+            if let (Err(e), false) = (writable, rolls_back) {
+                return Err(Error::from(format!("{} refused. {}", command, e)));
+            }
+
+            self.held_responses = Some(vec![]);
+        }
+
+        let in_transaction = self.transaction.is_some();
+
+        let start = Instant::now();
+
+        // Implicit savepoint, so a failed command doesn't take the rest of the transaction with it
+        let mut statement_mark = None;
+
+        if let Some(txn) = &mut self.transaction {
+            let accesses_databases = !matches!(
+                cmd,
+                Command::SetClientName { .. }
+                    | Command::Begin
+                    | Command::Commit
+                    | Command::Rollback
+                    | Command::SetIsolationLevel { .. }
+                    | Command::Savepoint { .. }
+                    | Command::ReleaseSavepoint { .. }
+                    | Command::RollbackToSavepoint { .. }
+                    | Command::Authenticate { .. }
+                    | Command::AuthProof { .. }
+                    | Command::CreateUser { .. }
+                    | Command::AlterUser { .. }
+                    | Command::DropUser { .. }
+                    | Command::CreateRole { .. }
+                    | Command::DropRole { .. }
+                    | Command::GrantRole { .. }
+                    | Command::RevokeRole { .. }
+                    | Command::Grant { .. }
+                    | Command::Revoke { .. }
+            );
+
+            if accesses_databases {
+                txn.start_command(&self.shared.transactions);
+                statement_mark = Some(txn.mark());
+            }
+        }
+
+        let mut res = match cmd {
+            Command::CreateDatabase { name } => self.exec_create_db(name),
+            Command::OpenDatabase { name } => self.exec_open_db(name),
+            Command::CreateTable {
+                name,
+                cols,
+                constraints,
+            } => self.exec_create_table(name, cols, constraints),
+            Command::DropDatabase {
+                name,
+                if_exists,
+                cascade,
+            } => self.exec_drop_db(name, if_exists, cascade),
+            Command::DropTable {
+                name,
+                if_exists,
+                cascade,
+            } => self.exec_drop_table(name, if_exists, cascade),
+            Command::RenameDatabase { name, new_name } => self.exec_rename_db(name, new_name),
+            Command::RenameTable { name, new_name } => self.exec_rename_table(name, new_name),
+            Command::DescribeTable { name } => self.exec_describe_table(name),
+            Command::AlterTable { name, action } => self.exec_alter_table(name, action),
+            Command::CreatePolicy { table, policy } => self.exec_create_policy(table, policy),
+            Command::DropPolicy { table, name } => self.exec_drop_policy(table, name),
+            Command::ListDatabases => self.exec_list_databases(),
+            Command::ListTables => self.exec_list_tables(),
+            Command::SetClientName { name } => self.exec_set_client_name(name),
+            Command::Begin => self.exec_begin(),
+            Command::Commit => self.exec_commit(),
+            Command::Rollback => self.exec_rollback(),
+            Command::SetIsolationLevel { level } => self.exec_set_isolation_level(level),
+            Command::LockTable { name, mode, nowait } => self.exec_lock_table(name, mode, nowait),
+            Command::Savepoint { name } => self.exec_savepoint(name),
+            Command::ReleaseSavepoint { name } => self.exec_release_savepoint(name),
+            Command::RollbackToSavepoint { name } => self.exec_rollback_to_savepoint(name),
+            Command::Authenticate { user } => self.exec_authenticate(user),
+            Command::AuthProof { proof } => self.exec_auth_proof(proof),
+            Command::CreateUser { name, credentials } => self.exec_create_user(name, credentials),
+            Command::AlterUser { name, credentials } => self.exec_alter_user(name, credentials),
+            Command::DropUser { name } => self.exec_drop_user(name),
+            Command::CreateRole { name } => self.exec_create_role(name),
+            Command::DropRole { name } => self.exec_drop_role(name),
+            Command::GrantRole { role, user } => self.exec_grant_role(role, user),
+            Command::RevokeRole { role, user } => self.exec_revoke_role(role, user),
+            Command::Grant {
+                privileges,
+                on,
+                role,
+            } => self.exec_grant(privileges, on, role),
+            Command::Revoke {
+                privileges,
+                on,
+                role,
+            } => self.exec_revoke(privileges, on, role),
+        };
+
+        // A deadlock victim's transaction is already gone
+        if let (Err(_), Some(mark), Some(txn)) = (&res, statement_mark, &mut self.transaction) {
+            txn.rollback_to_mark(mark, &mut self.shared.databases.write().unwrap());
+        }
+
+        if let Some((user, command, always)) = audit_command {
+            let denied = matches!(&res, Err(e) if e.code == ErrorCode::PermissionDenied);
+
+            if always || denied {
+                if let (Err(e), Ok(())) = (self.audit(&user, &command, &res), &res) {
+                    res = Err(e);
+                }
+            }
+
+            if always && res.is_ok() && in_transaction && self.transaction.is_some() {
+                self.audited_in_transaction = true;
+            }
+        }
+
+        if self.transaction.is_none() {
+            self.audited_in_transaction = false;
+        }
+
+        if let Some(held) = self.held_responses.take() {
+            if res.is_ok() {
+                self.send(&held);
+            }
+        }
+
+        let elapsed = start.elapsed();
+
+        self.shared.metrics.command_executed(cmd_name, elapsed);
+
+        if let (Some(threshold), Some(cmd_desc), Some(slow_query_loggers)) = (
+            slow_query_threshold,
+            cmd_desc,
+            &self.shared.slow_query_loggers,
+        ) {
+            if elapsed >= threshold {
+                slow_query_loggers.log_with_context(
+                    LogLevel::WARN,
+                    || {
+                        let mut context = self.log_context();
+                        context.push(("duration_us", elapsed.as_micros().to_string()));
+                        context
+                    },
+                    &format!("Slow command ({}ms): {}", elapsed.as_millis(), cmd_desc),
+                );
+            }
+        }
+
+        return res;
+    }
+
+    fn exec_create_db(&mut self, name: String) -> Result<(), Error> {
+        self.require_superuser("CREATE DATABASE")?;
+
+        let locks = vec![(
+            LockTarget::Database { name: name.clone() },
+            LockMode::Exclusive,
+        )];
+
+        self.in_transaction(locks, |txn, databases, txns| {
+            txn.create_database(databases, txns, name.clone())
+        })?;
+
+        self.log(LogLevel::INFO, &format!("Created database [{}]", name));
+
+        self.send(&[0x02, 0x00, 0x00, 0x00, 0x00, 0x01]);
+
+        return Ok(());
+    }
+
+    fn exec_open_db(&mut self, name: String) -> Result<(), Error> {
+        self.record_read(transaction::Read::Database { name: name.clone() });
+
+        {
+            // Held until the database is registered as open, so that a drop or rename either
+            // commits first and hides it or sees it open, see `end_transaction`
+            let databases = self.shared.databases.read().unwrap();
+
+            if !transaction::visible(&databases, &self.snapshot()).any(|db| db.name == name) {
+                return Err(Error::from(format!(
+                    "Failed to open database. No database with name [{}]",
+                    name
+                )));
+            }
+
+            if !self.can_access_database(&name) {
+                return Err(permission_denied(format!(
+                    "Failed to open database. No privilege on database [{}]",
+                    name
+                )));
+            }
+
+            self.shared
+                .open_databases
+                .lock()
+                .unwrap()
+                .insert(self.id, name.clone());
+        }
+
+        self.open_db = Some(name.clone());
+
+        self.log(LogLevel::DEBUG, &format!("Opened database [{}]", name));
+
+        self.send(&[0x02, 0x00, 0x00, 0x00, 0x01, 0x01]);
+
+        return Ok(());
+    }
+
+    fn exec_create_table(
+        &mut self,
+        name: String,
+        cols: Vec<Column>,
+        constraints: Vec<Constraint>,
+    ) -> Result<(), Error> {
+        let db = match &self.open_db {
+            Some(db) => db.clone(),
+            None => {
+                return Err(Error::from(String::from(
+                    "CREATE TABLE failed. No open database",
+                )))
+            }
+        };
+
+        self.check_privilege(
+            &[Privilege::Create],
+            &Object::Database { name: db.clone() },
+            "CREATE TABLE",
+        )?;
+
+        let mut locks = vec![
+            (LockTarget::Database { name: db.clone() }, LockMode::Shared),
+            (
+                LockTarget::Table {
+                    db: db.clone(),
+                    name: name.clone(),
+                },
+                LockMode::Exclusive,
+            ),
+        ];
+
+        locks.extend(self.lock_referenced(&db, &name, &constraints, "CREATE TABLE")?);
+
+        let mut table = Table::new(name.clone(), cols, constraints)
+            .map_err(|e| Error::from(format!("CREATE TABLE failed. {}", e)))?;
+        table.owner = self.user.clone();
+
+        self.in_transaction(locks, |txn, databases, txns| {
+            txn.create_table(databases, txns, &db, table)
+        })?;
+
+        self.log(
+            LogLevel::INFO,
+            &format!("Created table [{}] in database [{}]", name, db),
+        );
+
+        self.send(&[0x02, 0x00, 0x00, 0x00, 0x02, 0x01]);
+
+        return Ok(());
+    }
+
+    fn exec_drop_db(&mut self, name: String, if_exists: bool, cascade: bool) -> Result<(), Error> {
+        self.require_superuser("DROP DATABASE")?;
+        self.check_not_open(&name, "DROP DATABASE")?;
+
+        let locks = vec![(
+            LockTarget::Database { name: name.clone() },
+            LockMode::Exclusive,
+        )];
+
+        let mut dropped = false;
+
+        self.in_transaction(locks, |txn, databases, _| {
+            dropped = txn.drop_database(databases, &name, if_exists, cascade)?;
+            Ok(())
+        })?;
+
+        match dropped {
+            true => self.log(LogLevel::INFO, &format!("Dropped database [{}]", name)),
+            false => self.log(
+                LogLevel::DEBUG,
+                &format!("Database [{}] doesn't exist, skipped DROP", name),
+            ),
+        }
+
+        self.send(&[0x02, 0x00, 0x00, 0x00, 0x19, dropped as u8]);
+
+        return Ok(());
+    }
+
+    fn exec_drop_table(
+        &mut self,
+        name: String,
+        if_exists: bool,
+        cascade: bool,
+    ) -> Result<(), Error> {
+        let db = match &self.open_db {
+            Some(db) => db.clone(),
+            None => {
+                return Err(Error::from(String::from(
+                    "DROP TABLE failed. No open database",
+                )))
+            }
+        };
+
+        self.check_privilege(
+            &[Privilege::Create],
+            &Object::Database { name: db.clone() },
+            "DROP TABLE",
+        )?;
+
+        let locks = vec![
+            (LockTarget::Database { name: db.clone() }, LockMode::Shared),
+            (
+                LockTarget::Table {
+                    db: db.clone(),
+                    name: name.clone(),
+                },
+                LockMode::Exclusive,
+            ),
+        ];
+
+        let mut dropped = false;
+
+        self.in_transaction(locks, |txn, databases, _| {
+            dropped = txn.drop_table(databases, &db, &name, if_exists, cascade)?;
+            Ok(())
+        })?;
+
+        match dropped {
+            true => self.log(
+                LogLevel::INFO,
+                &format!("Dropped table [{}] in database [{}]", name, db),
+            ),
+            false => self.log(
+                LogLevel::DEBUG,
+                &format!("Table [{}::{}] doesn't exist, skipped DROP", db, name),
+            ),
+        }
+
+        self.send(&[0x02, 0x00, 0x00, 0x00, 0x1A, dropped as u8]);
+
+        return Ok(());
+    }
+
+    fn exec_rename_db(&mut self, name: String, new_name: String) -> Result<(), Error> {
+        self.require_superuser("ALTER DATABASE")?;
+        self.check_not_open(&name, "ALTER DATABASE")?;
+
+        let locks = vec![
+            (
+                LockTarget::Database { name: name.clone() },
+                LockMode::Exclusive,
+            ),
+            (
+                LockTarget::Database {
+                    name: new_name.clone(),
+                },
+                LockMode::Exclusive,
+            ),
+        ];
+
+        self.in_transaction(locks, |txn, databases, txns| {
+            txn.rename_database(databases, txns, &name, &new_name)
+        })?;
+
+        self.log(
+            LogLevel::INFO,
+            &format!("Renamed database [{}] to [{}]", name, new_name),
+        );
+
+        self.send(&[0x02, 0x00, 0x00, 0x00, 0x1B, 0x01]);
+
+        return Ok(());
+    }
+
+    fn exec_rename_table(&mut self, name: String, new_name: String) -> Result<(), Error> {
+        let db = match &self.open_db {
+            Some(db) => db.clone(),
+            None => {
+                return Err(Error::from(String::from(
+                    "ALTER TABLE failed. No open database",
+                )))
+            }
+        };
+
+        self.check_privilege(
+            &[Privilege::Create],
+            &Object::Database { name: db.clone() },
+            "ALTER TABLE",
+        )?;
+
+        let locks = vec![
+            (LockTarget::Database { name: db.clone() }, LockMode::Shared),
+            (
+                LockTarget::Table {
+                    db: db.clone(),
+                    name: name.clone(),
+                },
+                LockMode::Exclusive,
+            ),
+            (
+                LockTarget::Table {
+                    db: db.clone(),
+                    name: new_name.clone(),
+                },
+                LockMode::Exclusive,
+            ),
+        ];
+
+        self.in_transaction(locks, |txn, databases, txns| {
+            txn.rename_table(databases, txns, &db, &name, &new_name)
+        })?;
+
+        self.log(
+            LogLevel::INFO,
+            &format!(
+                "Renamed table [{}] to [{}] in database [{}]",
+                name, new_name, db
+            ),
+        );
+
+        self.send(&[0x02, 0x00, 0x00, 0x00, 0x1C, 0x01]);
+
+        return Ok(());
+    }
+
+    fn exec_alter_table(&mut self, name: String, action: AlterTableAction) -> Result<(), Error> {
+        let db = match &self.open_db {
+            Some(db) => db.clone(),
+            None => {
+                return Err(Error::from(String::from(
+                    "ALTER TABLE failed. No open database",
+                )))
+            }
+        };
+
+        self.check_privilege(
+            &[Privilege::Create],
+            &Object::Database { name: db.clone() },
+            "ALTER TABLE",
+        )?;
+
+        let mut locks = vec![
+            (LockTarget::Database { name: db.clone() }, LockMode::Shared),
+            (
+                LockTarget::Table {
+                    db: db.clone(),
+                    name: name.clone(),
+                },
+                LockMode::Exclusive,
+            ),
+        ];
+
+        let added = match &action {
+            AlterTableAction::AddColumn { constraints, .. } => constraints.as_slice(),
+            AlterTableAction::AddConstraint { constraint } => std::slice::from_ref(constraint),
+            _ => &[],
+        };
+
+        locks.extend(self.lock_referenced(&db, &name, added, "ALTER TABLE")?);
+
+        let desc = describe_alter_table_action(&action);
+
+        self.in_transaction(locks, |txn, databases, _| {
+            txn.alter_table(databases, &db, &name, "ALTER TABLE", |table| match action {
+                AlterTableAction::AddColumn {
+                    column,
+                    constraints,
+                } => table.add_column(column, constraints),
+                AlterTableAction::DropColumn { name } => table.drop_column(&name),
+                AlterTableAction::RenameColumn { name, new_name } => {
+                    table.rename_column(&name, new_name)
+                }
+                AlterTableAction::AlterColumnType { name, column_type } => {
+                    table.alter_column_type(&name, column_type)
+                }
+                AlterTableAction::SetDefault { name, default } => table.set_default(&name, default),
+                AlterTableAction::SetNotNull { name, not_null } => {
+                    table.set_not_null(&name, not_null)
+                }
+                AlterTableAction::AddConstraint { constraint } => table.add_constraint(constraint),
+                AlterTableAction::DropConstraint { name } => table.drop_constraint(&name),
+            })
+        })?;
+
+        self.log(
+            LogLevel::INFO,
+            &format!("Altered table [{}] in database [{}]: {}", name, db, desc),
+        );
+
+        self.send(&[0x02, 0x00, 0x00, 0x00, 0x1E, 0x01]);
+
+        return Ok(());
+    }
+
+    fn exec_create_policy(&mut self, table: String, policy: Policy) -> Result<(), Error> {
+        let db = match &self.open_db {
+            Some(db) => db.clone(),
+            None => {
+                return Err(Error::from(String::from(
+                    "CREATE POLICY failed. No open database",
+                )))
+            }
+        };
+
+        if !self.shared.acl.read().unwrap().role_exists(&policy.role) {
+            return Err(Error::from(format!(
+                "CREATE POLICY failed. No role with name [{}]",
+                policy.role
+            )));
+        }
+
+        self.require_owner(&db, &table, "CREATE POLICY")?;
+
+        let locks = vec![
+            (LockTarget::Database { name: db.clone() }, LockMode::Shared),
+            (
+                LockTarget::Table {
+                    db: db.clone(),
+                    name: table.clone(),
+                },
+                LockMode::Exclusive,
+            ),
+        ];
+
+        let desc = policy.describe();
+
+        self.in_transaction(locks, |txn, databases, _| {
+            txn.alter_table(databases, &db, &table, "CREATE POLICY", |tb| {
+                tb.add_policy(policy)
+            })
+        })?;
+
+        self.log(
+            LogLevel::INFO,
+            &format!(
+                "Created policy on table [{}] in database [{}]: {}",
+                table, db, desc
+            ),
+        );
+
+        self.send(&[0x02, 0x00, 0x00, 0x00, 0x1F, 0x01]);
+
+        return Ok(());
+    }
+
+    fn exec_drop_policy(&mut self, table: String, name: String) -> Result<(), Error> {
+        let db = match &self.open_db {
+            Some(db) => db.clone(),
+            None => {
+                return Err(Error::from(String::from(
+                    "DROP POLICY failed. No open database",
+                )))
+            }
+        };
+
+        self.require_owner(&db, &table, "DROP POLICY")?;
+
+        let locks = vec![
+            (LockTarget::Database { name: db.clone() }, LockMode::Shared),
+            (
+                LockTarget::Table {
+                    db: db.clone(),
+                    name: table.clone(),
+                },
+                LockMode::Exclusive,
+            ),
+        ];
+
+        self.in_transaction(locks, |txn, databases, _| {
+            txn.alter_table(databases, &db, &table, "DROP POLICY", |tb| {
+                tb.drop_policy(&name)
+            })
+        })?;
+
+        self.log(
+            LogLevel::INFO,
+            &format!(
+                "Dropped policy [{}] on table [{}] in database [{}]",
+                name, table, db
+            ),
+        );
+
+        self.send(&[0x02, 0x00, 0x00, 0x00, 0x20, 0x01]);
+
+        return Ok(());
+    }
+
+    /// Shared locks on the tables the foreign keys of `constraints` on table `name` reference, so
+    /// that they can't be dropped or altered until the transaction ends. Fails unless the user
+    /// holds REFERENCES on them.
+    fn lock_referenced(
+        &self,
+        db: &str,
+        name: &str,
+        constraints: &[Constraint],
+        cmd: &str,
+    ) -> Result<Vec<(LockTarget, LockMode)>, Error> {
+        let mut locks = vec![];
+
+        for constraint in constraints {
+            let ref_table = match &constraint.kind {
+                ConstraintKind::ForeignKey(fk) if fk.ref_table != name => fk.ref_table.clone(),
+                _ => continue,
+            };
+
+            self.check_privilege(
+                &[Privilege::References],
+                &Object::Table {
+                    db: String::from(db),
+                    name: ref_table.clone(),
+                },
+                cmd,
+            )?;
+
+            locks.push((
+                LockTarget::Table {
+                    db: String::from(db),
+                    name: ref_table,
+                },
+                LockMode::Shared,
+            ));
+        }
+
+        return Ok(locks);
+    }
+
+    /// Fail if a connection, this one included, has database `name` open
+    fn check_not_open(&self, name: &str, cmd: &str) -> Result<(), Error> {
+        let open_databases = self.shared.open_databases.lock().unwrap();
+
+        if open_databases.get(&self.id).is_some_and(|db| db == name) {
+            return Err(Error::from(format!(
+                "{} failed. Database [{}] is open in this session",
+                cmd, name
+            )));
+        }
+
+        let others = open_databases.values().filter(|db| *db == name).count();
+
+        if others > 0 {
+            return Err(Error::from(format!(
+                "{} failed. Database [{}] is open in {} other session(s)",
+                cmd, name, others
+            )));
+        }
+
+        return Ok(());
+    }
+
+    fn exec_list_databases(&mut self) -> Result<(), Error> {
+        self.record_read(transaction::Read::Databases);
+
+        let snapshot = self.snapshot();
+
+        // Databases the user can't open aren't listed
+        let names: Vec<String> =
+            transaction::visible(&self.shared.databases.read().unwrap(), &snapshot)
+                .map(|db| db.name.clone())
+                .filter(|name| self.can_access_database(name))
+                .collect();
+
+        self.write_msg(&v0::response::serialise_list_databases(&names));
+
+        return Ok(());
+    }
+
+    fn exec_list_tables(&mut self) -> Result<(), Error> {
+        let db = match &self.open_db {
+            Some(db) => db.clone(),
+            None => {
+                return Err(Error::from(String::from(
+                    "LIST TABLES failed. No open database",
+                )))
+            }
+        };
+
+        self.record_read(transaction::Read::Tables { db: db.clone() });
+
+        let snapshot = self.snapshot();
+
+        // Tables the user holds no privilege on aren't listed
+        let names: Vec<String> =
+            transaction::visible(&self.shared.databases.read().unwrap(), &snapshot)
+                .filter(|d| d.name == db)
+                .flat_map(|d| transaction::visible(&d.tables, &snapshot))
+                .map(|tb| tb.name.clone())
+                .filter(|name| self.can_access_table(&db, name))
+                .collect();
+
+        self.write_msg(&v0::response::serialise_list_tables(&names));
+
+        return Ok(());
+    }
+
+    fn exec_describe_table(&mut self, name: String) -> Result<(), Error> {
+        let db = match &self.open_db {
+            Some(db) => db.clone(),
+            None => {
+                return Err(Error::from(String::from(
+                    "DESCRIBE failed. No open database",
+                )))
+            }
+        };
+
+        self.check_privilege(
+            &Privilege::ALL,
+            &Object::Table {
+                db: db.clone(),
+                name: name.clone(),
+            },
+            "DESCRIBE",
+        )?;
+
+        self.record_read(transaction::Read::Tables { db: db.clone() });
+
+        let snapshot = self.snapshot();
+
+        let table = transaction::visible(&self.shared.databases.read().unwrap(), &snapshot)
+            .filter(|d| d.name == db)
+            .flat_map(|d| transaction::visible(&d.tables, &snapshot))
+            .find(|tb| tb.name == name)
+            .cloned();
+
+        let table = match table {
+            Some(table) => table,
+            None => {
+                return Err(Error::from(format!(
+                    "DESCRIBE failed. No table with name [{}::{}]",
+                    db, name
+                )))
+            }
+        };
+
+        self.write_msg(&v0::response::serialise_describe_table(&table));
+
+        return Ok(());
+    }
+
+    fn exec_set_client_name(&mut self, name: String) -> Result<(), Error> {
+        self.log(LogLevel::DEBUG, &format!("Client name set to [{}]", name));
+
+        self.client_name = Some(name);
+
+        self.send(&[0x02, 0x00, 0x00, 0x00, 0x05, 0x01]);
+
+        return Ok(());
+    }
+
+    fn write_msg(&mut self, msg: &[u8]) -> () {
+        let mut frame = Vec::with_capacity(4 + msg.len());
+        frame.extend_from_slice(&(msg.len() as u32).to_le_bytes());
+        frame.extend_from_slice(msg);
+
+        self.send(&frame);
+    }
+
+    /// Write `bytes` to the client. Once a write fails, nothing more is sent and the connection
+    /// is closed after the current command, see `ClientConnection::run`.
+    fn send(&mut self, bytes: &[u8]) -> () {
+        if let Some(held) = &mut self.held_responses {
+            held.extend_from_slice(bytes);
+            return;
+        }
+
+        if self.write_error.is_some() {
+            return;
+        }
+
+        if let Err(e) = self
+            .stream
+            .write_all(bytes)
+            .and_then(|_| self.stream.flush())
+        {
+            self.write_error = Some(e);
+        }
+    }
+
+    fn record_read(&mut self, read: transaction::Read) -> () {
+        if let Some(txn) = &mut self.transaction {
+            txn.record_read(read);
+        }
+    }
+
+    /// Snapshot reads go through: the open transaction's, or the latest committed state
+    fn snapshot(&self) -> Snapshot {
+        return match &self.transaction {
+            Some(txn) => txn.snapshot().clone(),
+            None => self.shared.transactions.snapshot(),
+        };
+    }
+
+    /// Acquire `locks` then run `f` in the open transaction, or in a transaction of its own which
+    /// commits straight away if there is none
+    fn in_transaction(
+        &mut self,
+        locks: Vec<(LockTarget, LockMode)>,
+        f: impl FnOnce(&mut Transaction, &mut Databases, &TxnManager) -> Result<(), Error>,
+    ) -> Result<(), Error> {
+        let implicit = self.transaction.is_none();
+
+        if implicit {
+            self.transaction = Some(Transaction::begin(&self.shared.transactions));
+        }
+
+        // Locks are acquired before the databases lock, so waiting for them doesn't block anyone
+        let mut res = self.acquire_locks(locks, false);
+
+        if let (Ok(()), Some(txn)) = (&res, &mut self.transaction) {
+            res = f(
+                txn,
+                &mut self.shared.databases.write().unwrap(),
+                &self.shared.transactions,
+            );
+        }
+
+        if !implicit {
+            return res;
+        }
+
+        // A deadlock already rolled the transaction back
+        let txn = match self.transaction.take() {
+            Some(txn) => txn,
+            None => return res,
+        };
+
+        return match res {
+            Ok(()) => self.end_transaction(txn, true),
+            Err(e) => {
+                let _ = self.end_transaction(txn, false);
+                Err(e)
+            }
+        };
+    }
+
+    /// Acquire `locks` for the open transaction. A deadlock rolls the transaction back.
+    fn acquire_locks(
+        &mut self,
+        locks: Vec<(LockTarget, LockMode)>,
+        nowait: bool,
+    ) -> Result<(), Error> {
+        let id = match &self.transaction {
+            Some(txn) => txn.id(),
+            None => return Ok(()),
+        };
+
+        let wait = match nowait {
+            true => Wait::NoWait,
+            false => Wait::Timeout(self.shared.settings.read().unwrap().lock_timeout),
+        };
+
+        for (target, mode) in locks {
+            match self.shared.locks.acquire(id, target.clone(), mode, wait) {
+                Ok(()) => {}
+                Err(LockError::NotAvailable) => {
+                    return Err(Error::new(
+                        ErrorCode::LockNotAvailable,
+                        format!("Could not obtain lock on {}", describe_lock_target(&target)),
+                    ));
+                }
+                Err(LockError::Deadlock) => {
+                    if let Some(txn) = self.transaction.take() {
+                        self.audit_implicit_rollback("deadlock");
+                        let _ = self.end_transaction(txn, false);
+                    }
+
+                    return Err(Error::new(
+                        ErrorCode::DeadlockDetected,
+                        format!(
+                            "Deadlock detected waiting for a lock on {}. Transaction rolled back",
+                            describe_lock_target(&target)
+                        ),
+                    ));
+                }
+            }
+        }
+
+        return Ok(());
+    }
+
+    /// Commit or roll back `txn`, then release its locks. A commit dropping or renaming a database
+    /// which a session opened since the DROP or ALTER ran rolls back instead.
+    fn end_transaction(&mut self, txn: Transaction, commit: bool) -> Result<(), Error> {
+        let id = txn.id();
+        let changes = match commit {
+            true => txn.changes().to_vec(),
+            false => vec![],
+        };
+
+        let res = {
+            let mut databases = self.shared.databases.write().unwrap();
+
+            let res = match commit {
+                true => match self.check_changed_not_open(&changes) {
+                    Ok(()) => txn.commit(&mut databases, &self.shared.transactions),
+                    Err(e) => {
+                        txn.rollback(&mut databases, &self.shared.transactions);
+                        Err(e)
+                    }
+                },
+                false => {
+                    txn.rollback(&mut databases, &self.shared.transactions);
+                    Ok(())
+                }
+            };
+
+            // Grants follow what they're on as the drop or rename becomes visible, before GRANT
+            // can see it
+            if res.is_ok() {
+                self.update_grants(&changes);
+            }
+
+            res
+        };
+
+        self.shared.locks.release_all(id);
+
+        return res;
+    }
+
+    /// `check_not_open` again for the databases `changes` drop or rename, as a session may have
+    /// opened one since
+    fn check_changed_not_open(&self, changes: &[Change]) -> Result<(), Error> {
+        for change in changes {
+            match change {
+                Change::DropDatabase { name } => self.check_not_open(name, "DROP DATABASE")?,
+                Change::RenameDatabase { from, .. } => {
+                    self.check_not_open(from, "ALTER DATABASE")?
+                }
+                Change::CreateDatabase { .. }
+                | Change::CreateTable { .. }
+                | Change::DropTable { .. }
+                | Change::RenameTable { .. }
+                | Change::AlterTable { .. } => {}
+            }
+        }
+
+        return Ok(());
+    }
+
+    fn update_grants(&self, changes: &[Change]) -> () {
+        let mut acl = self.shared.acl.write().unwrap();
+
+        for change in changes {
+            match change {
+                Change::DropDatabase { name } => acl.drop_database(name),
+                Change::DropTable { db, name } => acl.drop_table(db, name),
+                Change::RenameDatabase { from, to } => acl.rename_database(from, to),
+                Change::RenameTable { db, from, to } => acl.rename_table(db, from, to),
+                Change::CreateDatabase { .. }
+                | Change::CreateTable { .. }
+                | Change::AlterTable { .. } => {}
+            }
+        }
+    }
+
+    fn exec_begin(&mut self) -> Result<(), Error> {
+        if self.transaction.is_some() {
+            return Err(Error::new(
+                ErrorCode::InvalidTransactionState,
+                String::from("BEGIN failed. A transaction is already open"),
+            ));
+        }
+
+        self.transaction = Some(Transaction::begin(&self.shared.transactions));
+
+        self.log(LogLevel::DEBUG, "Transaction started");
+
+        self.send(&[0x02, 0x00, 0x00, 0x00, 0x06, 0x01]);
+
+        return Ok(());
+    }
+
+    fn exec_commit(&mut self) -> Result<(), Error> {
+        let txn = match self.transaction.take() {
+            Some(txn) => txn,
+            None => {
+                return Err(Error::new(
+                    ErrorCode::InvalidTransactionState,
+                    String::from("COMMIT failed. No open transaction"),
+                ))
+            }
+        };
+
+        let changes = txn.changes().len();
+
+        if let Err(e) = self.end_transaction(txn, true) {
+            return Err(Error::new(
+                e.code,
+                format!("COMMIT failed, transaction rolled back. {}", e),
+            ));
+        }
+
+        self.log(
+            LogLevel::DEBUG,
+            &format!("Transaction committed with {} change(s)", changes),
+        );
+
+        self.send(&[0x02, 0x00, 0x00, 0x00, 0x07, 0x01]);
+
+        return Ok(());
+    }
+
+    fn exec_rollback(&mut self) -> Result<(), Error> {
+        let txn = match self.transaction.take() {
+            Some(txn) => txn,
+            None => {
+                return Err(Error::new(
+                    ErrorCode::InvalidTransactionState,
+                    String::from("ROLLBACK failed. No open transaction"),
+                ))
+            }
+        };
+
+        self.log(
+            LogLevel::DEBUG,
+            &format!(
+                "Transaction rolled back, discarding {} change(s)",
+                txn.changes().len()
+            ),
+        );
+
+        let _ = self.end_transaction(txn, false);
+
+        self.send(&[0x02, 0x00, 0x00, 0x00, 0x08, 0x01]);
+
+        return Ok(());
+    }
+
+    fn exec_set_isolation_level(&mut self, level: IsolationLevel) -> Result<(), Error> {
+        let txn = match &mut self.transaction {
+            Some(txn) => txn,
+            None => {
+                return Err(Error::new(
+                    ErrorCode::InvalidTransactionState,
+                    String::from("SET TRANSACTION ISOLATION LEVEL failed. No open transaction"),
+                ))
+            }
+        };
+
+        txn.set_isolation_level(level, &self.shared.transactions)?;
+
+        self.log(
+            LogLevel::DEBUG,
+            &format!("Transaction isolation level set to {}", level.name()),
+        );
+
+        self.send(&[0x02, 0x00, 0x00, 0x00, 0x09, 0x01]);
+
+        return Ok(());
+    }
+
+    fn exec_lock_table(&mut self, name: String, mode: LockMode, nowait: bool) -> Result<(), Error> {
+        if self.transaction.is_none() {
+            return Err(Error::new(
+                ErrorCode::InvalidTransactionState,
+                String::from("LOCK TABLE failed. Locks are only held inside a transaction"),
+            ));
+        }
+
+        let db = match &self.open_db {
+            Some(db) => db.clone(),
+            None => {
+                return Err(Error::from(String::from(
+                    "LOCK TABLE failed. No open database",
+                )))
+            }
+        };
+
+        self.record_read(transaction::Read::Tables { db: db.clone() });
+
+        if !self.table_exists(&db, &name) {
+            return Err(Error::from(format!(
+                "LOCK TABLE failed. No table with name [{}::{}]",
+                db, name
+            )));
+        }
+
+        // Like reading for a shared lock, like writing for an exclusive one
+        let privileges: &[Privilege] = match mode {
+            LockMode::Shared => &Privilege::ALL,
+            LockMode::Exclusive => &[Privilege::Insert, Privilege::Update, Privilege::Delete],
+        };
+
+        self.check_privilege(
+            privileges,
+            &Object::Table {
+                db: db.clone(),
+                name: name.clone(),
+            },
+            "LOCK TABLE",
+        )?;
+
+        let locks = vec![
+            (LockTarget::Database { name: db.clone() }, LockMode::Shared),
+            (
+                LockTarget::Table {
+                    db: db.clone(),
+                    name: name.clone(),
+                },
+                mode,
+            ),
+        ];
+
+        self.acquire_locks(locks, nowait)?;
+
+        self.log(
+            LogLevel::DEBUG,
+            &format!("Locked table [{}::{}] in {:?} mode", db, name, mode),
+        );
+
+        self.send(&[0x02, 0x00, 0x00, 0x00, 0x0A, 0x01]);
+
+        return Ok(());
+    }
+
+    fn exec_savepoint(&mut self, name: String) -> Result<(), Error> {
+        let txn = match &mut self.transaction {
+            Some(txn) => txn,
+            None => {
+                return Err(Error::new(
+                    ErrorCode::InvalidTransactionState,
+                    String::from("SAVEPOINT failed. No open transaction"),
+                ))
+            }
+        };
+
+        txn.savepoint(name.clone());
+
+        self.log(LogLevel::DEBUG, &format!("Savepoint [{}] set", name));
+
+        self.send(&[0x02, 0x00, 0x00, 0x00, 0x0B, 0x01]);
+
+        return Ok(());
+    }
+
+    fn exec_release_savepoint(&mut self, name: String) -> Result<(), Error> {
+        let txn = match &mut self.transaction {
+            Some(txn) => txn,
+            None => {
+                return Err(Error::new(
+                    ErrorCode::InvalidTransactionState,
+                    String::from("RELEASE SAVEPOINT failed. No open transaction"),
+                ))
+            }
+        };
+
+        txn.release_savepoint(&name)?;
+
+        self.log(LogLevel::DEBUG, &format!("Savepoint [{}] released", name));
+
+        self.send(&[0x02, 0x00, 0x00, 0x00, 0x0C, 0x01]);
+
+        return Ok(());
+    }
+
+    fn exec_rollback_to_savepoint(&mut self, name: String) -> Result<(), Error> {
+        let txn = match &mut self.transaction {
+            Some(txn) => txn,
+            None => {
+                return Err(Error::new(
+                    ErrorCode::InvalidTransactionState,
+                    String::from("ROLLBACK TO SAVEPOINT failed. No open transaction"),
+                ))
+            }
+        };
+
+        let changes = txn.changes().len();
+
+        txn.rollback_to_savepoint(&name, &mut self.shared.databases.write().unwrap())?;
+
+        let discarded = changes - txn.changes().len();
+
+        self.log(
+            LogLevel::DEBUG,
+            &format!(
+                "Rolled back to savepoint [{}], discarding {} change(s)",
+                name, discarded
+            ),
+        );
+
+        self.send(&[0x02, 0x00, 0x00, 0x00, 0x0D, 0x01]);
+
+        return Ok(());
+    }
+
+    fn exec_authenticate(&mut self, user: String) -> Result<(), Error> {
+        if self.transaction.is_some() {
+            return Err(Error::new(
+                ErrorCode::InvalidTransactionState,
+                String::from("AUTHENTICATE failed. Can't switch users inside a transaction"),
+            ));
+        }
+
+        let (challenge, credentials) = self.shared.users.read().unwrap().challenge(&user);
+
+        self.write_msg(&v0::response::serialise_challenge(&challenge));
+
+        self.pending_auth = Some((user, challenge, credentials));
+
+        return Ok(());
+    }
+
+    fn exec_auth_proof(&mut self, proof: Vec<u8>) -> Result<(), Error> {
+        let (user, challenge, credentials) = match self.pending_auth.take() {
+            Some(pending_auth) => pending_auth,
+            None => return Err(auth::authentication_failed()),
+        };
+
+        let verified = credentials.is_some_and(|c| c.verify(&challenge.nonce, &proof));
+
+        if !verified {
+            self.log(
+                LogLevel::WARN,
+                &format!("Failed authentication as user [{}]", user),
+            );
+
+            return Err(auth::authentication_failed());
+        }
+
+        self.user = Some(user);
+        self.authenticated = true;
+
+        self.log(LogLevel::INFO, "Authenticated");
+
+        self.send(&[0x02, 0x00, 0x00, 0x00, 0x0F, 0x01]);
+
+        return Ok(());
+    }
+
+    /// User and description of `cmd` for the audit log, if it's audited whatever its outcome.
+    /// Credentials are left out. Ending a transaction which ran audited commands is audited too.
+    fn audit_command(&self, cmd: &Command) -> Option<(String, String)> {
+        let user = self.user.clone().unwrap_or_default();
+
+        let command = match cmd {
+            Command::Commit if self.audited_in_transaction => String::from("COMMIT"),
+            Command::Rollback if self.audited_in_transaction => String::from("ROLLBACK"),
+            Command::RollbackToSavepoint { name } if self.audited_in_transaction => {
+                format!("ROLLBACK TO SAVEPOINT {}", name)
+            }
+            Command::AuthProof { .. } => {
+                let user = match &self.pending_auth {
+                    Some((user, _, _)) => user.clone(),
+                    None => String::new(),
+                };
+
+                return Some((user, String::from("AUTHENTICATE")));
+            }
+            Command::CreateDatabase { name } => format!("CREATE DATABASE {}", name),
+            Command::CreateTable { name, .. } => format!("CREATE TABLE {}", self.qualify(name)),
+            Command::DropDatabase { name, .. } => format!("DROP DATABASE {}", name),
+            Command::DropTable { name, .. } => format!("DROP TABLE {}", self.qualify(name)),
+            Command::RenameDatabase { name, new_name } => {
+                format!("ALTER DATABASE {} RENAME TO {}", name, new_name)
+            }
+            Command::RenameTable { name, new_name } => {
+                format!("ALTER TABLE {} RENAME TO {}", self.qualify(name), new_name)
+            }
+            Command::AlterTable { name, action } => format!(
+                "ALTER TABLE {} {}",
+                self.qualify(name),
+                describe_alter_table_action(action)
+            ),
+            Command::CreatePolicy { table, policy } => format!(
+                "CREATE POLICY {} ON {} FOR {} TO {} USING ({})",
+                policy.name,
+                self.qualify(table),
+                policy.command.name(),
+                policy.role,
+                policy.predicate.describe()
+            ),
+            Command::DropPolicy { table, name } => {
+                format!("DROP POLICY {} ON {}", name, self.qualify(table))
+            }
+            Command::CreateUser { name, .. } => format!("CREATE USER {}", name),
+            Command::AlterUser { name, .. } => format!("ALTER USER {} PASSWORD", name),
+            Command::DropUser { name } => format!("DROP USER {}", name),
+            Command::CreateRole { name } => format!("CREATE ROLE {}", name),
+            Command::DropRole { name } => format!("DROP ROLE {}", name),
+            Command::GrantRole { role, user } => format!("GRANT {} TO {}", role, user),
+            Command::RevokeRole { role, user } => format!("REVOKE {} FROM {}", role, user),
+            Command::Grant {
+                privileges,
+                on,
+                role,
+            } => format!(
+                "GRANT {} ON {} TO {}",
+                describe_privileges(privileges),
+                describe_grant_on(on),
+                role
+            ),
+            Command::Revoke {
+                privileges,
+                on,
+                role,
+            } => format!(
+                "REVOKE {} ON {} FROM {}",
+                describe_privileges(privileges),
+                describe_grant_on(on),
+                role
+            ),
+            _ => return None,
+        };
+
+        return Some((user, command));
+    }
+
+    /// Description of a command which is only audited when it's denied
+    fn describe_denied(&self, cmd: &Command) -> String {
+        return match cmd {
+            Command::OpenDatabase { name } => format!("OPEN DATABASE {}", name),
+            Command::DescribeTable { name } => format!("DESCRIBE TABLE {}", self.qualify(name)),
+            Command::LockTable { name, .. } => format!("LOCK TABLE {}", self.qualify(name)),
+            _ => cmd.name().replace('_', " ").to_uppercase(),
+        };
+    }
+
+    /// `db::name` for table `name` of the open database, or just `name` without one
+    fn qualify(&self, name: &str) -> String {
+        return match &self.open_db {
+            Some(db) => format!("{}::{}", db, name),
+            None => String::from(name),
+        };
+    }
+
+    /// Record `command` and its outcome `res` in the audit log
+    fn audit(&self, user: &str, command: &str, res: &Result<(), Error>) -> Result<(), Error> {
+        let outcome = match res {
+            Ok(()) => String::from("ok"),
+            Err(e) => format!("{}: {}", e.code.name(), e.msg),
+        };
+
+        let audit = self.shared.audit.as_ref().unwrap();
+
+        if let Err(e) = audit.append(user, &self.peer, command, &outcome) {
+            self.log(
+                LogLevel::ERROR,
+                &format!("Failed to write audit record for [{}]: {}", command, e),
+            );
+
+            return Err(Error::from(format!(
+                "{} ran, but its audit record couldn't be written: {}",
+                command, e
+            )));
+        }
+
+        return Ok(());
+    }
+
+    /// Record that the open transaction's audited commands were rolled back without a ROLLBACK
+    fn audit_implicit_rollback(&mut self, reason: &str) -> () {
+        if !self.audited_in_transaction {
+            return;
+        }
+
+        self.audited_in_transaction = false;
+
+        let user = self.user.clone().unwrap_or_default();
+        let _ = self.audit(&user, &format!("ROLLBACK ({})", reason), &Ok(()));
+    }
+
+    /// Whether the client holds every privilege. Anonymous connections, only accepted with
+    /// `allow_anonymous`, never do: they get the privileges granted to PUBLIC.
+    fn is_superuser(&self) -> bool {
+        return match &self.user {
+            Some(user) => self.shared.users.read().unwrap().is_superuser(user),
+            None => false,
+        };
+    }
+
+    /// Users, roles, role members and privileges change straight away rather than at commit, so
+    /// they can't be changed inside a transaction, which could roll back what they were checked
+    /// against
+    fn require_no_transaction(&self, cmd: &str) -> Result<(), Error> {
+        if self.transaction.is_some() {
+            return Err(Error::new(
+                ErrorCode::InvalidTransactionState,
+                format!(
+                    "{} failed. Users, roles and privileges can't be changed inside a transaction",
+                    cmd
+                ),
+            ));
+        }
+
+        return Ok(());
+    }
+
+    fn require_superuser(&self, cmd: &str) -> Result<(), Error> {
+        if !self.is_superuser() {
+            return Err(permission_denied(format!(
+                "{} failed. Requires a superuser",
+                cmd
+            )));
+        }
+
+        return Ok(());
+    }
+
+    /// Fail unless the user holds one of `privileges` on `object`, or on its database
+    fn check_privilege(
+        &self,
+        privileges: &[Privilege],
+        object: &Object,
+        cmd: &str,
+    ) -> Result<(), Error> {
+        if self.is_superuser() {
+            return Ok(());
+        }
+
+        if !self
+            .shared
+            .acl
+            .read()
+            .unwrap()
+            .has_any(self.user.as_deref(), privileges, object)
+        {
+            let names: Vec<&str> = privileges.iter().map(|p| p.name()).collect();
+
+            return Err(permission_denied(format!(
+                "{} failed. Requires {} on {}",
+                cmd,
+                names.join(" or "),
+                describe_object(object)
+            )));
+        }
+
+        return Ok(());
+    }
+
+    /// Whether the user holds any privilege on database `name` or on one of its tables
+    fn can_access_database(&self, name: &str) -> bool {
+        return self.is_superuser()
+            || self
+                .shared
+                .acl
+                .read()
+                .unwrap()
+                .has_any_in_database(self.user.as_deref(), name);
+    }
+
+    /// Whether the user holds any privilege on table `name` of database `db`, or on `db`
+    fn can_access_table(&self, db: &str, name: &str) -> bool {
+        return self.is_superuser()
+            || self.shared.acl.read().unwrap().has_any(
+                self.user.as_deref(),
+                &Privilege::ALL,
+                &Object::Table {
+                    db: String::from(db),
+                    name: String::from(name),
+                },
+            );
+    }
+
+    /// Fail unless the user owns table `name` of database `db`, or is a superuser
+    fn require_owner(&self, db: &str, name: &str, cmd: &str) -> Result<(), Error> {
+        let snapshot = self.snapshot();
+
+        let owner = transaction::visible(&self.shared.databases.read().unwrap(), &snapshot)
+            .filter(|d| d.name == db)
+            .flat_map(|d| transaction::visible(&d.tables, &snapshot))
+            .find(|tb| tb.name == name)
+            .map(|tb| tb.owner.clone());
+
+        return match owner {
+            None => Err(Error::from(format!(
+                "{} failed. No table with name [{}::{}]",
+                cmd, db, name
+            ))),
+            Some(_) if self.is_superuser() => Ok(()),
+            Some(owner) if owner.is_some() && owner == self.user => Ok(()),
+            Some(_) => Err(permission_denied(format!(
+                "{} failed. Requires ownership of table [{}::{}]",
+                cmd, db, name
+            ))),
+        };
+    }
+
+    /// Whether `f` holds for a table which isn't dropped, or is dropped by a transaction which
+    /// may still roll back. Users and roles aren't versioned, so they can only be dropped once
+    /// no such table refers to them.
+    fn any_live_table(&self, f: impl Fn(&Table) -> bool) -> bool {
+        let txns = &self.shared.transactions;
+        let live = |deleted_by: Option<TxnId>| match deleted_by {
+            Some(id) => txns.is_active(id),
+            None => true,
+        };
+
+        return self
+            .shared
+            .databases
+            .read()
+            .unwrap()
+            .iter()
+            .filter(|d| live(d.deleted_by))
+            .flat_map(|d| d.value.tables.iter())
+            .any(|tb| live(tb.deleted_by) && f(&tb.value));
+    }
+
+    fn table_exists(&self, db: &str, name: &str) -> bool {
+        let snapshot = self.snapshot();
+
+        return transaction::visible(&self.shared.databases.read().unwrap(), &snapshot)
+            .filter(|d| d.name == db)
+            .flat_map(|d| transaction::visible(&d.tables, &snapshot))
+            .any(|tb| tb.name == name);
+    }
+
+    fn exec_create_user(&mut self, name: String, credentials: Credentials) -> Result<(), Error> {
+        self.require_superuser("CREATE USER")?;
+        self.require_no_transaction("CREATE USER")?;
+
+        self.shared
+            .users
+            .write()
+            .unwrap()
+            .create(name.clone(), credentials, false)?;
+
+        self.log(LogLevel::INFO, &format!("Created user [{}]", name));
+
+        self.send(&[0x02, 0x00, 0x00, 0x00, 0x10, 0x01]);
+
+        return Ok(());
+    }
+
+    fn exec_alter_user(&mut self, name: String, credentials: Credentials) -> Result<(), Error> {
+        // Users can change their own password
+        if self.user.as_ref() != Some(&name) {
+            self.require_superuser("ALTER USER")?;
+        }
+
+        self.require_no_transaction("ALTER USER")?;
+
+        self.shared
+            .users
+            .write()
+            .unwrap()
+            .alter(&name, credentials)?;
+
+        self.log(
+            LogLevel::INFO,
+            &format!("Changed password of user [{}]", name),
+        );
+
+        self.send(&[0x02, 0x00, 0x00, 0x00, 0x11, 0x01]);
+
+        return Ok(());
+    }
+
+    /// Connections already authenticated as the user stay open
+    fn exec_drop_user(&mut self, name: String) -> Result<(), Error> {
+        self.require_superuser("DROP USER")?;
+        self.require_no_transaction("DROP USER")?;
+
+        if self.any_live_table(|tb| tb.owner.as_deref() == Some(&name)) {
+            return Err(Error::from(format!(
+                "DROP USER failed. User [{}] owns tables. Drop them first",
+                name
+            )));
+        }
+
+        self.shared.users.write().unwrap().remove(&name)?;
+        self.shared.acl.write().unwrap().forget_user(&name);
+
+        self.log(LogLevel::INFO, &format!("Dropped user [{}]", name));
+
+        self.send(&[0x02, 0x00, 0x00, 0x00, 0x12, 0x01]);
+
+        return Ok(());
+    }
+
+    fn exec_create_role(&mut self, name: String) -> Result<(), Error> {
+        self.require_superuser("CREATE ROLE")?;
+        self.require_no_transaction("CREATE ROLE")?;
+
+        self.shared.acl.write().unwrap().create_role(name.clone())?;
+
+        self.log(LogLevel::INFO, &format!("Created role [{}]", name));
+
+        self.send(&[0x02, 0x00, 0x00, 0x00, 0x13, 0x01]);
+
+        return Ok(());
+    }
+
+    fn exec_drop_role(&mut self, name: String) -> Result<(), Error> {
+        self.require_superuser("DROP ROLE")?;
+        self.require_no_transaction("DROP ROLE")?;
+
+        if self.any_live_table(|tb| tb.policies.iter().any(|p| p.role == name)) {
+            return Err(Error::from(format!(
+                "DROP ROLE failed. Role [{}] is used by policies. Drop them first",
+                name
+            )));
+        }
+
+        self.shared.acl.write().unwrap().drop_role(&name)?;
+
+        self.log(LogLevel::INFO, &format!("Dropped role [{}]", name));
+
+        self.send(&[0x02, 0x00, 0x00, 0x00, 0x14, 0x01]);
+
+        return Ok(());
+    }
+
+    fn exec_grant_role(&mut self, role: String, user: String) -> Result<(), Error> {
+        self.require_superuser("GRANT")?;
+        self.require_no_transaction("GRANT")?;
+
+        if !self.shared.users.read().unwrap().exists(&user) {
+            return Err(Error::from(format!(
+                "GRANT failed. No user with name [{}]",
+                user
+            )));
+        }
+
+        self.shared
+            .acl
+            .write()
+            .unwrap()
+            .grant_role(&role, user.clone())?;
+
+        self.log(
+            LogLevel::INFO,
+            &format!("Granted role [{}] to user [{}]", role, user),
+        );
+
+        self.send(&[0x02, 0x00, 0x00, 0x00, 0x15, 0x01]);
+
+        return Ok(());
+    }
+
+    fn exec_revoke_role(&mut self, role: String, user: String) -> Result<(), Error> {
+        self.require_superuser("REVOKE")?;
+        self.require_no_transaction("REVOKE")?;
+
+        self.shared.acl.write().unwrap().revoke_role(&role, &user)?;
+
+        self.log(
+            LogLevel::INFO,
+            &format!("Revoked role [{}] from user [{}]", role, user),
+        );
+
+        self.send(&[0x02, 0x00, 0x00, 0x00, 0x16, 0x01]);
+
+        return Ok(());
+    }
+
+    /// Object named by GRANT or REVOKE, which must exist in the committed `databases`
+    fn resolve_grant_on(
+        &self,
+        databases: &Databases,
+        on: GrantOn,
+        privileges: &[Privilege],
+        cmd: &str,
+    ) -> Result<Object, Error> {
+        let snapshot = self.shared.transactions.snapshot();
+        let mut visible = transaction::visible(databases, &snapshot);
+
+        return match on {
+            GrantOn::Database { name } => {
+                if !visible.any(|db| db.name == name) {
+                    return Err(Error::from(format!(
+                        "{} failed. No database with name [{}]",
+                        cmd, name
+                    )));
+                }
+
+                Ok(Object::Database { name })
+            }
+            GrantOn::Table { name } => {
+                if privileges.contains(&Privilege::Create) {
+                    return Err(Error::from(format!(
+                        "{} failed. CREATE only applies to databases",
+                        cmd
+                    )));
+                }
+
+                let db = match &self.open_db {
+                    Some(db) => db.clone(),
+                    None => return Err(Error::from(format!("{} failed. No open database", cmd))),
+                };
+
+                let exists = visible
+                    .filter(|d| d.name == db)
+                    .flat_map(|d| transaction::visible(&d.tables, &snapshot))
+                    .any(|tb| tb.name == name);
+
+                if !exists {
+                    return Err(Error::from(format!(
+                        "{} failed. No table with name [{}::{}]",
+                        cmd, db, name
+                    )));
+                }
+
+                Ok(Object::Table { db, name })
+            }
+        };
+    }
+
+    fn exec_grant(
+        &mut self,
+        privileges: Vec<Privilege>,
+        on: GrantOn,
+        role: String,
+    ) -> Result<(), Error> {
+        self.require_superuser("GRANT")?;
+        self.require_no_transaction("GRANT")?;
+
+        let object = {
+            // Held until the grant is made, so that a drop or rename can't commit in between and
+            // leave the grant on an object which no longer exists, see `end_transaction`
+            let databases = self.shared.databases.read().unwrap();
+            let object = self.resolve_grant_on(&databases, on, &privileges, "GRANT")?;
+
+            self.shared
+                .acl
+                .write()
+                .unwrap()
+                .grant(&role, &privileges, object.clone())?;
+
+            object
+        };
+
+        let desc = describe_object(&object);
+
+        self.log(
+            LogLevel::INFO,
+            &format!(
+                "Granted {} on {} to role [{}]",
+                describe_privileges(&privileges),
+                desc,
+                role
+            ),
+        );
+
+        self.send(&[0x02, 0x00, 0x00, 0x00, 0x17, 0x01]);
+
+        return Ok(());
+    }
+
+    fn exec_revoke(
+        &mut self,
+        privileges: Vec<Privilege>,
+        on: GrantOn,
+        role: String,
+    ) -> Result<(), Error> {
+        self.require_superuser("REVOKE")?;
+        self.require_no_transaction("REVOKE")?;
+
+        let object = {
+            let databases = self.shared.databases.read().unwrap();
+            let object = self.resolve_grant_on(&databases, on, &privileges, "REVOKE")?;
+
+            self.shared
+                .acl
+                .write()
+                .unwrap()
+                .revoke(&role, &privileges, &object)?;
+
+            object
+        };
+
+        self.log(
+            LogLevel::INFO,
+            &format!(
+                "Revoked {} on {} from role [{}]",
+                describe_privileges(&privileges),
+                describe_object(&object),
+                role
+            ),
+        );
+
+        self.send(&[0x02, 0x00, 0x00, 0x00, 0x18, 0x01]);
+
+        return Ok(());
+    }
+}
+
+impl Drop for ClientConnection {
+    fn drop(&mut self) {
+        if let Some(txn) = self.transaction.take() {
+            self.log(
+                LogLevel::WARN,
+                &format!(
+                    "Connection dropped with an open transaction. Rolled back {} change(s)",
+                    txn.changes().len()
+                ),
+            );
+
+            self.audit_implicit_rollback("connection closed");
+            let _ = self.end_transaction(txn, false);
+        }
+
+        self.shared.open_databases.lock().unwrap().remove(&self.id);
+
+        self.shared
+            .active_connections
+            .fetch_sub(1, Ordering::SeqCst);
+        self.shared.metrics.connection_closed();
+    }
+}
+
+fn describe_lock_target(target: &LockTarget) -> String {
+    return match target {
+        LockTarget::Database { name } => format!("database [{}]", name),
+        LockTarget::Table { db, name } => format!("table [{}::{}]", db, name),
+        LockTarget::Row { db, table, key } => format!("row [{}] of table [{}::{}]", key, db, table),
+    };
+}
+
+fn describe_object(object: &Object) -> String {
+    return match object {
+        Object::Database { name } => format!("database [{}]", name),
+        Object::Table { db, name } => format!("table [{}::{}]", db, name),
+    };
+}
+
+fn describe_grant_on(on: &GrantOn) -> String {
+    return match on {
+        GrantOn::Database { name } => format!("DATABASE {}", name),
+        GrantOn::Table { name } => format!("TABLE {}", name),
+    };
+}
+
+/// `cmd` for the logs, without the secrets it may hold
+fn describe_command(cmd: &Command) -> String {
+    return match cmd {
+        Command::AuthProof { .. } => String::from("AuthProof { .. }"),
+        // `Credentials` leave out their secrets themselves
+        _ => format!("{:?}", cmd),
+    };
+}
+
+fn describe_alter_table_action(action: &AlterTableAction) -> String {
+    return match action {
+        AlterTableAction::AddColumn { column, .. } => {
+            format!("ADD COLUMN {} {}", column.name, column.column_type.name())
+        }
+        AlterTableAction::DropColumn { name } => format!("DROP COLUMN {}", name),
+        AlterTableAction::RenameColumn { name, new_name } => {
+            format!("RENAME COLUMN {} TO {}", name, new_name)
+        }
+        AlterTableAction::AlterColumnType { name, column_type } => {
+            format!("ALTER COLUMN {} TYPE {}", name, column_type.name())
+        }
+        AlterTableAction::SetDefault {
+            name,
+            default: Some(default),
+        } => format!("ALTER COLUMN {} SET DEFAULT {}", name, default.describe()),
+        AlterTableAction::SetDefault {
+            name,
+            default: None,
+        } => format!("ALTER COLUMN {} DROP DEFAULT", name),
+        AlterTableAction::SetNotNull {
+            name,
+            not_null: true,
+        } => format!("ALTER COLUMN {} SET NOT NULL", name),
+        AlterTableAction::SetNotNull {
+            name,
+            not_null: false,
+        } => format!("ALTER COLUMN {} DROP NOT NULL", name),
+        AlterTableAction::AddConstraint { constraint } => match &constraint.name {
+            Some(name) => format!("ADD CONSTRAINT {} {}", name, constraint.kind.describe()),
+            None => format!("ADD {}", constraint.kind.describe()),
+        },
+        AlterTableAction::DropConstraint { name } => format!("DROP CONSTRAINT {}", name),
+    };
+}
+
+fn describe_privileges(privileges: &[Privilege]) -> String {
+    let names: Vec<&str> = privileges.iter().map(|p| p.name()).collect();
+
+    return names.join(", ");
+}
+
+fn permission_denied(msg: String) -> Error {
+    return Error::new(ErrorCode::PermissionDenied, msg);
+}
@@ -1,6 +1,24 @@
+// Explicit `return`s and `-> ()` are house style
+#![allow(clippy::needless_return, clippy::unused_unit)]
+
+pub mod acl;
+pub mod audit;
+pub mod auth;
+pub mod cipher;
 pub mod column;
 pub mod command;
+pub mod constraint;
 pub mod database;
+pub mod error;
+pub mod lock;
+pub mod log;
+pub mod metrics;
+pub mod mvcc;
+pub mod net;
+pub mod policy;
 pub mod protocol;
+pub mod server;
 pub mod table;
+pub mod tls;
+pub mod transaction;
 pub mod utils;
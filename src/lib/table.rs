@@ -1,16 +1,1133 @@
-use crate::column::Column;
+use std::collections::HashSet;
 
-#[derive(Debug)]
+use crate::column::{Column, ColumnType, DefaultValue};
+use crate::constraint::{Constraint, ConstraintKind, ForeignKey, ReferentialAction};
+use crate::policy::{Policy, PolicyCommand};
+
+#[derive(Debug, Clone)]
 pub struct Table {
     pub name: String,
-    _columns: Vec<Column>,
+    pub columns: Vec<Column>,
+
+    /// All named, see `Table::add_constraint`
+    pub constraints: Vec<Constraint>,
+
+    /// User who created the table, `None` if an anonymous connection did. Only the owner and
+    /// superusers can create and drop its policies.
+    pub owner: Option<String>,
+
+    pub policies: Vec<Policy>,
 }
 
+// Changes which fail leave the table half changed, so they're made to a copy of it
 impl Table {
-    pub fn new(name: String) -> Table {
-        Table {
+    pub fn new(
+        name: String,
+        columns: Vec<Column>,
+        constraints: Vec<Constraint>,
+    ) -> Result<Table, String> {
+        let mut table = Table {
             name,
-            _columns: vec![],
+            columns,
+            constraints: vec![],
+            owner: None,
+            policies: vec![],
+        };
+
+        table.check()?;
+
+        for constraint in constraints {
+            table.add_constraint(constraint)?;
+        }
+
+        return Ok(table);
+    }
+
+    /// Tables hold no rows yet, so a column is added without filling it in
+    pub fn add_column(
+        &mut self,
+        column: Column,
+        constraints: Vec<Constraint>,
+    ) -> Result<(), String> {
+        self.columns.push(column);
+        self.mark_foreign_key_columns();
+        self.check()?;
+
+        for constraint in constraints {
+            self.add_constraint(constraint)?;
+        }
+
+        return Ok(());
+    }
+
+    /// Dropping a column drops the constraints on it. Dropping a primary key column drops the
+    /// primary key.
+    pub fn drop_column(&mut self, name: &str) -> Result<(), String> {
+        let idx = self.column_idx(name)?;
+
+        // Dropping a policy could let users see rows it hid from them, so that's left to DROP
+        // POLICY
+        if let Some(policy) = self.policies.iter().find(|p| p.predicate.column == name) {
+            return Err(format!(
+                "Column [{}] is used by policy [{}]. Drop the policy first",
+                name, policy.name
+            ));
+        }
+        let column = self.columns.remove(idx);
+
+        if column.is_primary_key {
+            for column in self.columns.iter_mut() {
+                column.is_primary_key = false;
+            }
+        }
+
+        self.constraints
+            .retain(|constraint| !constraint.kind.columns().contains(&name));
+        self.mark_foreign_key_columns();
+
+        return Ok(());
+    }
+
+    pub fn rename_column(&mut self, name: &str, new_name: String) -> Result<(), String> {
+        let idx = self.column_idx(name)?;
+
+        if self.columns.iter().any(|col| col.name == new_name) {
+            return Err(format!("Column [{}] already exists", new_name));
+        }
+
+        self.columns[idx].name = new_name.clone();
+
+        for constraint in self.constraints.iter_mut() {
+            match &mut constraint.kind {
+                ConstraintKind::Unique { columns } => {
+                    for column in columns.iter_mut().filter(|column| *column == name) {
+                        *column = new_name.clone();
+                    }
+                }
+                ConstraintKind::Check(check) if check.column == name => {
+                    check.column = new_name.clone();
+                }
+                ConstraintKind::Check(_) => {}
+                ConstraintKind::ForeignKey(fk) => {
+                    for column in fk.columns.iter_mut().filter(|column| *column == name) {
+                        *column = new_name.clone();
+                    }
+
+                    if fk.ref_table == self.name {
+                        for column in fk.ref_columns.iter_mut().filter(|column| *column == name) {
+                            *column = new_name.clone();
+                        }
+                    }
+                }
+            }
+        }
+
+        for policy in self.policies.iter_mut() {
+            if policy.predicate.column == name {
+                policy.predicate.column = new_name.clone();
+            }
+        }
+
+        return Ok(());
+    }
+
+    /// Only conversions which can't lose data are allowed, see `ColumnType::converts_to`
+    pub fn alter_column_type(&mut self, name: &str, column_type: ColumnType) -> Result<(), String> {
+        let idx = self.column_idx(name)?;
+        let column = &mut self.columns[idx];
+
+        if !column.column_type.converts_to(column_type) {
+            return Err(format!(
+                "Column [{}] can't be converted from {} to {} without losing data",
+                name,
+                column.column_type.name(),
+                column_type.name()
+            ));
+        }
+
+        column.column_type = column_type;
+
+        return self.check();
+    }
+
+    pub fn set_default(&mut self, name: &str, default: Option<DefaultValue>) -> Result<(), String> {
+        let idx = self.column_idx(name)?;
+        self.columns[idx].default = default;
+
+        return self.check();
+    }
+
+    /// Tables hold no rows yet, so there's no NULL to look for before setting NOT NULL
+    pub fn set_not_null(&mut self, name: &str, not_null: bool) -> Result<(), String> {
+        let idx = self.column_idx(name)?;
+        self.columns[idx].is_optional = !not_null;
+
+        return self.check();
+    }
+
+    /// Add `constraint`, named after the table and its columns if it has no name
+    pub fn add_constraint(&mut self, mut constraint: Constraint) -> Result<(), String> {
+        let name = match constraint.name.take() {
+            Some(name) => name,
+            None => self.generate_constraint_name(&constraint.kind),
+        };
+
+        if self.constraint_idx(&name).is_some() {
+            return Err(format!("Constraint [{}] already exists", name));
+        }
+
+        constraint.name = Some(name);
+        self.constraints.push(constraint);
+        self.mark_foreign_key_columns();
+
+        return self.check();
+    }
+
+    pub fn drop_constraint(&mut self, name: &str) -> Result<(), String> {
+        let idx = self.constraint_idx(name).ok_or_else(|| {
+            format!(
+                "No constraint with name [{}] on table [{}]",
+                name, self.name
+            )
+        })?;
+
+        self.constraints.remove(idx);
+        self.mark_foreign_key_columns();
+
+        return Ok(());
+    }
+
+    pub fn add_policy(&mut self, policy: Policy) -> Result<(), String> {
+        if self.policy_idx(&policy.name).is_some() {
+            return Err(format!("Policy [{}] already exists", policy.name));
+        }
+
+        self.policies.push(policy);
+
+        return self.check();
+    }
+
+    pub fn drop_policy(&mut self, name: &str) -> Result<(), String> {
+        let idx = self
+            .policy_idx(name)
+            .ok_or_else(|| format!("No policy with name [{}] on table [{}]", name, self.name))?;
+
+        self.policies.remove(idx);
+
+        return Ok(());
+    }
+
+    /// Policies whose predicate is on column `name`
+    pub fn policies_on<'a>(&'a self, name: &'a str) -> impl Iterator<Item = &'a Policy> {
+        return self
+            .policies
+            .iter()
+            .filter(move |policy| policy.predicate.column == name);
+    }
+
+    /// Whether the policies let a member of `roles` run `command` on a row, whose values
+    /// `value_of` returns by column name. Every row is allowed on a table without policies.
+    pub fn row_allowed<'a>(
+        &self,
+        roles: &[&str],
+        command: PolicyCommand,
+        value_of: impl Fn(&str) -> Option<&'a str>,
+    ) -> Result<bool, String> {
+        if self.policies.is_empty() {
+            return Ok(true);
+        }
+
+        for policy in self
+            .policies
+            .iter()
+            .filter(|p| p.applies_to(roles, command))
+        {
+            let column = &self.columns[self.column_idx(&policy.predicate.column)?];
+
+            if policy.allows(column.column_type, value_of(&column.name))? {
+                return Ok(true);
+            }
+        }
+
+        return Ok(false);
+    }
+
+    /// Named foreign keys of the table
+    pub fn foreign_keys(&self) -> impl Iterator<Item = (&str, &ForeignKey)> {
+        return self
+            .constraints
+            .iter()
+            .filter_map(|constraint| match &constraint.kind {
+                ConstraintKind::ForeignKey(fk) => {
+                    Some((constraint.name.as_deref().unwrap_or_default(), fk))
+                }
+                _ => None,
+            });
+    }
+
+    /// Names of the foreign keys referencing table `name`, which may be this one
+    pub fn references(&self, name: &str) -> Vec<String> {
+        return self
+            .foreign_keys()
+            .filter(|(_, fk)| fk.ref_table == name)
+            .map(|(fk_name, _)| String::from(fk_name))
+            .collect();
+    }
+
+    /// Point the foreign keys referencing table `from` at table `to`
+    pub fn rename_references(&mut self, from: &str, to: &str) -> () {
+        for constraint in self.constraints.iter_mut() {
+            if let ConstraintKind::ForeignKey(fk) = &mut constraint.kind {
+                if fk.ref_table == from {
+                    fk.ref_table = String::from(to);
+                }
+            }
+        }
+    }
+
+    /// Drop the foreign keys referencing table `name`
+    pub fn drop_references(&mut self, name: &str) -> () {
+        self.constraints
+            .retain(|constraint| match &constraint.kind {
+                ConstraintKind::ForeignKey(fk) => fk.ref_table != name,
+                _ => true,
+            });
+        self.mark_foreign_key_columns();
+    }
+
+    /// Fill in the primary key of the referenced table in foreign keys which don't name the
+    /// columns they reference, and fail if a foreign key doesn't match its table. `find` looks up
+    /// the other tables of the database.
+    pub fn link_foreign_keys<'a>(
+        &mut self,
+        find: impl Fn(&str) -> Option<&'a Table>,
+    ) -> Result<(), String> {
+        for idx in 0..self.constraints.len() {
+            let (name, fk) = match &self.constraints[idx] {
+                Constraint {
+                    name,
+                    kind: ConstraintKind::ForeignKey(fk),
+                } => (name.as_deref().unwrap_or_default(), fk),
+                _ => continue,
+            };
+
+            let referenced = match fk.ref_table == self.name {
+                true => self,
+                false => find(&fk.ref_table).ok_or_else(|| {
+                    format!(
+                        "Constraint [{}]: No table with name [{}]",
+                        name, fk.ref_table
+                    )
+                })?,
+            };
+
+            let ref_columns = match fk.ref_columns.is_empty() {
+                true => referenced.primary_key(),
+                false => fk.ref_columns.clone(),
+            };
+
+            if ref_columns.is_empty() {
+                return Err(format!(
+                    "Constraint [{}]: Table [{}] has no primary key to reference",
+                    name, fk.ref_table
+                ));
+            }
+
+            let linked = ForeignKey {
+                ref_columns,
+                ..fk.clone()
+            };
+
+            self.check_reference(&linked, referenced)
+                .map_err(|e| format!("Constraint [{}]: {}", name, e))?;
+
+            self.constraints[idx].kind = ConstraintKind::ForeignKey(linked);
+        }
+
+        return Ok(());
+    }
+
+    /// Fail unless the columns `fk` references in `referenced` exist, have the types of the
+    /// columns referencing them, and are its primary key or UNIQUE
+    pub fn check_reference(&self, fk: &ForeignKey, referenced: &Table) -> Result<(), String> {
+        if fk.columns.len() != fk.ref_columns.len() {
+            return Err(format!(
+                "{} column(s) can't reference {} column(s)",
+                fk.columns.len(),
+                fk.ref_columns.len()
+            ));
+        }
+
+        for (column, ref_column) in fk.columns.iter().zip(&fk.ref_columns) {
+            let column = &self.columns[self.column_idx(column)?];
+            let ref_column = &referenced.columns[referenced.column_idx(ref_column)?];
+
+            if column.column_type != ref_column.column_type {
+                return Err(format!(
+                    "Column [{}] is a {} but references [{}.{}], a {}",
+                    column.name,
+                    column.column_type.name(),
+                    referenced.name,
+                    ref_column.name,
+                    ref_column.column_type.name()
+                ));
+            }
+        }
+
+        let same_columns = |columns: &[&str]| {
+            columns.len() == fk.ref_columns.len()
+                && columns
+                    .iter()
+                    .all(|column| fk.ref_columns.iter().any(|c| c == column))
+        };
+
+        let primary_key = referenced.primary_key();
+        let primary_key: Vec<&str> = primary_key.iter().map(|c| c.as_str()).collect();
+
+        let is_key = same_columns(&primary_key)
+            || referenced
+                .constraints
+                .iter()
+                .any(|constraint| match &constraint.kind {
+                    ConstraintKind::Unique { columns } => {
+                        same_columns(&columns.iter().map(|c| c.as_str()).collect::<Vec<&str>>())
+                    }
+                    _ => false,
+                });
+
+        if !is_key {
+            return Err(format!(
+                "Columns ({}) of table [{}] are neither its primary key nor UNIQUE",
+                fk.ref_columns.join(", "),
+                referenced.name
+            ));
+        }
+
+        return Ok(());
+    }
+
+    pub fn primary_key(&self) -> Vec<String> {
+        return self
+            .columns
+            .iter()
+            .filter(|col| col.is_primary_key)
+            .map(|col| col.name.clone())
+            .collect();
+    }
+
+    /// Constraints on column `name`
+    pub fn constraints_on<'a>(&'a self, name: &'a str) -> impl Iterator<Item = &'a Constraint> {
+        return self
+            .constraints
+            .iter()
+            .filter(move |constraint| constraint.kind.columns().contains(&name));
+    }
+
+    /// Fail if two columns share a name, a primary key column is nullable, a default doesn't
+    /// match its column's type or a constraint or policy doesn't match the columns. Foreign keys are only
+    /// checked against the table they reference by `Table::link_foreign_keys`.
+    ///
+    /// There's no row storage yet, so constraints and policies only describe the schema.
+    /// DESCRIBE marks them NOT ENFORCED.
+    fn check(&self) -> Result<(), String> {
+        let mut names = HashSet::new();
+
+        for column in &self.columns {
+            if !names.insert(column.name.as_str()) {
+                return Err(format!("Column [{}] defined more than once", column.name));
+            }
+
+            if column.is_primary_key && column.is_optional {
+                return Err(format!(
+                    "Primary key column [{}] can't be nullable",
+                    column.name
+                ));
+            }
+
+            column.check_default()?;
+        }
+
+        for constraint in &self.constraints {
+            let name = constraint.name.as_deref().unwrap_or_default();
+            let columns = constraint.kind.columns();
+
+            if columns.is_empty() {
+                return Err(format!("Constraint [{}] has no columns", name));
+            }
+
+            for (i, column) in columns.iter().enumerate() {
+                if columns[..i].contains(column) {
+                    return Err(format!(
+                        "Constraint [{}] names column [{}] more than once",
+                        name, column
+                    ));
+                }
+
+                if let Err(e) = self.column_idx(column) {
+                    return Err(format!("Constraint [{}]: {}", name, e));
+                }
+            }
+
+            match &constraint.kind {
+                ConstraintKind::Check(check) => {
+                    let idx = self.column_idx(&check.column)?;
+                    check
+                        .check_type(self.columns[idx].column_type)
+                        .map_err(|e| format!("Constraint [{}]: {}", name, e))?;
+                }
+                ConstraintKind::ForeignKey(fk) => self
+                    .check_foreign_key(fk)
+                    .map_err(|e| format!("Constraint [{}]: {}", name, e))?,
+                ConstraintKind::Unique { .. } => {}
+            }
+        }
+
+        for policy in &self.policies {
+            let idx = self
+                .column_idx(&policy.predicate.column)
+                .map_err(|e| format!("Policy [{}]: {}", policy.name, e))?;
+            policy.check_type(self.columns[idx].column_type)?;
+        }
+
+        return Ok(());
+    }
+
+    /// The checks of a foreign key which don't need the table it references, see
+    /// `Table::check_reference`
+    fn check_foreign_key(&self, fk: &ForeignKey) -> Result<(), String> {
+        if !fk.ref_columns.is_empty() && fk.ref_columns.len() != fk.columns.len() {
+            return Err(format!(
+                "{} column(s) can't reference {} column(s)",
+                fk.columns.len(),
+                fk.ref_columns.len()
+            ));
+        }
+
+        let set_null = [("DELETE", fk.on_delete), ("UPDATE", fk.on_update)]
+            .into_iter()
+            .find(|(_, action)| *action == ReferentialAction::SetNull);
+
+        if let Some((event, _)) = set_null {
+            for column in &fk.columns {
+                if !self.columns[self.column_idx(column)?].is_optional {
+                    return Err(format!(
+                        "ON {} SET NULL needs column [{}] to be nullable",
+                        event, column
+                    ));
+                }
+            }
         }
+
+        return Ok(());
+    }
+
+    fn mark_foreign_key_columns(&mut self) -> () {
+        let marked: Vec<bool> = self
+            .columns
+            .iter()
+            .map(|col| {
+                self.foreign_keys()
+                    .any(|(_, fk)| fk.columns.contains(&col.name))
+            })
+            .collect();
+
+        for (col, is_foreign_key) in self.columns.iter_mut().zip(marked) {
+            col.is_foreign_key = is_foreign_key;
+        }
+    }
+
+    /// `<table>_<columns>_<key|check|fkey>`, numbered if that's taken
+    fn generate_constraint_name(&self, kind: &ConstraintKind) -> String {
+        let base = format!(
+            "{}_{}_{}",
+            self.name,
+            kind.columns().join("_"),
+            kind.suffix()
+        );
+
+        let mut name = base.clone();
+        let mut n = 1;
+
+        while self.constraint_idx(&name).is_some() {
+            name = format!("{}{}", base, n);
+            n += 1;
+        }
+
+        return name;
+    }
+
+    fn column_idx(&self, name: &str) -> Result<usize, String> {
+        return self
+            .columns
+            .iter()
+            .position(|col| col.name == name)
+            .ok_or_else(|| format!("No column with name [{}] in table [{}]", name, self.name));
+    }
+
+    fn constraint_idx(&self, name: &str) -> Option<usize> {
+        return self
+            .constraints
+            .iter()
+            .position(|constraint| constraint.name.as_deref() == Some(name));
+    }
+
+    fn policy_idx(&self, name: &str) -> Option<usize> {
+        return self.policies.iter().position(|policy| policy.name == name);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::acl::PUBLIC;
+    use crate::constraint::{Check, CompareOp};
+
+    fn column(name: &str, column_type: ColumnType) -> Column {
+        return Column {
+            name: String::from(name),
+            column_type,
+            is_optional: true,
+            is_primary_key: false,
+            is_foreign_key: false,
+            default: None,
+        };
+    }
+
+    fn primary_key(name: &str) -> Column {
+        return Column {
+            is_optional: false,
+            is_primary_key: true,
+            ..column(name, ColumnType::SINT32)
+        };
+    }
+
+    fn unique(columns: &[&str]) -> Constraint {
+        return Constraint {
+            name: None,
+            kind: ConstraintKind::Unique {
+                columns: columns.iter().map(|c| String::from(*c)).collect(),
+            },
+        };
+    }
+
+    fn column_names(table: &Table) -> Vec<&str> {
+        return table.columns.iter().map(|col| col.name.as_str()).collect();
+    }
+
+    fn users() -> Table {
+        return Table::new(
+            String::from("users"),
+            vec![
+                primary_key("id"),
+                column("email", ColumnType::STRING),
+                column("age", ColumnType::UINT8),
+            ],
+            vec![unique(&["email"])],
+        )
+        .unwrap();
+    }
+
+    #[test]
+    fn add_column_fails_on_duplicate_name() {
+        let mut table = users();
+
+        table
+            .add_column(column("name", ColumnType::STRING), vec![])
+            .unwrap();
+        assert_eq!(column_names(&table), ["id", "email", "age", "name"]);
+
+        let e = table
+            .clone()
+            .add_column(column("age", ColumnType::STRING), vec![])
+            .unwrap_err();
+        assert_eq!(e, "Column [age] defined more than once");
+    }
+
+    #[test]
+    fn drop_column_drops_its_constraints() {
+        let mut table = users();
+
+        table.drop_column("email").unwrap();
+
+        assert_eq!(column_names(&table), ["id", "age"]);
+        assert!(table.constraints.is_empty());
+        assert!(table.drop_column("email").is_err());
+    }
+
+    #[test]
+    fn dropping_primary_key_column_drops_primary_key() {
+        let mut table = Table::new(
+            String::from("pairs"),
+            vec![primary_key("a"), primary_key("b")],
+            vec![],
+        )
+        .unwrap();
+
+        table.drop_column("a").unwrap();
+        assert!(table.primary_key().is_empty());
+    }
+
+    #[test]
+    fn rename_column_updates_constraints() {
+        let mut table = users();
+
+        table
+            .rename_column("email", String::from("address"))
+            .unwrap();
+
+        assert_eq!(column_names(&table), ["id", "address", "age"]);
+        assert_eq!(
+            table.constraints[0].kind,
+            ConstraintKind::Unique {
+                columns: vec![String::from("address")]
+            }
+        );
+        // The constraint keeps its name
+        assert_eq!(
+            table.constraints[0].name.as_deref(),
+            Some("users_email_key")
+        );
+
+        assert_eq!(
+            table.rename_column("age", String::from("id")),
+            Err(String::from("Column [id] already exists"))
+        );
+    }
+
+    #[test]
+    fn column_type_only_changes_without_losing_data() {
+        let mut table = users();
+
+        table.alter_column_type("age", ColumnType::SINT32).unwrap();
+        table.alter_column_type("age", ColumnType::FLOAT64).unwrap();
+        table.alter_column_type("age", ColumnType::STRING).unwrap();
+
+        assert!(table.alter_column_type("age", ColumnType::SINT32).is_err());
+        assert!(table.alter_column_type("id", ColumnType::SINT8).is_err());
+        assert!(table.alter_column_type("nope", ColumnType::STRING).is_err());
+    }
+
+    #[test]
+    fn constraints_are_named_after_table_and_columns() {
+        let mut table = users();
+        table.add_constraint(unique(&["email"])).unwrap();
+        table.add_constraint(unique(&["email"])).unwrap();
+        table.add_constraint(unique(&["email", "age"])).unwrap();
+
+        let names: Vec<&str> = table
+            .constraints
+            .iter()
+            .map(|constraint| constraint.name.as_deref().unwrap())
+            .collect();
+        assert_eq!(
+            names,
+            [
+                "users_email_key",
+                "users_email_key1",
+                "users_email_key2",
+                "users_email_age_key"
+            ]
+        );
+
+        let named = Constraint {
+            name: Some(String::from("users_email_key")),
+            ..unique(&["age"])
+        };
+        assert_eq!(
+            table.add_constraint(named),
+            Err(String::from("Constraint [users_email_key] already exists"))
+        );
+    }
+
+    #[test]
+    fn constraints_must_match_columns() {
+        let e = users().add_constraint(unique(&["name"])).unwrap_err();
+        assert_eq!(
+            e,
+            "Constraint [users_name_key]: No column with name [name] in table [users]"
+        );
+
+        let e = users().add_constraint(unique(&["age", "age"])).unwrap_err();
+        assert_eq!(
+            e,
+            "Constraint [users_age_age_key] names column [age] more than once"
+        );
+
+        let e = users().add_constraint(unique(&[])).unwrap_err();
+        assert_eq!(e, "Constraint [users__key] has no columns");
+    }
+
+    #[test]
+    fn defaults_must_match_column_type() {
+        let mut table = users();
+
+        table
+            .set_default("age", Some(DefaultValue::Literal(String::from("18"))))
+            .unwrap();
+        assert!(table
+            .clone()
+            .set_default("age", Some(DefaultValue::Literal(String::from("-1"))))
+            .is_err());
+        assert!(table
+            .clone()
+            .set_default("age", Some(DefaultValue::Now))
+            .is_err());
+
+        table.set_default("email", Some(DefaultValue::Now)).unwrap();
+        // A STRING can't go back to a number
+        assert!(table.alter_column_type("email", ColumnType::UINT8).is_err());
+    }
+
+    #[test]
+    fn primary_key_columns_are_not_null() {
+        let optional_key = Column {
+            is_optional: true,
+            ..primary_key("id")
+        };
+        let e = Table::new(String::from("t"), vec![optional_key], vec![]).unwrap_err();
+        assert_eq!(e, "Primary key column [id] can't be nullable");
+
+        let e = users().set_not_null("id", false).unwrap_err();
+        assert_eq!(e, "Primary key column [id] can't be nullable");
+    }
+
+    fn check(column: &str, op: CompareOp, value: &str) -> Constraint {
+        return Constraint {
+            name: None,
+            kind: ConstraintKind::Check(Check {
+                column: String::from(column),
+                op,
+                value: String::from(value),
+            }),
+        };
+    }
+
+    #[test]
+    fn check_values_must_match_column_type() {
+        let mut table = users();
+        table
+            .add_constraint(check("age", CompareOp::Ge, "18"))
+            .unwrap();
+
+        let e = users()
+            .add_constraint(check("age", CompareOp::Ge, "-1"))
+            .unwrap_err();
+        assert_eq!(
+            e,
+            "Constraint [users_age_check]: CHECK value [-1] isn't a UINT8 like column [age]"
+        );
+
+        // The check follows its column's type
+        table.alter_column_type("age", ColumnType::SINT32).unwrap();
+        table.rename_column("age", String::from("years")).unwrap();
+        assert_eq!(table.constraints[1].kind.describe(), "CHECK (years >= 18)");
+    }
+
+    fn foreign_key(columns: &[&str], ref_table: &str, ref_columns: &[&str]) -> Constraint {
+        return Constraint {
+            name: None,
+            kind: ConstraintKind::ForeignKey(ForeignKey {
+                columns: columns.iter().map(|c| String::from(*c)).collect(),
+                ref_table: String::from(ref_table),
+                ref_columns: ref_columns.iter().map(|c| String::from(*c)).collect(),
+                on_delete: ReferentialAction::Restrict,
+                on_update: ReferentialAction::Restrict,
+            }),
+        };
+    }
+
+    fn orders(constraint: Constraint) -> Result<Table, String> {
+        let mut table = Table::new(
+            String::from("orders"),
+            vec![
+                primary_key("id"),
+                column("user_id", ColumnType::SINT32),
+                column("email", ColumnType::STRING),
+            ],
+            vec![constraint],
+        )?;
+
+        let users = users();
+        table.link_foreign_keys(|name| match name == "users" {
+            true => Some(&users),
+            false => None,
+        })?;
+
+        return Ok(table);
+    }
+
+    #[test]
+    fn foreign_key_references_primary_key_by_default() {
+        let table = orders(foreign_key(&["user_id"], "users", &[])).unwrap();
+
+        let (name, fk) = table.foreign_keys().next().unwrap();
+        assert_eq!(name, "orders_user_id_fkey");
+        assert_eq!(fk.ref_columns, ["id"]);
+
+        let user_id = &table.columns[1];
+        assert!(user_id.is_foreign_key);
+        assert!(!table.columns[2].is_foreign_key);
+    }
+
+    #[test]
+    fn foreign_key_must_reference_a_key() {
+        orders(foreign_key(&["email"], "users", &["email"])).unwrap();
+
+        let e = orders(foreign_key(&["user_id"], "nope", &[])).unwrap_err();
+        assert_eq!(
+            e,
+            "Constraint [orders_user_id_fkey]: No table with name [nope]"
+        );
+
+        let e = orders(foreign_key(&["email"], "users", &["id"])).unwrap_err();
+        assert_eq!(
+            e,
+            "Constraint [orders_email_fkey]: Column [email] is a STRING but references [users.id], a SINT32"
+        );
+
+        let e = orders(foreign_key(
+            &["user_id", "email"],
+            "users",
+            &["id", "email"],
+        ))
+        .unwrap_err();
+        assert!(e.contains("neither its primary key nor UNIQUE"), "{}", e);
+
+        let e = orders(foreign_key(&["user_id", "email"], "users", &["id"])).unwrap_err();
+        assert!(
+            e.contains("2 column(s) can't reference 1 column(s)"),
+            "{}",
+            e
+        );
+    }
+
+    #[test]
+    fn foreign_key_needs_primary_key_to_default_to() {
+        let mut table = Table::new(
+            String::from("orders"),
+            vec![column("user_id", ColumnType::SINT32)],
+            vec![foreign_key(&["user_id"], "users", &[])],
+        )
+        .unwrap();
+
+        let keyless = Table::new(
+            String::from("users"),
+            vec![column("id", ColumnType::SINT32)],
+            vec![],
+        )
+        .unwrap();
+
+        let e = table.link_foreign_keys(|_| Some(&keyless)).unwrap_err();
+        assert_eq!(
+            e,
+            "Constraint [orders_user_id_fkey]: Table [users] has no primary key to reference"
+        );
+    }
+
+    fn with_actions(
+        mut constraint: Constraint,
+        on_delete: ReferentialAction,
+        on_update: ReferentialAction,
+    ) -> Constraint {
+        if let ConstraintKind::ForeignKey(fk) = &mut constraint.kind {
+            fk.on_delete = on_delete;
+            fk.on_update = on_update;
+        }
+
+        return constraint;
+    }
+
+    #[test]
+    fn referential_actions_are_kept() {
+        let constraint = with_actions(
+            foreign_key(&["user_id"], "users", &[]),
+            ReferentialAction::Cascade,
+            ReferentialAction::SetNull,
+        );
+
+        let table = orders(constraint).unwrap();
+        let (_, fk) = table.foreign_keys().next().unwrap();
+        assert_eq!(fk.on_delete, ReferentialAction::Cascade);
+        assert_eq!(fk.on_update, ReferentialAction::SetNull);
+    }
+
+    #[test]
+    fn set_null_needs_nullable_columns() {
+        let set_null = |on_delete, on_update| {
+            with_actions(
+                foreign_key(&["user_id"], "users", &[]),
+                on_delete,
+                on_update,
+            )
+        };
+
+        let mut table = orders(set_null(
+            ReferentialAction::SetNull,
+            ReferentialAction::SetNull,
+        ))
+        .unwrap();
+        assert_eq!(
+            table.set_not_null("user_id", true),
+            Err(String::from(
+                "Constraint [orders_user_id_fkey]: ON DELETE SET NULL needs column [user_id] to be nullable"
+            ))
+        );
+
+        let mut table = orders(foreign_key(&["email"], "users", &["email"])).unwrap();
+        table.set_not_null("user_id", true).unwrap();
+        let e = table
+            .add_constraint(set_null(
+                ReferentialAction::Restrict,
+                ReferentialAction::SetNull,
+            ))
+            .unwrap_err();
+        assert_eq!(
+            e,
+            "Constraint [orders_user_id_fkey]: ON UPDATE SET NULL needs column [user_id] to be nullable"
+        );
+    }
+
+    #[test]
+    fn self_reference_follows_table_rename() {
+        let mut table = Table::new(
+            String::from("nodes"),
+            vec![primary_key("id"), column("parent", ColumnType::SINT32)],
+            vec![foreign_key(&["parent"], "nodes", &[])],
+        )
+        .unwrap();
+        table.link_foreign_keys(|_| None).unwrap();
+        assert_eq!(table.references("nodes"), ["nodes_parent_fkey"]);
+
+        table.name = String::from("tree");
+        table.rename_references("nodes", "tree");
+        assert!(table.references("nodes").is_empty());
+        table.link_foreign_keys(|_| None).unwrap();
+    }
+
+    #[test]
+    fn dropping_references_unmarks_columns() {
+        let mut table = orders(foreign_key(&["user_id"], "users", &[])).unwrap();
+
+        table.drop_references("users");
+
+        assert!(table.constraints.is_empty());
+        assert!(!table.columns[1].is_foreign_key);
+    }
+
+    fn policy(name: &str, role: &str, column: &str, value: &str) -> Policy {
+        return Policy {
+            name: String::from(name),
+            command: PolicyCommand::All,
+            role: String::from(role),
+            predicate: Check {
+                column: String::from(column),
+                op: CompareOp::Eq,
+                value: String::from(value),
+            },
+        };
+    }
+
+    #[test]
+    fn policies_must_match_the_columns() {
+        let mut table = users();
+
+        assert_eq!(
+            table.add_policy(policy("p", PUBLIC, "tenant", "1")),
+            Err(String::from(
+                "Policy [p]: No column with name [tenant] in table [users]"
+            ))
+        );
+
+        let mut table = users();
+        assert_eq!(
+            table.add_policy(policy("p", PUBLIC, "age", "old")),
+            Err(String::from(
+                "Policy [p]: value [old] isn't a UINT8 like column [age]"
+            ))
+        );
+
+        let mut table = users();
+        table.add_policy(policy("p", PUBLIC, "age", "18")).unwrap();
+        assert_eq!(
+            table.add_policy(policy("p", PUBLIC, "id", "1")),
+            Err(String::from("Policy [p] already exists"))
+        );
+    }
+
+    #[test]
+    fn policies_follow_their_column() {
+        let mut table = users();
+        table.add_policy(policy("p", PUBLIC, "age", "18")).unwrap();
+
+        table.rename_column("age", String::from("years")).unwrap();
+        assert_eq!(table.policies[0].predicate.column, "years");
+
+        assert_eq!(
+            table.drop_column("years"),
+            Err(String::from(
+                "Column [years] is used by policy [p]. Drop the policy first"
+            ))
+        );
+
+        let mut table = users();
+        table.add_policy(policy("p", PUBLIC, "age", "18")).unwrap();
+        assert_eq!(table.alter_column_type("age", ColumnType::UINT32), Ok(()));
+        assert_eq!(
+            table.drop_policy("q"),
+            Err(String::from("No policy with name [q] on table [users]"))
+        );
+        table.drop_policy("p").unwrap();
+        table.drop_column("age").unwrap();
+    }
+
+    #[test]
+    fn rows_need_a_policy_allowing_them() {
+        let mut table = users();
+        let row = |age: Option<&'static str>| {
+            move |column: &str| match column {
+                "age" => age,
+                _ => None,
+            }
+        };
+
+        // Without policies, every row is allowed
+        assert_eq!(
+            table.row_allowed(&[], PolicyCommand::Select, row(Some("30"))),
+            Ok(true)
+        );
+
+        table
+            .add_policy(policy("adults", "analysts", "age", "18"))
+            .unwrap();
+        table
+            .add_policy(Policy {
+                command: PolicyCommand::Select,
+                ..policy("seniors", "analysts", "age", "65")
+            })
+            .unwrap();
+
+        let analysts = ["analysts"];
+        assert_eq!(
+            table.row_allowed(&analysts, PolicyCommand::Select, row(Some("18"))),
+            Ok(true)
+        );
+        assert_eq!(
+            table.row_allowed(&analysts, PolicyCommand::Select, row(Some("65"))),
+            Ok(true)
+        );
+        assert_eq!(
+            table.row_allowed(&analysts, PolicyCommand::Delete, row(Some("65"))),
+            Ok(false)
+        );
+        assert_eq!(
+            table.row_allowed(&analysts, PolicyCommand::Select, row(None)),
+            Ok(false)
+        );
+
+        // No policy applies to other roles, so they see nothing
+        assert_eq!(
+            table.row_allowed(&["sales"], PolicyCommand::Select, row(Some("18"))),
+            Ok(false)
+        );
     }
 }
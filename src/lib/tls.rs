@@ -0,0 +1,122 @@
+use std::fs::File;
+use std::io::{self, BufReader, ErrorKind};
+use std::path::Path;
+use std::sync::Arc;
+
+use rustls::pki_types::{CertificateDer, PrivateKeyDer, ServerName};
+use rustls::server::WebPkiClientVerifier;
+use rustls::{ClientConfig, RootCertStore, ServerConfig};
+
+/// TLS settings of the server. With `client_ca` set, clients must present a certificate signed
+/// by one of the CAs in it.
+pub fn server_config(
+    cert: &Path,
+    key: &Path,
+    client_ca: Option<&Path>,
+) -> io::Result<Arc<ServerConfig>> {
+    let builder = ServerConfig::builder();
+
+    let builder = match client_ca {
+        Some(client_ca) => {
+            let verifier = WebPkiClientVerifier::builder(Arc::new(load_roots(client_ca)?))
+                .build()
+                .map_err(|e| invalid(client_ca, e))?;
+
+            builder.with_client_cert_verifier(verifier)
+        }
+        None => builder.with_no_client_auth(),
+    };
+
+    let config = builder
+        .with_single_cert(load_certs(cert)?, load_key(key)?)
+        .map_err(|e| invalid(key, e))?;
+
+    return Ok(Arc::new(config));
+}
+
+/// TLS settings of the client. Servers are verified against `ca_cert`, or the system's trusted
+/// certificates when it's `None`. `client_cert` is a certificate and key to present to servers
+/// which verify clients.
+pub fn client_config(
+    ca_cert: Option<&Path>,
+    client_cert: Option<(&Path, &Path)>,
+) -> io::Result<Arc<ClientConfig>> {
+    let roots = match ca_cert {
+        Some(ca_cert) => load_roots(ca_cert)?,
+        None => {
+            let mut roots = RootCertStore::empty();
+            roots.add_parsable_certificates(rustls_native_certs::load_native_certs().certs);
+            roots
+        }
+    };
+
+    let builder = ClientConfig::builder().with_root_certificates(roots);
+
+    let config = match client_cert {
+        Some((cert, key)) => builder
+            .with_client_auth_cert(load_certs(cert)?, load_key(key)?)
+            .map_err(|e| invalid(key, e))?,
+        None => builder.with_no_client_auth(),
+    };
+
+    return Ok(Arc::new(config));
+}
+
+/// Name the server's certificate must be valid for, from the host the client connects to
+pub fn server_name(host: &str) -> io::Result<ServerName<'static>> {
+    return ServerName::try_from(String::from(host)).map_err(|e| {
+        io::Error::new(
+            ErrorKind::InvalidInput,
+            format!("Invalid TLS server name [{}]: {}", host, e),
+        )
+    });
+}
+
+fn load_certs(path: &Path) -> io::Result<Vec<CertificateDer<'static>>> {
+    let mut reader = BufReader::new(open(path)?);
+
+    let certs = rustls_pemfile::certs(&mut reader)
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|e| invalid(path, e))?;
+
+    if certs.is_empty() {
+        return Err(invalid(path, "no certificate found"));
+    }
+
+    return Ok(certs);
+}
+
+fn load_key(path: &Path) -> io::Result<PrivateKeyDer<'static>> {
+    let mut reader = BufReader::new(open(path)?);
+
+    return match rustls_pemfile::private_key(&mut reader).map_err(|e| invalid(path, e))? {
+        Some(key) => Ok(key),
+        None => Err(invalid(path, "no private key found")),
+    };
+}
+
+fn load_roots(path: &Path) -> io::Result<RootCertStore> {
+    let mut roots = RootCertStore::empty();
+
+    for cert in load_certs(path)? {
+        roots.add(cert).map_err(|e| invalid(path, e))?;
+    }
+
+    return Ok(roots);
+}
+
+fn open(path: &Path) -> io::Result<File> {
+    return File::open(path).map_err(|e| {
+        io::Error::new(
+            e.kind(),
+            format!("Failed to open [{}]: {}", path.display(), e),
+        )
+    });
+}
+
+fn invalid(path: &Path, e: impl std::fmt::Display) -> io::Error {
+    return io::Error::new(
+        ErrorKind::InvalidData,
+        format!("Invalid TLS file [{}]: {}", path.display(), e),
+    );
+}
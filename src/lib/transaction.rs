@@ -0,0 +1,1273 @@
+use crate::database::Database;
+use crate::error::{Error, ErrorCode};
+use crate::mvcc::{Snapshot, TxnId, TxnManager, Version};
+use crate::table::Table;
+
+/// Every version of every database, see `mvcc`
+pub type Databases = Vec<Version<Database>>;
+
+/// Change to the databases made by a command
+#[derive(Debug, Clone, PartialEq)]
+pub enum Change {
+    CreateDatabase {
+        name: String,
+    },
+    CreateTable {
+        db: String,
+        name: String,
+    },
+    DropDatabase {
+        name: String,
+    },
+    DropTable {
+        db: String,
+        name: String,
+    },
+    /// Deletes the database and creates a copy of it, tables included, under the new name
+    RenameDatabase {
+        from: String,
+        to: String,
+    },
+    RenameTable {
+        db: String,
+        from: String,
+        to: String,
+    },
+    /// Deletes the table and creates a copy of it with the new columns
+    AlterTable {
+        db: String,
+        name: String,
+    },
+}
+
+/// How much a transaction is isolated from concurrent ones
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum IsolationLevel {
+    /// Each command sees everything committed before it started
+    ReadCommitted,
+    /// Every command sees the snapshot taken when the transaction started
+    RepeatableRead,
+    /// Like `RepeatableRead`, and the transaction fails to commit if a concurrent transaction
+    /// committed a change to anything it read, so that transactions behave as if run one at a time
+    Serializable,
+}
+
+impl IsolationLevel {
+    pub fn name(&self) -> &'static str {
+        return match self {
+            IsolationLevel::ReadCommitted => "READ COMMITTED",
+            IsolationLevel::RepeatableRead => "REPEATABLE READ",
+            IsolationLevel::Serializable => "SERIALIZABLE",
+        };
+    }
+}
+
+/// Part of the databases read by a transaction, tracked for `IsolationLevel::Serializable`
+#[derive(Debug, Clone, PartialEq)]
+pub enum Read {
+    /// Which databases exist
+    Databases,
+    /// Whether the database exists
+    Database { name: String },
+    /// Which tables exist in the database
+    Tables { db: String },
+}
+
+/// Why a value can't be created
+enum Conflict {
+    /// A version visible to the transaction already uses the name
+    Exists,
+    /// A transaction which committed after the snapshot was taken created the same name first
+    Concurrent,
+}
+
+/// Transaction reading through snapshots, see `IsolationLevel`. Its changes are written as new
+/// versions straight away but stay invisible to other transactions until it commits. When two
+/// transactions make conflicting changes the first one to commit wins and the other one fails.
+#[derive(Debug)]
+pub struct Transaction {
+    snapshot: Snapshot,
+    isolation_level: IsolationLevel,
+    changes: Vec<Change>,
+    reads: Vec<Read>,
+
+    /// Named savepoints, with the number of changes made when each was set
+    savepoints: Vec<(String, usize)>,
+
+    /// Whether a command ran in the transaction, after which the isolation level is fixed
+    started: bool,
+}
+
+impl Transaction {
+    pub fn begin(txns: &TxnManager) -> Transaction {
+        Transaction {
+            snapshot: txns.begin(),
+            isolation_level: IsolationLevel::RepeatableRead,
+            changes: vec![],
+            reads: vec![],
+            savepoints: vec![],
+            started: false,
+        }
+    }
+
+    pub fn isolation_level(&self) -> IsolationLevel {
+        return self.isolation_level;
+    }
+
+    /// Change the isolation level. Only allowed before the transaction read or changed anything.
+    pub fn set_isolation_level(
+        &mut self,
+        isolation_level: IsolationLevel,
+        txns: &TxnManager,
+    ) -> Result<(), Error> {
+        if self.started {
+            return Err(Error::new(
+                ErrorCode::InvalidTransactionState,
+                String::from(
+                    "SET TRANSACTION ISOLATION LEVEL must be run before any other command of the transaction",
+                ),
+            ));
+        }
+
+        self.isolation_level = isolation_level;
+        self.snapshot = txns.refresh(self.id());
+
+        return Ok(());
+    }
+
+    /// Called before each command reading or changing the databases. Under
+    /// `IsolationLevel::ReadCommitted` each command sees the changes committed before it started.
+    pub fn start_command(&mut self, txns: &TxnManager) -> () {
+        if self.isolation_level == IsolationLevel::ReadCommitted {
+            self.snapshot = txns.refresh(self.id());
+        }
+
+        self.started = true;
+    }
+
+    /// Remember that the transaction read `read`, if its isolation level needs to know
+    pub fn record_read(&mut self, read: Read) -> () {
+        if self.isolation_level == IsolationLevel::Serializable && !self.reads.contains(&read) {
+            self.reads.push(read);
+        }
+    }
+
+    pub fn id(&self) -> TxnId {
+        // Snapshots from `TxnManager::begin` always belong to a transaction
+        return self.snapshot.txn().unwrap();
+    }
+
+    pub fn snapshot(&self) -> &Snapshot {
+        return &self.snapshot;
+    }
+
+    pub fn changes(&self) -> &[Change] {
+        return &self.changes;
+    }
+
+    /// Point `rollback_to_mark` can undo the changes made since
+    pub fn mark(&self) -> usize {
+        return self.changes.len();
+    }
+
+    /// Undo the changes made since `mark`, most recent first
+    pub fn rollback_to_mark(&mut self, mark: usize, databases: &mut Databases) -> () {
+        let id = self.id();
+
+        while self.changes.len() > mark {
+            match self.changes.pop().unwrap() {
+                Change::CreateDatabase { name } => {
+                    databases.retain(|db| !(db.created_by == id && db.value.name == name));
+                }
+                Change::CreateTable { db, name } => {
+                    for d in databases.iter_mut().filter(|d| d.value.name == db) {
+                        d.value
+                            .tables
+                            .retain(|tb| !(tb.created_by == id && tb.value.name == name));
+                    }
+                }
+                Change::DropDatabase { name } => undelete(databases, id, |db| db.name == name),
+                Change::DropTable { db, name } => {
+                    for d in databases.iter_mut().filter(|d| d.value.name == db) {
+                        undelete(&mut d.value.tables, id, |tb| tb.name == name);
+                    }
+                }
+                Change::RenameDatabase { from, to } => {
+                    uncreate(databases, id, |db| db.name == to);
+                    undelete(databases, id, |db| db.name == from);
+                }
+                Change::RenameTable { db, from, to } => {
+                    for d in databases.iter_mut().filter(|d| d.value.name == db) {
+                        uncreate(&mut d.value.tables, id, |tb| tb.name == to);
+                        undelete(&mut d.value.tables, id, |tb| tb.name == from);
+                    }
+                }
+                Change::AlterTable { db, name } => {
+                    for d in databases.iter_mut().filter(|d| d.value.name == db) {
+                        uncreate(&mut d.value.tables, id, |tb| tb.name == name);
+                        undelete(&mut d.value.tables, id, |tb| tb.name == name);
+                    }
+                }
+            }
+        }
+    }
+
+    /// Set a savepoint called `name`. A savepoint with the same name set earlier is hidden until
+    /// this one is released.
+    pub fn savepoint(&mut self, name: String) -> () {
+        self.savepoints.push((name, self.changes.len()));
+    }
+
+    /// Forget the savepoint called `name` and every savepoint set after it, keeping the changes
+    pub fn release_savepoint(&mut self, name: &str) -> Result<(), Error> {
+        let idx = self.find_savepoint(name)?;
+        self.savepoints.truncate(idx);
+
+        return Ok(());
+    }
+
+    /// Undo the changes made since the savepoint called `name` was set. The savepoint stays, the
+    /// ones set after it are forgotten. Locks taken since are held until the transaction ends.
+    pub fn rollback_to_savepoint(
+        &mut self,
+        name: &str,
+        databases: &mut Databases,
+    ) -> Result<(), Error> {
+        let idx = self.find_savepoint(name)?;
+        let mark = self.savepoints[idx].1;
+
+        self.savepoints.truncate(idx + 1);
+        self.rollback_to_mark(mark, databases);
+
+        return Ok(());
+    }
+
+    fn find_savepoint(&self, name: &str) -> Result<usize, Error> {
+        return self
+            .savepoints
+            .iter()
+            .rposition(|(n, _)| n == name)
+            .ok_or_else(|| {
+                Error::new(
+                    ErrorCode::InvalidTransactionState,
+                    format!("No savepoint with name [{}]", name),
+                )
+            });
+    }
+
+    pub fn create_database(
+        &mut self,
+        databases: &mut Databases,
+        txns: &TxnManager,
+        name: String,
+    ) -> Result<(), Error> {
+        self.record_read(Read::Database { name: name.clone() });
+
+        match self.check_create(databases, |db| db.name == name, txns) {
+            Ok(()) => {}
+            Err(Conflict::Exists) => {
+                return Err(Error::from(format!(
+                    "Failed to create database. Name [{}] already in use",
+                    name
+                )))
+            }
+            Err(Conflict::Concurrent) => {
+                return Err(Error::new(
+                    ErrorCode::SerializationFailure,
+                    format!(
+                        "Failed to create database. [{}] was created by a concurrent transaction",
+                        name
+                    ),
+                ))
+            }
+        }
+
+        databases.push(Version::new(Database::new(name.clone()), self.id()));
+        self.changes.push(Change::CreateDatabase { name });
+
+        return Ok(());
+    }
+
+    pub fn create_table(
+        &mut self,
+        databases: &mut Databases,
+        txns: &TxnManager,
+        db: &str,
+        mut table: Table,
+    ) -> Result<(), Error> {
+        let name = table.name.clone();
+
+        self.record_read(Read::Database {
+            name: String::from(db),
+        });
+        self.record_read(Read::Tables {
+            db: String::from(db),
+        });
+
+        let db_idx = self.find_database(databases, db, "CREATE TABLE")?;
+
+        match self.check_create(&databases[db_idx].value.tables, |tb| tb.name == name, txns) {
+            Ok(()) => {}
+            Err(Conflict::Exists) => {
+                return Err(Error::from(format!(
+                    "CREATE TABLE failed. Name [{}::{}] already in use",
+                    db, name
+                )))
+            }
+            Err(Conflict::Concurrent) => {
+                return Err(Error::new(
+                    ErrorCode::SerializationFailure,
+                    format!(
+                        "CREATE TABLE failed. [{}::{}] was created by a concurrent transaction",
+                        db, name
+                    ),
+                ))
+            }
+        }
+
+        self.link_foreign_keys(&databases[db_idx].value.tables, &mut table, "CREATE TABLE")?;
+
+        databases[db_idx]
+            .value
+            .tables
+            .push(Version::new(table, self.id()));
+        self.changes.push(Change::CreateTable {
+            db: String::from(db),
+            name,
+        });
+
+        return Ok(());
+    }
+
+    /// Drop the database called `name`. Unless `cascade` is set, it must not have any tables.
+    /// Returns whether there was a database to drop, which is only not an error with `if_exists`.
+    pub fn drop_database(
+        &mut self,
+        databases: &mut Databases,
+        name: &str,
+        if_exists: bool,
+        cascade: bool,
+    ) -> Result<bool, Error> {
+        self.record_read(Read::Database {
+            name: String::from(name),
+        });
+        self.record_read(Read::Tables {
+            db: String::from(name),
+        });
+
+        let db_idx = match self.find(databases, |db| db.name == name) {
+            Some(db_idx) => db_idx,
+            None if if_exists => return Ok(false),
+            None => {
+                return Err(Error::from(format!(
+                    "DROP DATABASE failed. No database with name [{}]",
+                    name
+                )))
+            }
+        };
+
+        let tables = visible(&databases[db_idx].value.tables, &self.snapshot).count();
+
+        if tables > 0 && !cascade {
+            return Err(Error::from(format!(
+                "DROP DATABASE failed. Database [{}] has {} table(s), use CASCADE to drop them too",
+                name, tables
+            )));
+        }
+
+        self.delete(&mut databases[db_idx], &format!("database [{}]", name))?;
+        self.changes.push(Change::DropDatabase {
+            name: String::from(name),
+        });
+
+        return Ok(true);
+    }
+
+    /// Drop the table called `name` in database `db`. Unless `cascade` is set, no other table may
+    /// reference it, otherwise their foreign keys referencing it are dropped too. Returns whether
+    /// there was a table to drop, which is only not an error with `if_exists`.
+    pub fn drop_table(
+        &mut self,
+        databases: &mut Databases,
+        db: &str,
+        name: &str,
+        if_exists: bool,
+        cascade: bool,
+    ) -> Result<bool, Error> {
+        self.record_read(Read::Database {
+            name: String::from(db),
+        });
+        self.record_read(Read::Tables {
+            db: String::from(db),
+        });
+
+        let db_idx = self.find_database(databases, db, "DROP TABLE")?;
+        let tables = &mut databases[db_idx].value.tables;
+
+        let tb_idx = match self.find(tables, |tb| tb.name == name) {
+            Some(tb_idx) => tb_idx,
+            None if if_exists => return Ok(false),
+            None => {
+                return Err(Error::from(format!(
+                    "DROP TABLE failed. No table with name [{}::{}]",
+                    db, name
+                )))
+            }
+        };
+
+        if !cascade {
+            let referencing = visible(tables, &self.snapshot)
+                .filter(|tb| tb.name != name)
+                .find_map(|tb| tb.references(name).pop().map(|fk| (tb.name.clone(), fk)));
+
+            if let Some((other, fk)) = referencing {
+                return Err(Error::from(format!(
+                    "DROP TABLE failed. Table [{}::{}] is referenced by constraint [{}] on table [{}], use CASCADE to drop the constraint too",
+                    db, name, fk, other
+                )));
+            }
+        }
+
+        self.delete(&mut tables[tb_idx], &format!("table [{}::{}]", db, name))?;
+        self.changes.push(Change::DropTable {
+            db: String::from(db),
+            name: String::from(name),
+        });
+
+        self.alter_referencing(tables, db, name, |tb| tb.drop_references(name))?;
+
+        return Ok(true);
+    }
+
+    pub fn rename_database(
+        &mut self,
+        databases: &mut Databases,
+        txns: &TxnManager,
+        from: &str,
+        to: &str,
+    ) -> Result<(), Error> {
+        self.record_read(Read::Database {
+            name: String::from(from),
+        });
+        self.record_read(Read::Database {
+            name: String::from(to),
+        });
+
+        let db_idx = self.find_database(databases, from, "ALTER DATABASE")?;
+
+        match self.check_create(databases, |db| db.name == to, txns) {
+            Ok(()) => {}
+            Err(Conflict::Exists) => {
+                return Err(Error::from(format!(
+                    "ALTER DATABASE failed. Name [{}] already in use",
+                    to
+                )))
+            }
+            Err(Conflict::Concurrent) => {
+                return Err(Error::new(
+                    ErrorCode::SerializationFailure,
+                    format!(
+                        "ALTER DATABASE failed. [{}] was created by a concurrent transaction",
+                        to
+                    ),
+                ))
+            }
+        }
+
+        self.delete(&mut databases[db_idx], &format!("database [{}]", from))?;
+
+        // Every version of the tables is copied, so that the renamed database looks the same as
+        // the old one to every snapshot
+        let mut renamed = databases[db_idx].value.clone();
+        renamed.name = String::from(to);
+
+        databases.push(Version::new(renamed, self.id()));
+        self.changes.push(Change::RenameDatabase {
+            from: String::from(from),
+            to: String::from(to),
+        });
+
+        return Ok(());
+    }
+
+    pub fn rename_table(
+        &mut self,
+        databases: &mut Databases,
+        txns: &TxnManager,
+        db: &str,
+        from: &str,
+        to: &str,
+    ) -> Result<(), Error> {
+        self.record_read(Read::Database {
+            name: String::from(db),
+        });
+        self.record_read(Read::Tables {
+            db: String::from(db),
+        });
+
+        let db_idx = self.find_database(databases, db, "ALTER TABLE")?;
+        let tables = &mut databases[db_idx].value.tables;
+
+        let tb_idx = match self.find(tables, |tb| tb.name == from) {
+            Some(tb_idx) => tb_idx,
+            None => {
+                return Err(Error::from(format!(
+                    "ALTER TABLE failed. No table with name [{}::{}]",
+                    db, from
+                )))
+            }
+        };
+
+        match self.check_create(tables, |tb| tb.name == to, txns) {
+            Ok(()) => {}
+            Err(Conflict::Exists) => {
+                return Err(Error::from(format!(
+                    "ALTER TABLE failed. Name [{}::{}] already in use",
+                    db, to
+                )))
+            }
+            Err(Conflict::Concurrent) => {
+                return Err(Error::new(
+                    ErrorCode::SerializationFailure,
+                    format!(
+                        "ALTER TABLE failed. [{}::{}] was created by a concurrent transaction",
+                        db, to
+                    ),
+                ))
+            }
+        }
+
+        self.delete(&mut tables[tb_idx], &format!("table [{}::{}]", db, from))?;
+
+        let mut renamed = tables[tb_idx].value.clone();
+        renamed.name = String::from(to);
+        renamed.rename_references(from, to);
+
+        tables.push(Version::new(renamed, self.id()));
+        self.changes.push(Change::RenameTable {
+            db: String::from(db),
+            from: String::from(from),
+            to: String::from(to),
+        });
+
+        self.alter_referencing(tables, db, from, |tb| tb.rename_references(from, to))?;
+
+        return Ok(());
+    }
+
+    /// Change table `name` in database `db` with `alter`. Errors start with `cmd`.
+    pub fn alter_table(
+        &mut self,
+        databases: &mut Databases,
+        db: &str,
+        name: &str,
+        cmd: &str,
+        alter: impl FnOnce(&mut Table) -> Result<(), String>,
+    ) -> Result<(), Error> {
+        self.record_read(Read::Database {
+            name: String::from(db),
+        });
+        self.record_read(Read::Tables {
+            db: String::from(db),
+        });
+
+        let db_idx = self.find_database(databases, db, cmd)?;
+        let tables = &mut databases[db_idx].value.tables;
+
+        let tb_idx = match self.find(tables, |tb| tb.name == name) {
+            Some(tb_idx) => tb_idx,
+            None => {
+                return Err(Error::from(format!(
+                    "{} failed. No table with name [{}::{}]",
+                    cmd, db, name
+                )))
+            }
+        };
+
+        let mut altered = tables[tb_idx].value.clone();
+        alter(&mut altered).map_err(|e| Error::from(format!("{} failed. {}", cmd, e)))?;
+
+        self.link_foreign_keys(tables, &mut altered, cmd)?;
+
+        self.delete(&mut tables[tb_idx], &format!("table [{}::{}]", db, name))?;
+
+        tables.push(Version::new(altered, self.id()));
+        self.changes.push(Change::AlterTable {
+            db: String::from(db),
+            name: String::from(name),
+        });
+
+        return Ok(());
+    }
+
+    /// Make the changes visible to transactions started from now on. If a concurrent transaction
+    /// committed a conflicting change first, roll back instead and fail.
+    pub fn commit(self, databases: &mut Databases, txns: &TxnManager) -> Result<(), Error> {
+        // The caller holds the write lock on `databases`, so no other transaction commits between
+        // this check and `finish`
+        for change in &self.changes {
+            let conflict = match change {
+                Change::CreateDatabase { name } | Change::RenameDatabase { to: name, .. } => self
+                    .committed_conflict(databases, |db| db.name == *name, txns)
+                    .then(|| format!("database [{}]", name)),
+                Change::CreateTable { db, name } | Change::RenameTable { db, to: name, .. } => self
+                    .find(databases, |d| d.name == *db)
+                    .is_some_and(|db_idx| {
+                        self.committed_conflict(
+                            &databases[db_idx].value.tables,
+                            |tb| tb.name == *name,
+                            txns,
+                        )
+                    })
+                    .then(|| format!("table [{}::{}]", db, name)),
+                // Deleting a version already fails when a concurrent transaction deleted it
+                Change::DropDatabase { .. }
+                | Change::DropTable { .. }
+                | Change::AlterTable { .. } => None,
+            };
+
+            if let Some(conflict) = conflict {
+                // Under READ COMMITTED this is an ordinary name clash, as retrying would see the
+                // other transaction's change
+                let code = match self.isolation_level {
+                    IsolationLevel::ReadCommitted => ErrorCode::CommandFailed,
+                    _ => ErrorCode::SerializationFailure,
+                };
+
+                self.rollback(databases, txns);
+
+                return Err(Error::new(
+                    code,
+                    format!(
+                        "Could not serialize access, {} was created by a concurrent transaction",
+                        conflict
+                    ),
+                ));
+            }
+        }
+
+        if let Some(read) = self
+            .reads
+            .iter()
+            .find(|read| self.changed_since_snapshot(databases, read, txns))
+        {
+            let read = read.clone();
+
+            self.rollback(databases, txns);
+
+            let what = match read {
+                Read::Databases => String::from("the list of databases"),
+                Read::Database { name } => format!("database [{}]", name),
+                Read::Tables { db } => format!("the tables of database [{}]", db),
+            };
+
+            return Err(Error::new(
+                ErrorCode::SerializationFailure,
+                format!(
+                    "Could not serialize access, {} was changed by a concurrent transaction after this one read it",
+                    what
+                ),
+            ));
+        }
+
+        txns.finish(self.id());
+
+        return Ok(());
+    }
+
+    /// Remove every version this transaction created and undo its deletions
+    pub fn rollback(self, databases: &mut Databases, txns: &TxnManager) -> () {
+        let id = self.id();
+
+        databases.retain(|db| db.created_by != id);
+
+        for db in databases.iter_mut() {
+            if db.deleted_by == Some(id) {
+                db.deleted_by = None;
+            }
+
+            db.value.tables.retain(|tb| tb.created_by != id);
+
+            for tb in db.value.tables.iter_mut() {
+                if tb.deleted_by == Some(id) {
+                    tb.deleted_by = None;
+                }
+            }
+        }
+
+        txns.finish(id);
+    }
+
+    /// Whether a transaction which committed after the snapshot was taken changed `read`
+    fn changed_since_snapshot(
+        &self,
+        databases: &Databases,
+        read: &Read,
+        txns: &TxnManager,
+    ) -> bool {
+        let id = self.id();
+        let concurrent =
+            |other: TxnId| other != id && !self.snapshot.sees(other) && !txns.is_active(other);
+        let changed = |created_by: TxnId, deleted_by: Option<TxnId>| {
+            concurrent(created_by) || deleted_by.is_some_and(concurrent)
+        };
+
+        return match read {
+            Read::Databases => databases
+                .iter()
+                .any(|db| changed(db.created_by, db.deleted_by)),
+            Read::Database { name } => databases
+                .iter()
+                .any(|db| db.value.name == *name && changed(db.created_by, db.deleted_by)),
+            Read::Tables { db } => databases
+                .iter()
+                .filter(|d| d.value.name == *db)
+                .flat_map(|d| d.value.tables.iter())
+                .any(|tb| changed(tb.created_by, tb.deleted_by)),
+        };
+    }
+
+    /// Link the foreign keys of `table` to the tables they reference, see
+    /// `Table::link_foreign_keys`, and fail if a foreign key of another table no longer matches
+    /// it. `tables` are the tables of its database, in which `table` stands in for the version
+    /// with its name.
+    fn link_foreign_keys(
+        &self,
+        tables: &[Version<Table>],
+        table: &mut Table,
+        cmd: &str,
+    ) -> Result<(), Error> {
+        let find = |name: &str| {
+            self.find(tables, |tb| tb.name == name)
+                .map(|tb_idx| &tables[tb_idx])
+        };
+
+        // A referenced table deleted by a concurrent transaction may be gone once it commits
+        for (fk_name, fk) in table.foreign_keys() {
+            let ref_table = &fk.ref_table;
+
+            if *ref_table != table.name && find(ref_table).is_some_and(|v| v.deleted_by.is_some()) {
+                return Err(Error::new(
+                    ErrorCode::SerializationFailure,
+                    format!(
+                        "{} failed. Could not serialize access, table [{}] referenced by constraint [{}] was dropped or altered by a concurrent transaction",
+                        cmd, ref_table, fk_name
+                    ),
+                ));
+            }
+        }
+
+        table
+            .link_foreign_keys(|name| find(name).map(|v| &v.value))
+            .map_err(|e| Error::from(format!("{} failed. {}", cmd, e)))?;
+
+        for other in visible(tables, &self.snapshot).filter(|tb| tb.name != table.name) {
+            for (fk_name, fk) in other.foreign_keys() {
+                if fk.ref_table != table.name {
+                    continue;
+                }
+
+                other.check_reference(fk, table).map_err(|e| {
+                    Error::from(format!(
+                        "{} failed. Constraint [{}] on table [{}] references table [{}]. {}",
+                        cmd, fk_name, other.name, table.name, e
+                    ))
+                })?;
+            }
+        }
+
+        return Ok(());
+    }
+
+    /// Replace the tables of database `db` which reference table `name` with a copy changed by
+    /// `alter`. Tables referencing themselves are left to the caller.
+    fn alter_referencing(
+        &mut self,
+        tables: &mut Vec<Version<Table>>,
+        db: &str,
+        name: &str,
+        alter: impl Fn(&mut Table) -> (),
+    ) -> Result<(), Error> {
+        let referencing: Vec<usize> = (0..tables.len())
+            .filter(|tb_idx| {
+                let tb = &tables[*tb_idx];
+
+                tb.visible_to(&self.snapshot)
+                    && tb.value.name != name
+                    && !tb.value.references(name).is_empty()
+            })
+            .collect();
+
+        for tb_idx in referencing {
+            let other = tables[tb_idx].value.name.clone();
+
+            self.delete(&mut tables[tb_idx], &format!("table [{}::{}]", db, other))?;
+
+            let mut altered = tables[tb_idx].value.clone();
+            alter(&mut altered);
+
+            tables.push(Version::new(altered, self.id()));
+            self.changes.push(Change::AlterTable {
+                db: String::from(db),
+                name: other,
+            });
+        }
+
+        return Ok(());
+    }
+
+    /// Index of the database called `name` visible to this transaction
+    fn find_database(&self, databases: &Databases, name: &str, cmd: &str) -> Result<usize, Error> {
+        return self.find(databases, |db| db.name == name).ok_or_else(|| {
+            Error::from(format!("{} failed. No database with name [{}]", cmd, name))
+        });
+    }
+
+    /// Mark a version visible to this transaction as deleted by it. If the version is already
+    /// deleted, by a transaction this one can't see, it was dropped or renamed concurrently.
+    fn delete<T>(&self, version: &mut Version<T>, what: &str) -> Result<(), Error> {
+        if version.deleted_by.is_some() {
+            return Err(Error::new(
+                ErrorCode::SerializationFailure,
+                format!(
+                    "Could not serialize access, {} was dropped or renamed by a concurrent transaction",
+                    what
+                ),
+            ));
+        }
+
+        version.deleted_by = Some(self.id());
+
+        return Ok(());
+    }
+
+    /// Index of the version matching `pred` visible to this transaction
+    fn find<T>(&self, versions: &[Version<T>], pred: impl Fn(&T) -> bool) -> Option<usize> {
+        return versions
+            .iter()
+            .position(|v| v.visible_to(&self.snapshot) && pred(&v.value));
+    }
+
+    fn check_create<T>(
+        &self,
+        versions: &[Version<T>],
+        pred: impl Fn(&T) -> bool,
+        txns: &TxnManager,
+    ) -> Result<(), Conflict> {
+        if self.find(versions, &pred).is_some() {
+            return Err(Conflict::Exists);
+        }
+
+        if self.committed_conflict(versions, &pred, txns) {
+            return Err(Conflict::Concurrent);
+        }
+
+        return Ok(());
+    }
+
+    /// Whether another transaction committed a live version matching `pred`
+    fn committed_conflict<T>(
+        &self,
+        versions: &[Version<T>],
+        pred: impl Fn(&T) -> bool,
+        txns: &TxnManager,
+    ) -> bool {
+        let id = self.id();
+        let committed = |other: TxnId| other != id && !txns.is_active(other);
+
+        return versions.iter().any(|v| {
+            pred(&v.value) && committed(v.created_by) && !v.deleted_by.is_some_and(committed)
+        });
+    }
+}
+
+/// Undo the latest deletion by `id` of a version matching `pred`
+fn undelete<T>(versions: &mut [Version<T>], id: TxnId, pred: impl Fn(&T) -> bool) -> () {
+    if let Some(v) = versions
+        .iter_mut()
+        .rev()
+        .find(|v| v.deleted_by == Some(id) && pred(&v.value))
+    {
+        v.deleted_by = None;
+    }
+}
+
+/// Remove the latest version created by `id` matching `pred`
+fn uncreate<T>(versions: &mut Vec<Version<T>>, id: TxnId, pred: impl Fn(&T) -> bool) -> () {
+    if let Some(idx) = versions
+        .iter()
+        .rposition(|v| v.created_by == id && pred(&v.value))
+    {
+        versions.remove(idx);
+    }
+}
+
+/// Visible versions of `versions`, for reads outside of a transaction or through `snapshot`
+pub fn visible<'a, T>(
+    versions: &'a [Version<T>],
+    snapshot: &'a Snapshot,
+) -> impl Iterator<Item = &'a T> {
+    return versions
+        .iter()
+        .filter(|v| v.visible_to(snapshot))
+        .map(|v| &v.value);
+}
+
+/// Remove versions deleted by transactions older than `horizon`, see `TxnManager::horizon`.
+/// Returns the number of versions removed.
+pub fn collect_garbage(databases: &mut Databases, horizon: TxnId) -> usize {
+    let dead = |deleted_by: Option<TxnId>| deleted_by.is_some_and(|id| id < horizon);
+
+    let before = databases.len();
+    databases.retain(|db| !dead(db.deleted_by));
+    let mut removed = before - databases.len();
+
+    for db in databases.iter_mut() {
+        let before = db.value.tables.len();
+        db.value.tables.retain(|tb| !dead(tb.deleted_by));
+        removed += before - db.value.tables.len();
+    }
+
+    return removed;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::column::{Column, ColumnType};
+
+    fn database_names(databases: &Databases, snapshot: &Snapshot) -> Vec<String> {
+        return visible(databases, snapshot)
+            .map(|db| db.name.clone())
+            .collect();
+    }
+
+    /// Commit a transaction creating database `name`
+    fn create_database(databases: &mut Databases, txns: &TxnManager, name: &str) -> () {
+        let mut txn = Transaction::begin(txns);
+        txn.create_database(databases, txns, String::from(name))
+            .unwrap();
+        txn.commit(databases, txns).unwrap();
+    }
+
+    #[test]
+    fn changes_are_invisible_until_commit() {
+        let txns = TxnManager::new();
+        let mut databases = Databases::new();
+
+        let mut txn = Transaction::begin(&txns);
+        txn.create_database(&mut databases, &txns, String::from("a"))
+            .unwrap();
+
+        assert_eq!(database_names(&databases, txn.snapshot()), ["a"]);
+        assert!(database_names(&databases, &txns.snapshot()).is_empty());
+
+        txn.commit(&mut databases, &txns).unwrap();
+        assert_eq!(database_names(&databases, &txns.snapshot()), ["a"]);
+    }
+
+    #[test]
+    fn rollback_removes_changes() {
+        let txns = TxnManager::new();
+        let mut databases = Databases::new();
+        create_database(&mut databases, &txns, "a");
+
+        let mut txn = Transaction::begin(&txns);
+        txn.create_database(&mut databases, &txns, String::from("b"))
+            .unwrap();
+        txn.drop_database(&mut databases, "a", false, false)
+            .unwrap();
+        txn.rollback(&mut databases, &txns);
+
+        assert_eq!(database_names(&databases, &txns.snapshot()), ["a"]);
+        assert_eq!(databases.len(), 1);
+    }
+
+    #[test]
+    fn repeatable_read_keeps_its_snapshot() {
+        let txns = TxnManager::new();
+        let mut databases = Databases::new();
+
+        let mut reader = Transaction::begin(&txns);
+        reader.start_command(&txns);
+
+        create_database(&mut databases, &txns, "a");
+
+        reader.start_command(&txns);
+        assert!(database_names(&databases, reader.snapshot()).is_empty());
+    }
+
+    #[test]
+    fn concurrent_create_of_same_name_fails_to_commit() {
+        let txns = TxnManager::new();
+        let mut databases = Databases::new();
+
+        let mut first = Transaction::begin(&txns);
+        let mut second = Transaction::begin(&txns);
+
+        first
+            .create_database(&mut databases, &txns, String::from("a"))
+            .unwrap();
+        second
+            .create_database(&mut databases, &txns, String::from("a"))
+            .unwrap();
+
+        first.commit(&mut databases, &txns).unwrap();
+
+        let e = second.commit(&mut databases, &txns).unwrap_err();
+        assert_eq!(e.code, ErrorCode::SerializationFailure);
+        assert_eq!(database_names(&databases, &txns.snapshot()), ["a"]);
+        assert_eq!(databases.len(), 1);
+    }
+
+    #[test]
+    fn create_of_name_committed_since_snapshot_fails() {
+        let txns = TxnManager::new();
+        let mut databases = Databases::new();
+
+        let mut txn = Transaction::begin(&txns);
+        create_database(&mut databases, &txns, "a");
+
+        let e = txn
+            .create_database(&mut databases, &txns, String::from("a"))
+            .unwrap_err();
+        assert_eq!(e.code, ErrorCode::SerializationFailure);
+    }
+
+    #[test]
+    fn concurrent_drops_conflict() {
+        let txns = TxnManager::new();
+        let mut databases = Databases::new();
+        create_database(&mut databases, &txns, "a");
+
+        let mut first = Transaction::begin(&txns);
+        let mut second = Transaction::begin(&txns);
+
+        first
+            .drop_database(&mut databases, "a", false, false)
+            .unwrap();
+
+        let e = second
+            .drop_database(&mut databases, "a", false, false)
+            .unwrap_err();
+        assert_eq!(e.code, ErrorCode::SerializationFailure);
+    }
+
+    #[test]
+    fn garbage_is_kept_while_a_snapshot_sees_it() {
+        let txns = TxnManager::new();
+        let mut databases = Databases::new();
+        create_database(&mut databases, &txns, "a");
+
+        let reader = Transaction::begin(&txns);
+
+        let mut dropper = Transaction::begin(&txns);
+        dropper
+            .drop_database(&mut databases, "a", false, false)
+            .unwrap();
+        dropper.commit(&mut databases, &txns).unwrap();
+
+        assert_eq!(collect_garbage(&mut databases, txns.horizon()), 0);
+        assert_eq!(database_names(&databases, reader.snapshot()), ["a"]);
+
+        reader.commit(&mut databases, &txns).unwrap();
+        assert_eq!(collect_garbage(&mut databases, txns.horizon()), 1);
+    }
+
+    #[test]
+    fn read_committed_sees_commits_from_the_next_command() {
+        let txns = TxnManager::new();
+        let mut databases = Databases::new();
+
+        let mut reader = Transaction::begin(&txns);
+        reader
+            .set_isolation_level(IsolationLevel::ReadCommitted, &txns)
+            .unwrap();
+        reader.start_command(&txns);
+
+        create_database(&mut databases, &txns, "a");
+        assert!(database_names(&databases, reader.snapshot()).is_empty());
+
+        reader.start_command(&txns);
+        assert_eq!(database_names(&databases, reader.snapshot()), ["a"]);
+    }
+
+    #[test]
+    fn isolation_level_is_fixed_once_started() {
+        let txns = TxnManager::new();
+
+        let mut txn = Transaction::begin(&txns);
+        txn.start_command(&txns);
+
+        let e = txn
+            .set_isolation_level(IsolationLevel::Serializable, &txns)
+            .unwrap_err();
+        assert_eq!(e.code, ErrorCode::InvalidTransactionState);
+    }
+
+    #[test]
+    fn read_committed_name_clash_is_not_a_serialization_failure() {
+        let txns = TxnManager::new();
+        let mut databases = Databases::new();
+
+        let mut txn = Transaction::begin(&txns);
+        txn.set_isolation_level(IsolationLevel::ReadCommitted, &txns)
+            .unwrap();
+        txn.create_database(&mut databases, &txns, String::from("a"))
+            .unwrap();
+
+        create_database(&mut databases, &txns, "a");
+
+        let e = txn.commit(&mut databases, &txns).unwrap_err();
+        assert_eq!(e.code, ErrorCode::CommandFailed);
+    }
+
+    /// Each transaction checks that a database doesn't exist and creates another one. Run one at a
+    /// time, the second would see the first's database.
+    fn write_skew(isolation_level: IsolationLevel) -> Result<(), Error> {
+        let txns = TxnManager::new();
+        let mut databases = Databases::new();
+
+        let mut first = Transaction::begin(&txns);
+        let mut second = Transaction::begin(&txns);
+
+        for txn in [&mut first, &mut second] {
+            txn.set_isolation_level(isolation_level, &txns).unwrap();
+        }
+
+        first.record_read(Read::Database {
+            name: String::from("b"),
+        });
+        first
+            .create_database(&mut databases, &txns, String::from("a"))
+            .unwrap();
+
+        second.record_read(Read::Database {
+            name: String::from("a"),
+        });
+        second
+            .create_database(&mut databases, &txns, String::from("b"))
+            .unwrap();
+
+        first.commit(&mut databases, &txns).unwrap();
+
+        return second.commit(&mut databases, &txns);
+    }
+
+    #[test]
+    fn serializable_fails_on_write_skew() {
+        let e = write_skew(IsolationLevel::Serializable).unwrap_err();
+        assert_eq!(e.code, ErrorCode::SerializationFailure);
+    }
+
+    #[test]
+    fn repeatable_read_allows_write_skew() {
+        assert_eq!(write_skew(IsolationLevel::RepeatableRead), Ok(()));
+    }
+
+    fn table_names(databases: &Databases, snapshot: &Snapshot, db: &str) -> Vec<String> {
+        return visible(databases, snapshot)
+            .filter(|d| d.name == db)
+            .flat_map(|d| visible(&d.tables, snapshot))
+            .map(|tb| tb.name.clone())
+            .collect();
+    }
+
+    fn column(name: &str) -> Column {
+        return Column {
+            name: String::from(name),
+            column_type: ColumnType::SINT32,
+            is_optional: true,
+            is_primary_key: false,
+            is_foreign_key: false,
+            default: None,
+        };
+    }
+
+    #[test]
+    fn rollback_to_savepoint_undoes_later_changes_only() {
+        let txns = TxnManager::new();
+        let mut databases = Databases::new();
+        create_database(&mut databases, &txns, "a");
+
+        let mut txn = Transaction::begin(&txns);
+        txn.create_database(&mut databases, &txns, String::from("b"))
+            .unwrap();
+        txn.savepoint(String::from("sp"));
+        txn.drop_database(&mut databases, "a", false, false)
+            .unwrap();
+        txn.rename_database(&mut databases, &txns, "b", "c")
+            .unwrap();
+
+        txn.rollback_to_savepoint("sp", &mut databases).unwrap();
+        assert_eq!(database_names(&databases, txn.snapshot()), ["a", "b"]);
+
+        // The savepoint stays, so it can be rolled back to again
+        txn.drop_database(&mut databases, "b", false, false)
+            .unwrap();
+        txn.rollback_to_savepoint("sp", &mut databases).unwrap();
+
+        txn.commit(&mut databases, &txns).unwrap();
+        assert_eq!(database_names(&databases, &txns.snapshot()), ["a", "b"]);
+    }
+
+    #[test]
+    fn rollback_to_savepoint_restores_altered_table() {
+        let txns = TxnManager::new();
+        let mut databases = Databases::new();
+        create_database(&mut databases, &txns, "db");
+
+        let mut txn = Transaction::begin(&txns);
+        txn.create_table(
+            &mut databases,
+            &txns,
+            "db",
+            Table::new(String::from("t"), vec![column("x")], vec![]).unwrap(),
+        )
+        .unwrap();
+        txn.savepoint(String::from("sp"));
+        txn.alter_table(&mut databases, "db", "t", "ALTER TABLE", |tb| {
+            tb.add_column(column("y"), vec![])
+        })
+        .unwrap();
+        txn.rename_table(&mut databases, &txns, "db", "t", "u")
+            .unwrap();
+
+        txn.rollback_to_savepoint("sp", &mut databases).unwrap();
+
+        assert_eq!(table_names(&databases, txn.snapshot(), "db"), ["t"]);
+        let columns: Vec<String> = visible(&databases[0].value.tables, txn.snapshot())
+            .flat_map(|tb| tb.columns.iter().map(|col| col.name.clone()))
+            .collect();
+        assert_eq!(columns, ["x"]);
+    }
+
+    #[test]
+    fn savepoints_nest_and_shadow() {
+        let txns = TxnManager::new();
+        let mut databases = Databases::new();
+
+        let mut txn = Transaction::begin(&txns);
+        txn.savepoint(String::from("sp"));
+        txn.create_database(&mut databases, &txns, String::from("a"))
+            .unwrap();
+        txn.savepoint(String::from("sp"));
+        txn.create_database(&mut databases, &txns, String::from("b"))
+            .unwrap();
+        txn.savepoint(String::from("inner"));
+
+        // Rolls back to the latest savepoint of that name, forgetting the ones set after it
+        txn.rollback_to_savepoint("sp", &mut databases).unwrap();
+        assert_eq!(database_names(&databases, txn.snapshot()), ["a"]);
+        assert!(txn.release_savepoint("inner").is_err());
+
+        // Releasing uncovers the earlier savepoint of the same name
+        txn.release_savepoint("sp").unwrap();
+        txn.rollback_to_savepoint("sp", &mut databases).unwrap();
+        assert!(database_names(&databases, txn.snapshot()).is_empty());
+
+        txn.release_savepoint("sp").unwrap();
+        let e = txn.rollback_to_savepoint("sp", &mut databases).unwrap_err();
+        assert_eq!(e.code, ErrorCode::InvalidTransactionState);
+    }
+}
@@ -1,8 +1,7 @@
 use std::io::{self, Read};
 use std::mem;
-use std::net::TcpStream;
 
-pub fn read_msg(reader: &mut TcpStream) -> Result<Vec<u8>, io::Error> {
+pub fn read_msg<R: Read>(reader: &mut R) -> Result<Vec<u8>, io::Error> {
     let mut buf: [u8; 4] = [0; 4];
 
     reader.read_exact(&mut buf)?;
@@ -46,6 +45,26 @@ pub fn serialise_string(string: &String, bytes: &mut Vec<u8>) {
     bytes.extend_from_slice(string.as_bytes());
 }
 
+pub fn parse_bytes(bytes: &[u8]) -> Result<(&[u8], Vec<u8>), String> {
+    let (bytes, len) = parse_u32(bytes)?;
+    let len = len as usize;
+
+    if bytes.len() < len {
+        return Err(format!(
+            "Data too short to hold {} bytes. Got data length {}",
+            len,
+            bytes.len()
+        ));
+    }
+
+    return Ok((&bytes[len..], Vec::from(&bytes[..len])));
+}
+
+pub fn serialise_bytes(data: &[u8], bytes: &mut Vec<u8>) {
+    serialise_u32(data.len() as u32, bytes);
+    bytes.extend_from_slice(data);
+}
+
 pub fn parse_bool(bytes: &[u8]) -> Result<(&[u8], bool), String> {
     if bytes.len() < mem::size_of::<u8>() {
         return Err(format!(
@@ -85,3 +104,24 @@ pub fn parse_u32(bytes: &[u8]) -> Result<(&[u8], u32), String> {
 pub fn serialise_u32(u32: u32, bytes: &mut Vec<u8>) {
     bytes.extend_from_slice(&u32.to_le_bytes());
 }
+
+pub fn parse_u16(bytes: &[u8]) -> Result<(&[u8], u16), String> {
+    if bytes.len() < mem::size_of::<u16>() {
+        return Err(format!(
+            "Data too short to hold u16. Got data length {}",
+            bytes.len()
+        ));
+    }
+
+    let u16_bytes = bytes[0..mem::size_of::<u16>()].try_into().unwrap();
+
+    let u16 = u16::from_le_bytes(u16_bytes);
+
+    let bytes = &bytes[mem::size_of::<u16>()..];
+
+    return Ok((bytes, u16));
+}
+
+pub fn serialise_u16(u16: u16, bytes: &mut Vec<u8>) {
+    bytes.extend_from_slice(&u16.to_le_bytes());
+}
@@ -0,0 +1,569 @@
+// Explicit `return`s and `-> ()` are house style
+#![allow(clippy::needless_return, clippy::unused_unit)]
+
+use std::io::{Read, Write};
+use std::net::TcpStream;
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use squeef::acl::{GrantOn, Privilege};
+use squeef::audit::{self, AuditRecord};
+use squeef::auth::{self, Credentials};
+use squeef::column::{Column, ColumnType};
+use squeef::command::Command;
+use squeef::constraint::{Check, CompareOp};
+use squeef::policy::{Policy, PolicyCommand};
+use squeef::protocol::v0;
+use squeef::server::{Server, ServerBuilder, ServerHandle};
+use squeef::tls;
+use squeef::utils;
+
+use rustls::{ClientConnection, StreamOwned};
+
+const ADMIN_PASSWORD: &str = "secret";
+
+/// Server on a free port, with the superuser `admin`
+fn builder(allow_anonymous: bool) -> ServerBuilder {
+    return Server::builder()
+        .port(0)
+        .allow_anonymous(allow_anonymous)
+        .user(
+            String::from("admin"),
+            Credentials::new(ADMIN_PASSWORD),
+            true,
+        )
+        .unwrap();
+}
+
+fn spawn(allow_anonymous: bool) -> ServerHandle {
+    return builder(allow_anonymous).spawn().unwrap();
+}
+
+/// Directory for the files of one test, removed when dropped
+struct TempDir {
+    path: PathBuf,
+}
+
+impl TempDir {
+    fn new(name: &str) -> TempDir {
+        static NEXT: AtomicUsize = AtomicUsize::new(0);
+
+        let path = std::env::temp_dir().join(format!(
+            "squeef-{}-{}-{}",
+            name,
+            std::process::id(),
+            NEXT.fetch_add(1, Ordering::SeqCst)
+        ));
+        std::fs::create_dir_all(&path).unwrap();
+
+        return TempDir { path };
+    }
+
+    fn join(&self, path: &str) -> PathBuf {
+        return self.path.join(path);
+    }
+}
+
+impl Drop for TempDir {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_dir_all(&self.path);
+    }
+}
+
+trait ReadWrite: Read + Write {}
+
+impl<S: Read + Write> ReadWrite for S {}
+
+struct Client {
+    stream: Box<dyn ReadWrite>,
+}
+
+impl Client {
+    fn connect(server: &ServerHandle) -> Client {
+        let stream = TcpStream::connect(server.local_addr().unwrap()).unwrap();
+        return Client {
+            stream: Box::new(stream),
+        };
+    }
+
+    /// Connect with TLS, trusting the test CA
+    fn connect_tls(server: &ServerHandle) -> Client {
+        let config = tls::client_config(Some(&fixture("ca.pem")), None).unwrap();
+        let conn = ClientConnection::new(config, tls::server_name("localhost").unwrap()).unwrap();
+        let stream = TcpStream::connect(server.local_addr().unwrap()).unwrap();
+
+        return Client {
+            stream: Box::new(StreamOwned::new(conn, stream)),
+        };
+    }
+
+    /// Send `cmd` and parse the response
+    fn request(&mut self, cmd: Command) -> Result<String, String> {
+        return v0::response::parse(&self.request_raw(cmd));
+    }
+
+    fn request_raw(&mut self, cmd: Command) -> Vec<u8> {
+        return self.try_request_raw(cmd).unwrap();
+    }
+
+    fn try_request_raw(&mut self, cmd: Command) -> std::io::Result<Vec<u8>> {
+        let data = v0::request::serialise(cmd);
+        self.stream.write_all(&(data.len() as u32).to_le_bytes())?;
+        self.stream.write_all(&data)?;
+
+        return utils::read_msg(&mut self.stream);
+    }
+
+    fn login(&mut self, user: &str, password: &str) -> Result<String, String> {
+        let data = self.request_raw(Command::Authenticate {
+            user: String::from(user),
+        });
+        let challenge = v0::response::parse_challenge(&data)?;
+
+        let proof = auth::client_proof(password, &challenge);
+        return self.request(Command::AuthProof { proof });
+    }
+}
+
+/// File of the test certificates, see tests/tls/generate.sh
+fn fixture(name: &str) -> PathBuf {
+    return PathBuf::from(env!("CARGO_MANIFEST_DIR"))
+        .join("tests/tls")
+        .join(name);
+}
+
+fn spawn_tls() -> ServerHandle {
+    return builder(false)
+        .tls(fixture("server.pem"), fixture("server.key"), None)
+        .spawn()
+        .unwrap();
+}
+
+fn create_database(name: &str) -> Command {
+    return Command::CreateDatabase {
+        name: String::from(name),
+    };
+}
+
+#[test]
+fn commands_need_login_without_anonymous_access() {
+    let server = spawn(false);
+    let mut client = Client::connect(&server);
+
+    let e = client.request(Command::ListDatabases).unwrap_err();
+    assert!(e.contains("Authentication required"), "{}", e);
+
+    assert!(client.login("admin", "wrong").is_err());
+
+    client.login("admin", ADMIN_PASSWORD).unwrap();
+    client.request(create_database("shop")).unwrap();
+    assert_eq!(
+        client.request(Command::ListDatabases).unwrap(),
+        "Databases: [shop]"
+    );
+
+    server.stop();
+}
+
+#[test]
+fn anonymous_clients_are_not_superusers() {
+    let server = spawn(true);
+    let mut client = Client::connect(&server);
+
+    let e = client.request(create_database("shop")).unwrap_err();
+    assert!(e.contains("permission_denied"), "{}", e);
+
+    server.stop();
+}
+
+#[test]
+fn rolled_back_changes_are_not_seen_by_other_clients() {
+    let server = spawn(false);
+
+    let mut writer = Client::connect(&server);
+    writer.login("admin", ADMIN_PASSWORD).unwrap();
+    let mut reader = Client::connect(&server);
+    reader.login("admin", ADMIN_PASSWORD).unwrap();
+
+    writer.request(create_database("kept")).unwrap();
+
+    writer.request(Command::Begin).unwrap();
+    writer.request(create_database("dropped")).unwrap();
+    assert_eq!(
+        reader.request(Command::ListDatabases).unwrap(),
+        "Databases: [kept]"
+    );
+    writer.request(Command::Rollback).unwrap();
+
+    assert_eq!(
+        reader.request(Command::ListDatabases).unwrap(),
+        "Databases: [kept]"
+    );
+
+    server.stop();
+}
+
+#[test]
+fn stopped_server_refuses_connections() {
+    let server = spawn(true);
+    let addr = server.local_addr().unwrap();
+
+    let mut client = Client::connect(&server);
+    client
+        .request(Command::SetClientName {
+            name: String::from("test"),
+        })
+        .unwrap();
+    drop(client);
+
+    server.stop();
+
+    assert!(TcpStream::connect(addr).is_err());
+}
+
+#[test]
+fn users_and_roles_cant_change_inside_a_transaction() {
+    let server = spawn(false);
+    let mut client = Client::connect(&server);
+    client.login("admin", ADMIN_PASSWORD).unwrap();
+
+    client.request(Command::Begin).unwrap();
+    let e = client
+        .request(Command::CreateRole {
+            name: String::from("analysts"),
+        })
+        .unwrap_err();
+    assert!(e.contains("can't be changed inside a transaction"), "{}", e);
+
+    let e = client
+        .request(Command::DropUser {
+            name: String::from("admin"),
+        })
+        .unwrap_err();
+    assert!(e.contains("can't be changed inside a transaction"), "{}", e);
+    client.request(Command::Rollback).unwrap();
+
+    client
+        .request(Command::CreateRole {
+            name: String::from("analysts"),
+        })
+        .unwrap();
+
+    server.stop();
+}
+
+#[test]
+fn only_table_owners_manage_policies() {
+    let server = spawn(false);
+    let mut admin = Client::connect(&server);
+    admin.login("admin", ADMIN_PASSWORD).unwrap();
+
+    admin.request(create_database("shop")).unwrap();
+    admin
+        .request(Command::CreateRole {
+            name: String::from("staff"),
+        })
+        .unwrap();
+    admin
+        .request(Command::Grant {
+            privileges: vec![Privilege::Create],
+            on: GrantOn::Database {
+                name: String::from("shop"),
+            },
+            role: String::from("staff"),
+        })
+        .unwrap();
+
+    for user in ["alice", "bob"] {
+        admin
+            .request(Command::CreateUser {
+                name: String::from(user),
+                credentials: Credentials::new(user),
+            })
+            .unwrap();
+        admin
+            .request(Command::GrantRole {
+                role: String::from("staff"),
+                user: String::from(user),
+            })
+            .unwrap();
+    }
+
+    let mut alice = Client::connect(&server);
+    alice.login("alice", "alice").unwrap();
+    alice
+        .request(Command::OpenDatabase {
+            name: String::from("shop"),
+        })
+        .unwrap();
+    alice
+        .request(Command::CreateTable {
+            name: String::from("orders"),
+            cols: vec![Column {
+                name: String::from("tenant_id"),
+                column_type: ColumnType::UINT32,
+                is_optional: false,
+                is_primary_key: false,
+                is_foreign_key: false,
+                default: None,
+            }],
+            constraints: vec![],
+        })
+        .unwrap();
+
+    let create_policy = || Command::CreatePolicy {
+        table: String::from("orders"),
+        policy: Policy {
+            name: String::from("own_tenant"),
+            command: PolicyCommand::Select,
+            role: String::from("staff"),
+            predicate: Check {
+                column: String::from("tenant_id"),
+                op: CompareOp::Eq,
+                value: String::from("7"),
+            },
+        },
+    };
+
+    let mut bob = Client::connect(&server);
+    bob.login("bob", "bob").unwrap();
+    bob.request(Command::OpenDatabase {
+        name: String::from("shop"),
+    })
+    .unwrap();
+    let e = bob.request(create_policy()).unwrap_err();
+    assert!(
+        e.contains("Requires ownership of table [shop::orders]"),
+        "{}",
+        e
+    );
+
+    assert_eq!(alice.request(create_policy()).unwrap(), "Policy created");
+
+    let described = bob
+        .request(Command::DescribeTable {
+            name: String::from("orders"),
+        })
+        .unwrap();
+    assert!(
+        described.contains("own_tenant FOR SELECT TO staff USING (tenant_id = 7) NOT ENFORCED"),
+        "{}",
+        described
+    );
+
+    let e = admin
+        .request(Command::DropRole {
+            name: String::from("staff"),
+        })
+        .unwrap_err();
+    assert!(e.contains("Role [staff] is used by policies"), "{}", e);
+
+    let e = admin
+        .request(Command::DropUser {
+            name: String::from("alice"),
+        })
+        .unwrap_err();
+    assert!(e.contains("User [alice] owns tables"), "{}", e);
+
+    let drop_policy = || Command::DropPolicy {
+        table: String::from("orders"),
+        name: String::from("own_tenant"),
+    };
+    assert!(bob.request(drop_policy()).is_err());
+    assert_eq!(alice.request(drop_policy()).unwrap(), "Policy dropped");
+
+    admin
+        .request(Command::DropRole {
+            name: String::from("staff"),
+        })
+        .unwrap();
+
+    server.stop();
+}
+
+#[test]
+fn audit_log_records_how_transactions_end() {
+    let dir = TempDir::new("audit");
+    std::fs::create_dir_all(dir.join("log")).unwrap();
+    std::fs::create_dir_all(dir.join("key")).unwrap();
+    std::fs::write(dir.join("key/audit.key"), [7u8; audit::MIN_KEY_LEN]).unwrap();
+
+    let server = builder(false)
+        .audit_log(dir.join("log/audit.log"), dir.join("key/audit.key"))
+        .spawn()
+        .unwrap();
+    let mut client = Client::connect(&server);
+    client.login("admin", ADMIN_PASSWORD).unwrap();
+
+    client.request(Command::Begin).unwrap();
+    client.request(create_database("dropped")).unwrap();
+    client.request(Command::Rollback).unwrap();
+
+    client.request(Command::Begin).unwrap();
+    client.request(create_database("kept")).unwrap();
+    client.request(Command::Commit).unwrap();
+
+    // Transactions without audited commands aren't recorded
+    client.request(Command::Begin).unwrap();
+    client.request(Command::ListDatabases).unwrap();
+    client.request(Command::Commit).unwrap();
+
+    server.stop();
+
+    let log = std::fs::read_to_string(dir.join("log/audit.log")).unwrap();
+    let records: Vec<(String, String)> = log
+        .lines()
+        .map(|line| serde_json::from_str::<AuditRecord>(line).unwrap())
+        .map(|record| (record.command, record.outcome))
+        .collect();
+    let expected = [
+        "AUTHENTICATE",
+        "CREATE DATABASE dropped",
+        "ROLLBACK",
+        "CREATE DATABASE kept",
+        "COMMIT",
+    ]
+    .map(|command| (String::from(command), String::from("ok")));
+    assert_eq!(records, expected);
+
+    let key = audit::load_key(&dir.join("key/audit.key")).unwrap();
+    assert_eq!(
+        audit::verify(&dir.join("log/audit.log"), &key)
+            .unwrap()
+            .records,
+        5
+    );
+}
+
+/// GET `path` from the metrics endpoint, returning the status line and body
+fn scrape(server: &ServerHandle, path: &str) -> (String, String) {
+    let mut stream = TcpStream::connect(server.metrics_addr().unwrap()).unwrap();
+    write!(stream, "GET {} HTTP/1.1\r\nHost: localhost\r\n\r\n", path).unwrap();
+
+    let mut response = String::new();
+    stream.read_to_string(&mut response).unwrap();
+
+    let (head, body) = response.split_once("\r\n\r\n").unwrap();
+    let status = head.lines().next().unwrap();
+    return (String::from(status), String::from(body));
+}
+
+#[test]
+fn metrics_endpoint_serves_server_metrics() {
+    let server = builder(false)
+        .metrics_bind(String::from("127.0.0.1:0"))
+        .spawn()
+        .unwrap();
+
+    let mut client = Client::connect(&server);
+    client.login("admin", ADMIN_PASSWORD).unwrap();
+    client.request(create_database("shop")).unwrap();
+    client.request(create_database("shop")).unwrap_err();
+    // Commands are counted after their response is sent, so this one makes sure the ones before
+    // it are
+    client.request(Command::ListDatabases).unwrap();
+
+    let (status, body) = scrape(&server, "/metrics");
+    assert_eq!(status, "HTTP/1.1 200 OK");
+
+    let samples: Vec<&str> = body.lines().filter(|l| !l.starts_with('#')).collect();
+    for sample in [
+        "squeef_connections_active 1",
+        "squeef_connections_total 1",
+        "squeef_commands_total{command=\"create_database\"} 2",
+        "squeef_command_duration_seconds_count{command=\"create_database\"} 2",
+        "squeef_errors_total{code=\"command_failed\"} 1",
+    ] {
+        assert!(
+            samples.contains(&sample),
+            "{} missing from:\n{}",
+            sample,
+            body
+        );
+    }
+    assert!(body.contains("# TYPE squeef_command_duration_seconds histogram"));
+
+    let (status, _) = scrape(&server, "/other");
+    assert_eq!(status, "HTTP/1.1 404 Not Found");
+
+    server.stop();
+}
+
+#[test]
+fn tls_clients_can_log_in() {
+    let server = spawn_tls();
+    let mut client = Client::connect_tls(&server);
+
+    client.login("admin", ADMIN_PASSWORD).unwrap();
+    client.request(create_database("shop")).unwrap();
+    assert_eq!(
+        client.request(Command::ListDatabases).unwrap(),
+        "Databases: [shop]"
+    );
+
+    server.stop();
+}
+
+#[test]
+fn tls_server_refuses_plaintext_clients() {
+    let server = spawn_tls();
+    let mut client = Client::connect(&server);
+
+    // The server answers with a TLS alert, if anything, and hangs up
+    assert!(client.try_request_raw(Command::ListDatabases).is_err());
+
+    // Other clients are still served
+    let mut client = Client::connect_tls(&server);
+    client.login("admin", ADMIN_PASSWORD).unwrap();
+
+    server.stop();
+}
+
+#[test]
+fn bad_tls_files_are_reported_at_startup() {
+    let dir = TempDir::new("tls");
+    std::fs::write(dir.join("garbage.pem"), "not a certificate\n").unwrap();
+
+    let cert = fixture("server.pem");
+    let key = fixture("server.key");
+    let missing = dir.join("missing.pem");
+    let garbage = dir.join("garbage.pem");
+
+    let e = tls::server_config(&missing, &key, None).unwrap_err();
+    assert_eq!(e.kind(), std::io::ErrorKind::NotFound);
+    assert!(
+        e.to_string()
+            .starts_with(&format!("Failed to open [{}]", missing.display())),
+        "{}",
+        e
+    );
+
+    let e = tls::server_config(&garbage, &key, None).unwrap_err();
+    assert_eq!(
+        e.to_string(),
+        format!(
+            "Invalid TLS file [{}]: no certificate found",
+            garbage.display()
+        )
+    );
+
+    let e = tls::server_config(&cert, &garbage, None).unwrap_err();
+    assert_eq!(
+        e.to_string(),
+        format!(
+            "Invalid TLS file [{}]: no private key found",
+            garbage.display()
+        )
+    );
+
+    // A key which doesn't belong to the certificate
+    let e = tls::server_config(&fixture("ca.pem"), &key, None).unwrap_err();
+    assert!(e.to_string().starts_with("Invalid TLS file"), "{}", e);
+
+    // The server doesn't start
+    let e = builder(false)
+        .tls(cert, garbage.clone(), None)
+        .spawn()
+        .unwrap_err();
+    assert!(e.to_string().contains("no private key found"), "{}", e);
+}